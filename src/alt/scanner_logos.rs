@@ -1,16 +1,16 @@
-use logos::Logos;
+//! Scanner generated by logos, producing the same tokens, lines and errors as
+//! the hand-written one in `scanner.rs`.
 
-use crate::Result;
+use logos::{Lexer, Logos};
 
-#[derive(Debug, PartialEq, Eq)]
-pub struct Token<'source_code> {
-    pub type_: TokenType,
-    pub lexeme: &'source_code str,
-    pub line: u32,
-}
+use crate::{
+    error::{compilation_error, CompilationError},
+    scanner::{self, Scanner, Token, TokenType},
+    Result,
+};
 
-#[derive(Logos, Debug, PartialEq, Eq)]
-pub enum TokenType {
+#[derive(Logos, Debug, PartialEq, Eq, Clone, Copy)]
+enum LogosToken {
     // Single-character tokens
     #[token("(")]
     LeftParen,
@@ -54,12 +54,14 @@ pub enum TokenType {
     LessEqual,
 
     // Literals
-
     #[regex("[a-zA-Z_][a-zA-Z_0-9]*")]
     Identifier,
     #[regex("\"[^\"]*\"")]
     String,
-    #[regex("[0-9]+(\\.[0-9]+)?")]
+    #[regex("[0-9][0-9_]*(\\.[0-9][0-9_]*)?([eE][+-]?[0-9][0-9_]*)?")]
+    #[regex("0x[0-9a-fA-F][0-9a-fA-F_]*")]
+    #[regex("0o[0-7][0-7_]*")]
+    #[regex("0b[01][01_]*")]
     NumberLiteral,
 
     // Keywords
@@ -96,53 +98,151 @@ pub enum TokenType {
     #[token("while")]
     While,
 
-    #[regex("//[^\n]*", logos::skip)]
+    #[regex("//[^\n]*")]
+    #[token("/*", block_comment)]
     Comment,
-    #[regex("[ \t\r]+", logos::skip)]
+    #[regex("[ \t\r\n]")]
     Whitespace,
-    #[token("\n")]
-    NewLine,
 
     #[error]
-    Error
+    Error,
+}
+
+/// Skips the rest of a block comment, failing when it is not closed.
+fn block_comment(lexer: &mut Lexer<LogosToken>) -> bool {
+    let start = lexer.span().start;
+    match scanner::block_comment_length(&lexer.source()[start..]) {
+        Some(length) => {
+            lexer.bump(length - 2);
+            true
+        }
+        None => false,
+    }
+}
+
+impl LogosToken {
+    fn token_type(self) -> Option<TokenType> {
+        use LogosToken::*;
+
+        Some(match self {
+            LeftParen => TokenType::LeftParen,
+            RightParen => TokenType::RightParen,
+            LeftBrace => TokenType::LeftBrace,
+            RightBrace => TokenType::RightBrace,
+            Comma => TokenType::Comma,
+            Dot => TokenType::Dot,
+            Plus => TokenType::Plus,
+            Minus => TokenType::Minus,
+            Semicolon => TokenType::Semicolon,
+            Slash => TokenType::Slash,
+            Star => TokenType::Star,
+            Bang => TokenType::Bang,
+            BangEqual => TokenType::BangEqual,
+            Equal => TokenType::Equal,
+            EqualEqual => TokenType::EqualEqual,
+            Greater => TokenType::Greater,
+            GreaterEqual => TokenType::GreaterEqual,
+            Less => TokenType::Less,
+            LessEqual => TokenType::LessEqual,
+            Identifier => TokenType::Identifier,
+            String => TokenType::String,
+            NumberLiteral => TokenType::NumberLiteral,
+            And => TokenType::And,
+            Class => TokenType::Class,
+            Else => TokenType::Else,
+            False => TokenType::False,
+            Fun => TokenType::Fun,
+            For => TokenType::For,
+            If => TokenType::If,
+            Nil => TokenType::Nil,
+            Or => TokenType::Or,
+            Print => TokenType::Print,
+            Return => TokenType::Return,
+            Super => TokenType::Super,
+            This => TokenType::This,
+            True => TokenType::True,
+            Var => TokenType::Var,
+            While => TokenType::While,
+            Comment | Whitespace | Error => return None,
+        })
+    }
+}
+
+/// Scans the tokens of the program, without comments, whitespace and `Eof`.
+pub fn scan_tokens(src: &str) -> Result<Vec<Token<'_>>> {
+    scan(src, false)
 }
 
-pub fn scan_tokens(src: &str) -> Result<Vec<Token>> {
-    let mut lexer = TokenType::lexer(src).spanned();
+/// Scans every token, including comments, whitespace and the final `Eof`.
+///
+/// Unlike the hand-written scanner, a line comment leaves the end of its line to
+/// the whitespace after it.
+pub fn scan_tokens_lossless(src: &str) -> Result<Vec<Token<'_>>> {
+    scan(src, true)
+}
+
+fn scan(src: &str, lossless: bool) -> Result<Vec<Token<'_>>> {
     let mut tokens = Vec::new();
     let mut line = 1;
 
-    while let Some((token_type, span)) = lexer.next() {
-        match token_type {
-            TokenType::NewLine => line += 1,
-            TokenType::String => {
-                tokens.push(Token {
-                    type_: token_type,
-                    lexeme: &src[span.clone()],
+    for (token, span) in LogosToken::lexer(src).spanned() {
+        let lexeme = &src[span];
+
+        if token == LogosToken::Error {
+            // a string or a block comment without its end are the only tokens
+            // that can fail after their first character
+            if lexeme.starts_with("/*") {
+                return Err(compilation_error(CompilationError::UnterminatedComment(
                     line,
-                });
-                line += (&src[span]).chars().filter(|c| *c == '\n').count() as u32;
-            },
-            TokenType::Error => {
-                // TODO
-            },
-            _ => tokens.push(Token {
-                type_: token_type,
-                lexeme: &src[span],
-                line,
-            }),
+                )));
+            }
+            return Err(compilation_error(match lexeme.chars().next() {
+                Some('"') => CompilationError::UnterminatedString,
+                Some(other) => CompilationError::UnexpectedCharacter(other),
+                None => CompilationError::UnterminatedString,
+            }));
+        }
+
+        // malformed numbers are matched whole, like the hand-written scanner does
+        if token == LogosToken::NumberLiteral && scanner::parse_number(lexeme).is_none() {
+            return Err(compilation_error(CompilationError::InvalidLiteral(
+                "number".into(),
+                lexeme.into(),
+            )));
+        }
+
+        let type_ = match token {
+            LogosToken::Comment if lossless => Some(scanner::comment_type(lexeme)),
+            LogosToken::Whitespace if lossless => Some(TokenType::Whitespace),
+            _ => token.token_type(),
+        };
+        if let Some(type_) = type_ {
+            tokens.push(Token::new(type_, lexeme, line));
         }
+        line += lexeme.matches('\n').count() as u32;
     }
 
+    if lossless {
+        tokens.push(Token::new(TokenType::Eof, "", line));
+    }
     Ok(tokens)
 }
 
-impl<'source_code> Token<'source_code> {
-    pub fn new(type_: TokenType, lexeme: &'source_code str, line: u32) -> Self {
-        Token {
-            type_,
-            lexeme,
-            line,
-        }
+/// The logos scanner, as a `Scanner` backend.
+pub struct LogosScanner;
+
+impl Scanner for LogosScanner {
+    fn scan_tokens<'source_code>(
+        &self,
+        source_code: &'source_code str,
+    ) -> Result<Vec<Token<'source_code>>> {
+        scan_tokens(source_code)
+    }
+
+    fn scan_tokens_lossless<'source_code>(
+        &self,
+        source_code: &'source_code str,
+    ) -> Result<Vec<Token<'source_code>>> {
+        scan_tokens_lossless(source_code)
     }
 }
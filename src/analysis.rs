@@ -0,0 +1,416 @@
+//! Static analysis of the source code for editor support: syntax errors, and
+//! which declaration each name refers to.
+
+use std::ops::Range;
+
+use crate::{
+    ast::{FunctionDecl, Statement, StatementKind},
+    cst::{NodeKind, SyntaxNode, SyntaxToken},
+    error::ErrorOrEarlyReturn,
+    lower, native, parser, scanner,
+    scanner::TokenType,
+    with_stack,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// Byte range in the source code.
+    pub range: Range<usize>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Variable,
+    Parameter,
+    Function,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Definition {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// Range of the name.
+    pub range: Range<usize>,
+    /// Range of the whole declaration.
+    pub full_range: Range<usize>,
+    /// Index of the function declaring it, if any.
+    pub parent: Option<usize>,
+    /// `fun name(params)` for functions.
+    pub signature: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    pub name: String,
+    pub range: Range<usize>,
+    /// Index of the definition, or `None` for native functions and undefined names.
+    pub definition: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scope {
+    pub range: Range<usize>,
+    pub parent: Option<usize>,
+    /// Indexes of the definitions, in the order they are declared.
+    pub definitions: Vec<usize>,
+}
+
+/// Result of resolving every name of a program. The first scope is the global one.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Analysis {
+    pub definitions: Vec<Definition>,
+    pub references: Vec<Reference>,
+    pub scopes: Vec<Scope>,
+    /// Errors found while resolving names.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Parses and resolves the source code.
+///
+/// The parser stops at the first syntax error, which is then the only diagnostic.
+pub fn analyze_source(src: &str) -> Result<Analysis, Diagnostic> {
+    let (tokens, error) = scanner::scan_tokens_until_error(src);
+    if let Some(error) = error {
+        let offset: usize = tokens.iter().map(|token| token.lexeme.len()).sum();
+        let length = src[offset..].chars().next().map_or(0, char::len_utf8);
+        return Err(Diagnostic {
+            range: offset..offset + length,
+            message: message(&error),
+        });
+    }
+
+    match parser::try_parse_cst(&tokens) {
+        Ok(root) => Ok(analyze(&root)),
+        Err(failure) => {
+            let offset: usize = tokens[..failure.token]
+                .iter()
+                .map(|token| token.lexeme.len())
+                .sum();
+            let length = tokens
+                .get(failure.token)
+                .map_or(0, |token| token.lexeme.len());
+            Err(Diagnostic {
+                range: offset..offset + length,
+                message: message(&failure.error),
+            })
+        }
+    }
+}
+
+fn message(error: &ErrorOrEarlyReturn) -> String {
+    match error {
+        ErrorOrEarlyReturn::CompilationError(error) => error.to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// Resolves the names of a `Program` node.
+pub fn analyze(root: &SyntaxNode) -> Analysis {
+    let mut resolver = Resolver {
+        analysis: Analysis::default(),
+        scopes: vec![],
+        functions: vec![],
+        initializing: None,
+        globals: vec![],
+    };
+
+    resolver.scoped(root, |resolver| {
+        for child in root.children() {
+            resolver.node(&child);
+        }
+    });
+    resolver.resolve_globals();
+
+    let mut analysis = resolver.analysis;
+    analysis
+        .diagnostics
+        .sort_by_key(|diagnostic| diagnostic.range.start);
+    analysis
+}
+
+impl Analysis {
+    /// The definition of the name at the offset, which may be a reference or the definition itself.
+    pub fn definition_at(&self, offset: usize) -> Option<usize> {
+        let contains = |range: &Range<usize>| range.start <= offset && offset <= range.end;
+
+        self.references
+            .iter()
+            .find(|reference| contains(&reference.range))
+            .and_then(|reference| reference.definition)
+            .or_else(|| {
+                self.definitions
+                    .iter()
+                    .position(|definition| contains(&definition.range))
+            })
+    }
+
+    /// The reference at the offset, if there is one.
+    pub fn reference_at(&self, offset: usize) -> Option<&Reference> {
+        self.references
+            .iter()
+            .find(|reference| reference.range.start <= offset && offset <= reference.range.end)
+    }
+
+    pub fn references_to(&self, definition: usize) -> impl Iterator<Item = &Reference> {
+        self.references
+            .iter()
+            .filter(move |reference| reference.definition == Some(definition))
+    }
+
+    /// Definitions that can be used at the offset, the innermost ones first.
+    ///
+    /// Globals can be used anywhere, locals only after being declared.
+    pub fn visible_at(&self, offset: usize) -> Vec<&Definition> {
+        let mut visible: Vec<&Definition> = vec![];
+
+        let mut scope = self
+            .scopes
+            .iter()
+            .enumerate()
+            .filter(|(_, scope)| scope.range.start <= offset && offset <= scope.range.end)
+            .max_by_key(|(_, scope)| scope.range.start)
+            .map(|(index, _)| index);
+
+        while let Some(index) = scope {
+            let is_global = self.scopes[index].parent.is_none();
+            for &definition in self.scopes[index].definitions.iter().rev() {
+                let definition = &self.definitions[definition];
+                let declared = is_global || definition.range.end <= offset;
+                if declared && visible.iter().all(|other| other.name != definition.name) {
+                    visible.push(definition);
+                }
+            }
+            scope = self.scopes[index].parent;
+        }
+
+        visible
+    }
+}
+
+/// `fun name(a, b)`
+pub fn signature(decl: &FunctionDecl) -> String {
+    format!("fun {}({})", decl.name, decl.params.join(", "))
+}
+
+/// Whether the name is of a function implemented by the interpreter.
+pub fn is_native(name: &str) -> bool {
+    native::functions().iter().any(|native| native.name == name)
+}
+
+struct Resolver {
+    analysis: Analysis,
+    /// Open scopes, the innermost last.
+    scopes: Vec<usize>,
+    /// Functions being resolved, the innermost last.
+    functions: Vec<usize>,
+    /// Local variable whose initializer is being resolved.
+    initializing: Option<String>,
+    /// References that are not to locals, resolved once every global is known.
+    globals: Vec<usize>,
+}
+
+impl Resolver {
+    fn node(&mut self, node: &SyntaxNode) {
+        with_stack(|| match node.kind() {
+            NodeKind::VarDecl => self.var_decl(node),
+            NodeKind::FunDecl => self.fun_decl(node),
+            NodeKind::Block | NodeKind::For => self.scoped(node, |resolver| {
+                for child in node.children() {
+                    resolver.node(&child);
+                }
+            }),
+            NodeKind::Name => {
+                if let Some(name) = node.tokens().next() {
+                    self.reference(&name);
+                }
+            }
+            _ => {
+                for child in node.children() {
+                    self.node(&child);
+                }
+            }
+        })
+    }
+
+    fn var_decl(&mut self, node: &SyntaxNode) {
+        let name = match node.child_token(TokenType::Identifier) {
+            Some(name) => name,
+            None => return,
+        };
+
+        if let Some(initializer) = node.children().next() {
+            if self.is_local() {
+                self.initializing = Some(name.text().into());
+            }
+            self.node(&initializer);
+            self.initializing = None;
+        }
+
+        self.define(&name, SymbolKind::Variable, node.text_range(), None);
+    }
+
+    fn fun_decl(&mut self, node: &SyntaxNode) {
+        let name = match node.child_token(TokenType::Identifier) {
+            Some(name) => name,
+            None => return,
+        };
+        let signature = match lower::statement(node) {
+            Ok(Statement {
+                kind: StatementKind::FunDecl(decl),
+                ..
+            }) => Some(signature(&decl)),
+            _ => None,
+        };
+
+        // defined before the body, so the function can call itself
+        let function = self.define(&name, SymbolKind::Function, node.text_range(), signature);
+
+        self.functions.push(function);
+        self.scoped(node, |resolver| {
+            for child in node.children() {
+                match child.kind() {
+                    NodeKind::ParamList => {
+                        for param in child.tokens() {
+                            if param.kind() == TokenType::Identifier {
+                                resolver.define(
+                                    &param,
+                                    SymbolKind::Parameter,
+                                    param.text_range(),
+                                    None,
+                                );
+                            }
+                        }
+                    }
+                    // the parameters and the body share the same scope
+                    NodeKind::Block => {
+                        for stmt in child.children() {
+                            resolver.node(&stmt);
+                        }
+                    }
+                    _ => resolver.node(&child),
+                }
+            }
+        });
+        self.functions.pop();
+    }
+
+    fn scoped(&mut self, node: &SyntaxNode, resolve: impl FnOnce(&mut Self)) {
+        self.analysis.scopes.push(Scope {
+            range: node.text_range(),
+            parent: self.scopes.last().copied(),
+            definitions: vec![],
+        });
+        self.scopes.push(self.analysis.scopes.len() - 1);
+        resolve(self);
+        self.scopes.pop();
+    }
+
+    fn is_local(&self) -> bool {
+        self.scopes.len() > 1
+    }
+
+    fn define(
+        &mut self,
+        name: &SyntaxToken,
+        kind: SymbolKind,
+        full_range: Range<usize>,
+        signature: Option<String>,
+    ) -> usize {
+        let scope = *self.scopes.last().unwrap();
+
+        if self.is_local() && self.lookup(scope, name.text()).is_some() {
+            self.error(
+                name.text_range(),
+                format!("Already a variable named '{}' in this scope.", name.text()),
+            );
+        }
+
+        self.analysis.definitions.push(Definition {
+            name: name.text().into(),
+            kind,
+            range: name.text_range(),
+            full_range,
+            parent: self.functions.last().copied(),
+            signature,
+        });
+        let definition = self.analysis.definitions.len() - 1;
+        self.analysis.scopes[scope].definitions.push(definition);
+        definition
+    }
+
+    fn lookup(&self, scope: usize, name: &str) -> Option<usize> {
+        self.analysis.scopes[scope]
+            .definitions
+            .iter()
+            .rev()
+            .copied()
+            .find(|&definition| self.analysis.definitions[definition].name == name)
+    }
+
+    fn reference(&mut self, name: &SyntaxToken) {
+        if self.initializing.as_deref() == Some(name.text()) {
+            self.error(
+                name.text_range(),
+                format!(
+                    "Can't read local variable '{}' in its own initializer.",
+                    name.text()
+                ),
+            );
+            self.analysis.references.push(Reference {
+                name: name.text().into(),
+                range: name.text_range(),
+                definition: None,
+            });
+            return;
+        }
+
+        let local = self.scopes[1..]
+            .iter()
+            .rev()
+            .find_map(|&scope| self.lookup(scope, name.text()));
+
+        self.analysis.references.push(Reference {
+            name: name.text().into(),
+            range: name.text_range(),
+            definition: local,
+        });
+        if local.is_none() {
+            self.globals.push(self.analysis.references.len() - 1);
+        }
+    }
+
+    /// Globals may be used before being declared, inside functions called later.
+    fn resolve_globals(&mut self) {
+        for reference in std::mem::take(&mut self.globals) {
+            let Reference { name, range, .. } = &self.analysis.references[reference];
+            let candidates: Vec<usize> = self.analysis.scopes[0]
+                .definitions
+                .iter()
+                .copied()
+                .filter(|&definition| &self.analysis.definitions[definition].name == name)
+                .collect();
+
+            let definition = candidates
+                .iter()
+                .rev()
+                .find(|&&definition| {
+                    self.analysis.definitions[definition].range.start < range.start
+                })
+                .or_else(|| candidates.first())
+                .copied();
+
+            if definition.is_none() && !is_native(name) {
+                let (range, message) = (range.clone(), format!("Undefined variable '{}'.", name));
+                self.error(range, message);
+            }
+            self.analysis.references[reference].definition = definition;
+        }
+    }
+
+    fn error(&mut self, range: Range<usize>, message: String) {
+        self.analysis
+            .diagnostics
+            .push(Diagnostic { range, message });
+    }
+}
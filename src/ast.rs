@@ -1,21 +1,74 @@
-use std::rc::Rc;
+use std::{
+    cell::OnceCell,
+    collections::HashMap,
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
 
-use crate::scanner::TokenType;
+use crate::{
+    analysis::{self, Diagnostic},
+    cst::SyntaxNode,
+    scanner::TokenType,
+    source_map::FileId,
+    with_stack,
+};
 
-#[derive(Debug, PartialEq, Clone)]
+/// Identifies a node of the syntax tree, so analyses can keep what they find
+/// about it in a `NodeMap` instead of in the tree.
+///
+/// Ids are unique within the process, even across trees, and are left out when
+/// comparing or printing nodes, which compare by structure. A cloned node keeps
+/// the ids of the original, so what is known about one holds for the other;
+/// build the node again, or give it a `fresh` id, to tell them apart.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, PartialOrd, Ord)]
+pub struct NodeId(u64);
+
+impl NodeId {
+    /// A new id. The counter is 64 bits wide, so it does not wrap around.
+    pub fn fresh() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        NodeId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// Results of an analysis, for the nodes it looked at.
+pub type NodeMap<T> = HashMap<NodeId, T>;
+
+/// Root of the tree, as returned by `parser::parse`.
+#[derive(Clone)]
 pub struct Program {
     statements: Vec<Statement>,
+    file: Option<FileId>,
+    /// Files imported by the program, in the order of their imports. Empty until
+    /// Lox has an import statement.
+    imports: Vec<FileId>,
+    /// Index in `statements` of the global declarations, by name.
+    declarations: HashMap<String, usize>,
+    /// Concrete tree the statements come from, resolved for the diagnostics.
+    syntax: SyntaxNode,
+    /// Found the first time they are asked for, so running a program does not
+    /// pay for them.
+    diagnostics: OnceCell<Vec<Diagnostic>>,
+}
+
+#[derive(Clone)]
+pub struct Statement {
+    pub id: NodeId,
+    pub kind: StatementKind,
 }
 
 #[derive(Debug, PartialEq, Clone)]
-pub enum Statement {
+pub enum StatementKind {
     Expr(Expr),
     Print(Expr),
     VariableDecl(String, Option<Expr>),
     Block(Vec<Statement>),
     If(IfStatement),
     While(WhileStatement),
-    FunDecl(Rc<FunctionDecl>),
+    FunDecl(Arc<FunctionDecl>),
     Return(Option<Expr>),
 }
 
@@ -32,15 +85,24 @@ pub struct WhileStatement {
     pub stmt: Box<Statement>,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Clone)]
 pub struct FunctionDecl {
+    pub id: NodeId,
     pub name: String,
     pub params: Vec<String>,
     pub body: Vec<Statement>,
 }
 
+/// A long chain of operators, as in `a + a + …`, makes a tree as deep as the
+/// chain is long, so expressions are cloned, compared, printed and dropped on a
+/// native stack that grows on demand.
+pub struct Expr {
+    pub id: NodeId,
+    pub kind: ExprKind,
+}
+
 #[derive(Debug, PartialEq, Clone)]
-pub enum Expr {
+pub enum ExprKind {
     Identifier(String),
     Literal(LiteralExpr),
     Unary(UnaryExpr),
@@ -76,3 +138,199 @@ pub struct CallExpr {
     pub callee: Box<Expr>,
     pub args: Vec<Expr>,
 }
+
+impl Program {
+    /// `syntax` is the `Program` node the statements were lowered from.
+    pub fn new(statements: Vec<Statement>, syntax: SyntaxNode) -> Self {
+        let declarations = statements
+            .iter()
+            .enumerate()
+            .filter_map(|(index, stmt)| match &stmt.kind {
+                StatementKind::VariableDecl(name, _) => Some((name.clone(), index)),
+                StatementKind::FunDecl(function) => Some((function.name.clone(), index)),
+                _ => None,
+            })
+            .collect();
+
+        Program {
+            statements,
+            file: None,
+            imports: vec![],
+            declarations,
+            syntax,
+            diagnostics: OnceCell::new(),
+        }
+    }
+
+    /// Records the file the program was parsed from.
+    pub fn with_file(self, file: FileId) -> Self {
+        Program {
+            file: Some(file),
+            ..self
+        }
+    }
+
+    pub fn statements(&self) -> &[Statement] {
+        &self.statements
+    }
+
+    pub fn into_statements(self) -> Vec<Statement> {
+        self.statements
+    }
+
+    /// The file the program was parsed from. Only `SourceMap::parse` knows it,
+    /// so it is `None` for the programs returned by `parser::parse`.
+    pub fn file(&self) -> Option<FileId> {
+        self.file
+    }
+
+    /// The global variable or function declaration of the name. When there are
+    /// several, the last one, which is in effect once the program has run.
+    pub fn declaration(&self, name: &str) -> Option<&Statement> {
+        self.declarations
+            .get(name)
+            .map(|&index| &self.statements[index])
+    }
+
+    /// Names of the global variables and functions.
+    pub fn declarations(&self) -> impl Iterator<Item = &str> {
+        self.declarations.keys().map(String::as_str)
+    }
+
+    /// Files the program imports.
+    pub fn imports(&self) -> &[FileId] {
+        &self.imports
+    }
+
+    /// Problems that do not prevent the program from running, such as names
+    /// that are never declared. Ranges are in the text of the parsed tokens, so
+    /// in the source code when comments and whitespace were kept.
+    ///
+    /// The names are resolved on the first call.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        self.diagnostics
+            .get_or_init(|| analysis::analyze(&self.syntax).diagnostics)
+    }
+}
+
+/// Programs compare by their statements, file and imports.
+impl PartialEq for Program {
+    fn eq(&self, other: &Self) -> bool {
+        self.statements == other.statements
+            && self.file == other.file
+            && self.imports == other.imports
+    }
+}
+
+impl fmt::Debug for Program {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("Program")
+            .field("statements", &self.statements)
+            .field("file", &self.file)
+            .field("imports", &self.imports)
+            .finish()
+    }
+}
+
+impl Statement {
+    pub fn new(kind: StatementKind) -> Self {
+        Statement {
+            id: NodeId::fresh(),
+            kind,
+        }
+    }
+}
+
+impl From<StatementKind> for Statement {
+    fn from(kind: StatementKind) -> Self {
+        Statement::new(kind)
+    }
+}
+
+impl PartialEq for Statement {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+    }
+}
+
+impl fmt::Debug for Statement {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.kind.fmt(fmt)
+    }
+}
+
+impl FunctionDecl {
+    pub fn new(name: String, params: Vec<String>, body: Vec<Statement>) -> Self {
+        FunctionDecl {
+            id: NodeId::fresh(),
+            name,
+            params,
+            body,
+        }
+    }
+}
+
+impl PartialEq for FunctionDecl {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.params == other.params && self.body == other.body
+    }
+}
+
+impl fmt::Debug for FunctionDecl {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("FunctionDecl")
+            .field("name", &self.name)
+            .field("params", &self.params)
+            .field("body", &self.body)
+            .finish()
+    }
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind) -> Self {
+        Expr {
+            id: NodeId::fresh(),
+            kind,
+        }
+    }
+
+    pub fn into_kind(mut self) -> ExprKind {
+        std::mem::replace(&mut self.kind, ExprKind::Literal(LiteralExpr::Nil))
+    }
+}
+
+impl From<ExprKind> for Expr {
+    fn from(kind: ExprKind) -> Self {
+        Expr::new(kind)
+    }
+}
+
+impl Clone for Expr {
+    fn clone(&self) -> Self {
+        with_stack(|| Expr {
+            id: self.id,
+            kind: self.kind.clone(),
+        })
+    }
+}
+
+impl PartialEq for Expr {
+    fn eq(&self, other: &Self) -> bool {
+        with_stack(|| self.kind == other.kind)
+    }
+}
+
+impl fmt::Debug for Expr {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        with_stack(|| self.kind.fmt(fmt))
+    }
+}
+
+impl Drop for Expr {
+    fn drop(&mut self) {
+        if !matches!(self.kind, ExprKind::Identifier(_) | ExprKind::Literal(_)) {
+            let kind = std::mem::replace(&mut self.kind, ExprKind::Literal(LiteralExpr::Nil));
+            with_stack(|| drop(kind));
+        }
+    }
+}
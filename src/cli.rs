@@ -0,0 +1,462 @@
+use std::{fmt::Display, iter::Peekable};
+
+use crate::{profile::ProfileFormat, scanner::Backend, trace::TraceFormat};
+
+pub const USAGE: &str = "\
+Usage: rlox [script [args...]]
+       rlox <command> [options] <script | -e code | -> [args...]
+       rlox fmt [--check | --write] [--width N] <script | -e code | ->...
+       rlox debug <script> [args...]
+       rlox lsp | rlox dap
+
+Commands:
+  run      run the script
+  check    scan, parse and resolve the script, without running it, and report
+           undefined names and other mistakes
+  tokens   show the tokens of the script
+  ast      show the syntax tree of the script
+  fmt      format the script
+  debug    run the script step by step, reading debugger commands from the standard input
+  lsp      start a language server, speaking LSP over the standard input and output
+  dap      start a debug adapter, speaking DAP over the standard input and output
+
+Options of run:
+  --trace        log the statements executed and the function calls on the standard error
+  --trace=json   log them as JSON lines
+  --profile      report the time spent in each function and how often each line runs, on
+                 the standard error
+  --profile=collapsed
+                 report the time spent in each call stack, for flame graph tools
+  --coverage FILE
+                 add the lines, branches and functions covered to the LCOV tracefile FILE,
+                 and show a summary on the standard error
+  --seed=N       make the run repeatable: seed random() with N, and stop clock() at 0
+
+Options of run, check, tokens, ast and debug:
+  --scanner=NAME scan with the 'recursive-descent' scanner (the default)
+                 or the 'logos' one
+
+Options of fmt:
+  --check      only report the scripts that are not formatted
+  --write      rewrite the files instead of printing the formatted code
+  --width N    wrap lines longer than N characters (default 80)
+
+Without a command, runs the script, or starts the prompt if there is none.
+The script is read from the standard input when it is '-'.
+Arguments after the script are available to it through argc() and argv(i).";
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Command {
+    Repl,
+    Run,
+    Check,
+    Tokens,
+    Ast,
+    Fmt,
+    Debug,
+    Lsp,
+    Dap,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Input {
+    File(String),
+    Stdin,
+    Inline(String),
+}
+
+impl Display for Input {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Input::File(filename) => write!(fmt, "'{}'", filename),
+            Input::Stdin => write!(fmt, "the standard input"),
+            Input::Inline(_) => write!(fmt, "the inline code"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Options {
+    /// `fmt --check`
+    pub check: bool,
+    /// `fmt --write`
+    pub write: bool,
+    /// `fmt --width N`
+    pub width: Option<usize>,
+    /// `run --trace`
+    pub trace: Option<TraceFormat>,
+    /// `run --profile`
+    pub profile: Option<ProfileFormat>,
+    /// `run --coverage FILE`
+    pub coverage: Option<String>,
+    /// `run --seed=N`
+    pub seed: Option<u64>,
+    /// `--scanner=NAME` of run, check, tokens, ast and debug
+    pub scanner: Option<Backend>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Cli {
+    pub command: Command,
+    pub options: Options,
+    /// Where the code comes from. `None` for the prompt, the language server and the debug adapter.
+    pub input: Option<Input>,
+    /// Arguments passed to the script, or the other scripts to be formatted by `fmt`.
+    pub args: Vec<String>,
+}
+
+/// Parses the command line arguments, not including the name of the program.
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Cli, String> {
+    let mut args = args.into_iter().peekable();
+
+    let command = match args.peek().map(String::as_str) {
+        None => {
+            return Ok(Cli {
+                command: Command::Repl,
+                options: Options::default(),
+                input: None,
+                args: vec![],
+            })
+        }
+        Some("run") => Command::Run,
+        Some("check") => Command::Check,
+        Some("tokens") => Command::Tokens,
+        Some("ast") => Command::Ast,
+        Some("fmt") => Command::Fmt,
+        Some("debug") => Command::Debug,
+        Some(name @ ("lsp" | "dap")) => {
+            let command = if name == "lsp" {
+                Command::Lsp
+            } else {
+                Command::Dap
+            };
+            args.next();
+            // editors usually ask for the transport, and stdio is the only one
+            let args: Vec<String> = args.filter(|arg| arg != "--stdio").collect();
+            if !args.is_empty() {
+                return Err(format!("Unexpected arguments: {}", args.join(" ")));
+            }
+            return Ok(Cli {
+                command,
+                options: Options::default(),
+                input: None,
+                args,
+            });
+        }
+        // `rlox script.lox` is the same as `rlox run script.lox`
+        Some(_) => {
+            let input = parse_input(&mut args)?;
+            return Ok(Cli {
+                command: Command::Run,
+                options: Options::default(),
+                input: Some(input),
+                args: args.collect(),
+            });
+        }
+    };
+    args.next();
+
+    let options = parse_options(command, &mut args)?;
+    let input = parse_input(&mut args)?;
+    let args: Vec<String> = args.collect();
+
+    // the standard input is where the debugger reads its commands from
+    if command == Command::Debug && !matches!(input, Input::File(_)) {
+        return Err("The script to debug must be a file".into());
+    }
+    if !matches!(command, Command::Run | Command::Fmt | Command::Debug) && !args.is_empty() {
+        return Err(format!("Unexpected arguments: {}", args.join(" ")));
+    }
+
+    Ok(Cli {
+        command,
+        options,
+        input: Some(input),
+        args,
+    })
+}
+
+fn parse_options(
+    command: Command,
+    args: &mut Peekable<impl Iterator<Item = String>>,
+) -> Result<Options, String> {
+    let mut options = Options::default();
+
+    while let Some(option) = args.next_if(|arg| arg.starts_with("--")) {
+        match (command, option.as_str()) {
+            (Command::Run, "--trace" | "--trace=text") => options.trace = Some(TraceFormat::Text),
+            (Command::Run, "--trace=json") => options.trace = Some(TraceFormat::Json),
+            (Command::Run, "--profile" | "--profile=text") => {
+                options.profile = Some(ProfileFormat::Text)
+            }
+            (Command::Run, "--profile=collapsed") => {
+                options.profile = Some(ProfileFormat::Collapsed)
+            }
+            (Command::Run, "--coverage") => {
+                let file = args.next().ok_or("Missing the file after '--coverage'")?;
+                options.coverage = Some(file);
+            }
+            (Command::Run, option) if option.starts_with("--seed=") => {
+                let seed = &option["--seed=".len()..];
+                let seed = seed
+                    .parse()
+                    .map_err(|_| format!("Invalid seed '{}'", seed))?;
+                options.seed = Some(seed);
+            }
+            (
+                Command::Run | Command::Check | Command::Tokens | Command::Ast | Command::Debug,
+                option,
+            ) if option.starts_with("--scanner=") => {
+                options.scanner = Some(parse_scanner(&option["--scanner=".len()..])?)
+            }
+            (Command::Fmt, "--check") => options.check = true,
+            (Command::Fmt, "--write") => options.write = true,
+            (Command::Fmt, "--width") => {
+                let width = args.next().ok_or("Missing the width after '--width'")?;
+                let width = width
+                    .parse()
+                    .map_err(|_| format!("Invalid width '{}'", width))?;
+                options.width = Some(width);
+            }
+            _ => return Err(format!("Unknown option '{}'", option)),
+        }
+    }
+
+    if options.check && options.write {
+        return Err("'--check' and '--write' cannot be used together".into());
+    }
+    let instrumentations = [
+        options.trace.is_some(),
+        options.profile.is_some(),
+        options.coverage.is_some(),
+    ];
+    if instrumentations.iter().filter(|&&enabled| enabled).count() > 1 {
+        return Err("Only one of '--trace', '--profile' and '--coverage' can be used".into());
+    }
+
+    Ok(options)
+}
+
+fn parse_scanner(name: &str) -> Result<Backend, String> {
+    match name {
+        "recursive-descent" => Ok(Backend::RecursiveDescent),
+        "logos" => Ok(Backend::Logos),
+        _ => Err(format!("Unknown scanner '{}'", name)),
+    }
+}
+
+fn parse_input(args: &mut impl Iterator<Item = String>) -> Result<Input, String> {
+    match args.next().as_deref() {
+        None => Err("Missing the script to run".into()),
+        Some("-") => Ok(Input::Stdin),
+        Some("-e") => args
+            .next()
+            .map(Input::Inline)
+            .ok_or_else(|| "Missing the code after '-e'".into()),
+        Some(option) if option.starts_with('-') => Err(format!("Unknown option '{}'", option)),
+        Some(file) => Ok(Input::File(file.into())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Cli, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_no_args_starts_the_prompt() {
+        assert_eq!(parse(&[]).unwrap().command, Command::Repl);
+    }
+
+    #[test]
+    fn test_script_without_command() {
+        assert_eq!(
+            parse(&["script.lox", "a", "b"]).unwrap(),
+            Cli {
+                command: Command::Run,
+                options: Options::default(),
+                input: Some(Input::File("script.lox".into())),
+                args: vec!["a".into(), "b".into()],
+            }
+        );
+    }
+
+    #[test]
+    fn test_commands() {
+        for (name, command) in [
+            ("run", Command::Run),
+            ("check", Command::Check),
+            ("tokens", Command::Tokens),
+            ("ast", Command::Ast),
+            ("fmt", Command::Fmt),
+            ("debug", Command::Debug),
+        ] {
+            assert_eq!(
+                parse(&[name, "script.lox"]).unwrap(),
+                Cli {
+                    command,
+                    options: Options::default(),
+                    input: Some(Input::File("script.lox".into())),
+                    args: vec![],
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn test_inline_code_and_stdin() {
+        assert_eq!(
+            parse(&["run", "-e", "print 1;", "x"]).unwrap().input,
+            Some(Input::Inline("print 1;".into()))
+        );
+        assert_eq!(parse(&["check", "-"]).unwrap().input, Some(Input::Stdin));
+        assert_eq!(parse(&["-e", "print 1;"]).unwrap().command, Command::Run);
+    }
+
+    #[test]
+    fn test_fmt_options() {
+        let cli = parse(&["fmt", "--check", "--width", "100", "a.lox", "b.lox"]).unwrap();
+        assert_eq!(
+            cli.options,
+            Options {
+                check: true,
+                write: false,
+                width: Some(100),
+                trace: None,
+                profile: None,
+                coverage: None,
+                seed: None,
+                scanner: None,
+            }
+        );
+        assert_eq!(cli.input, Some(Input::File("a.lox".into())));
+        assert_eq!(cli.args, vec!["b.lox".to_string()]);
+
+        assert!(parse(&["fmt", "--width", "wide", "a.lox"]).is_err());
+        assert!(parse(&["fmt", "--check", "--write", "a.lox"]).is_err());
+        assert!(parse(&["run", "--check", "a.lox"]).is_err());
+    }
+
+    #[test]
+    fn test_trace() {
+        assert_eq!(
+            parse(&["run", "--trace", "a.lox"]).unwrap().options.trace,
+            Some(TraceFormat::Text)
+        );
+        assert_eq!(
+            parse(&["run", "--trace=json", "a.lox"])
+                .unwrap()
+                .options
+                .trace,
+            Some(TraceFormat::Json)
+        );
+
+        assert!(parse(&["run", "--trace=xml", "a.lox"]).is_err());
+        assert!(parse(&["check", "--trace", "a.lox"]).is_err());
+    }
+
+    #[test]
+    fn test_profile() {
+        assert_eq!(
+            parse(&["run", "--profile", "a.lox"])
+                .unwrap()
+                .options
+                .profile,
+            Some(ProfileFormat::Text)
+        );
+        assert_eq!(
+            parse(&["run", "--profile=collapsed", "a.lox"])
+                .unwrap()
+                .options
+                .profile,
+            Some(ProfileFormat::Collapsed)
+        );
+
+        assert!(parse(&["run", "--profile", "--trace", "a.lox"]).is_err());
+    }
+
+    #[test]
+    fn test_coverage() {
+        let cli = parse(&["run", "--coverage", "lcov.info", "a.lox"]).unwrap();
+        assert_eq!(cli.options.coverage, Some("lcov.info".into()));
+        assert_eq!(cli.input, Some(Input::File("a.lox".into())));
+
+        assert!(parse(&["run", "--coverage"]).is_err());
+        assert!(parse(&["run", "--coverage", "lcov.info", "--profile", "a.lox"]).is_err());
+    }
+
+    #[test]
+    fn test_seed() {
+        assert_eq!(
+            parse(&["run", "--seed=42", "--trace", "a.lox"])
+                .unwrap()
+                .options
+                .seed,
+            Some(42)
+        );
+
+        assert!(parse(&["run", "--seed=-1", "a.lox"]).is_err());
+        assert!(parse(&["check", "--seed=42", "a.lox"]).is_err());
+    }
+
+    #[test]
+    fn test_scanner() {
+        for command in ["run", "check", "tokens", "ast", "debug"] {
+            assert_eq!(
+                parse(&[command, "--scanner=recursive-descent", "a.lox"])
+                    .unwrap()
+                    .options
+                    .scanner,
+                Some(Backend::RecursiveDescent)
+            );
+        }
+        assert_eq!(
+            parse(&["run", "--scanner=logos", "a.lox"])
+                .unwrap()
+                .options
+                .scanner,
+            Some(Backend::Logos)
+        );
+
+        assert!(parse(&["run", "--scanner=regex", "a.lox"]).is_err());
+        assert!(parse(&["fmt", "--scanner=recursive-descent", "a.lox"]).is_err());
+        assert_eq!(
+            parse(&["run", "--scanner=logos", "--profile", "a.lox"])
+                .unwrap()
+                .options
+                .scanner,
+            Some(Backend::Logos)
+        );
+    }
+
+    #[test]
+    fn test_lsp() {
+        let cli = parse(&["lsp", "--stdio"]).unwrap();
+        assert_eq!(cli.command, Command::Lsp);
+        assert_eq!(cli.input, None);
+
+        assert!(parse(&["lsp", "script.lox"]).is_err());
+        assert_eq!(parse(&["dap"]).unwrap().command, Command::Dap);
+    }
+
+    #[test]
+    fn test_debug() {
+        let cli = parse(&["debug", "script.lox", "a"]).unwrap();
+        assert_eq!(cli.command, Command::Debug);
+        assert_eq!(cli.args, vec!["a".to_string()]);
+
+        assert!(parse(&["debug", "-"]).is_err());
+        assert!(parse(&["debug", "-e", "print 1;"]).is_err());
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(parse(&["run"]).is_err());
+        assert!(parse(&["run", "-e"]).is_err());
+        assert!(parse(&["run", "--nope", "script.lox"]).is_err());
+        assert!(parse(&["check", "script.lox", "extra"]).is_err());
+    }
+}
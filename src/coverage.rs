@@ -0,0 +1,441 @@
+//! Statement, branch and function coverage, written in the LCOV format so
+//! existing coverage viewers can show it.
+
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    fmt,
+    io::Write,
+    sync::Arc,
+};
+
+use crate::{
+    ast::{Expr, ExprKind, FunctionDecl, NodeMap, Statement, StatementKind},
+    debugger::{Branch, Callee, Debugger, Script},
+    environment::Value,
+    error::{ice, ICE},
+    interpreter::Interpreter,
+    scanner::TokenType,
+    visit::{walk_expr, walk_function, walk_stmt, Visitor},
+    Result,
+};
+
+/// Collects the coverage of a script while it runs.
+pub struct Coverage<'script> {
+    script: &'script Script,
+    /// Hits of every line where execution may pause.
+    lines: BTreeMap<u32, u64>,
+    functions: NodeMap<FunctionSite>,
+    branches: NodeMap<BranchSite>,
+}
+
+struct FunctionSite {
+    name: String,
+    line: u32,
+    /// Position among the functions declared in the line.
+    position: u32,
+    hits: u64,
+}
+
+struct BranchSite {
+    line: u32,
+    /// Position among the conditions of the line.
+    block: u32,
+    /// Times the condition was true, then false.
+    taken: [u64; 2],
+}
+
+impl<'script> Coverage<'script> {
+    pub fn new(script: &'script Script) -> Self {
+        let mut coverage = Coverage {
+            script,
+            lines: script.lines().into_iter().map(|line| (line, 0)).collect(),
+            functions: NodeMap::new(),
+            branches: NodeMap::new(),
+        };
+
+        let mut sites = Sites {
+            script,
+            coverage: &mut coverage,
+            line: 0,
+            blocks: HashMap::new(),
+            functions: HashMap::new(),
+        };
+        sites.visit_block(script.statements());
+
+        coverage
+    }
+
+    /// What was covered so far.
+    pub fn report(&self) -> FileCoverage {
+        let functions = self
+            .functions
+            .values()
+            .map(|function| {
+                let key = (function.line, function.position, function.name.clone());
+                (key, function.hits)
+            })
+            .collect();
+
+        let branches = self
+            .branches
+            .values()
+            .flat_map(|site| {
+                // a condition that never ran has no branch taken, not zero times
+                let ran = site.taken.iter().any(|&taken| taken > 0);
+                (0..2).map(move |branch| {
+                    let taken = ran.then_some(site.taken[branch as usize]);
+                    ((site.line, site.block, branch), taken)
+                })
+            })
+            .collect();
+
+        FileCoverage {
+            lines: self.lines.clone(),
+            functions,
+            branches,
+        }
+    }
+}
+
+impl Debugger for Coverage<'_> {
+    fn before_stmt(&mut self, _interpreter: &mut Interpreter<'_>, stmt: &Statement) -> Result<()> {
+        if let Some(line) = self.script.line(stmt) {
+            *self.lines.entry(line).or_default() += 1;
+        }
+        Ok(())
+    }
+
+    fn on_call(
+        &mut self,
+        _interpreter: &Interpreter<'_>,
+        callee: Callee,
+        _args: &[Value],
+    ) -> Result<()> {
+        if let Callee::Function(decl) = callee {
+            if let Some(function) = self.functions.get_mut(&decl.id) {
+                function.hits += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn on_branch(
+        &mut self,
+        _interpreter: &Interpreter<'_>,
+        branch: Branch,
+        taken: bool,
+    ) -> Result<()> {
+        if let Some(site) = self.branches.get_mut(&branch.id()) {
+            site.taken[usize::from(!taken)] += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Finds the functions and conditions of the script before it runs, so the
+/// ones that never run are reported too.
+struct Sites<'a, 'script> {
+    script: &'script Script,
+    coverage: &'a mut Coverage<'script>,
+    /// Line of the statement being visited.
+    line: u32,
+    /// Number of conditions found so far in each line.
+    blocks: HashMap<u32, u32>,
+    /// Number of functions found so far in each line.
+    functions: HashMap<u32, u32>,
+}
+
+impl Sites<'_, '_> {
+    fn branch(&mut self, branch: Branch) {
+        let block = self.blocks.entry(self.line).or_default();
+        self.coverage.branches.insert(
+            branch.id(),
+            BranchSite {
+                line: self.line,
+                block: *block,
+                taken: [0, 0],
+            },
+        );
+        *block += 1;
+    }
+}
+
+impl<'ast> Visitor<'ast> for Sites<'_, '_> {
+    fn visit_stmt(&mut self, stmt: &'ast Statement) {
+        let line = self.line;
+        self.line = self.script.line(stmt).unwrap_or(line);
+        match &stmt.kind {
+            StatementKind::If(if_stmt) => {
+                self.visit_expr(&if_stmt.cond);
+                self.branch(Branch::If(stmt.id, if_stmt));
+                self.visit_stmt(&if_stmt.then_branch);
+                if let Some(else_branch) = &if_stmt.else_branch {
+                    self.visit_stmt(else_branch);
+                }
+            }
+            StatementKind::While(while_stmt) => {
+                self.visit_expr(&while_stmt.cond);
+                self.branch(Branch::While(stmt.id, while_stmt));
+                self.visit_stmt(&while_stmt.stmt);
+            }
+            _ => walk_stmt(self, stmt),
+        }
+        self.line = line;
+    }
+
+    fn visit_function(&mut self, function: &'ast Arc<FunctionDecl>) {
+        let position = self.functions.entry(self.line).or_default();
+        self.coverage.functions.insert(
+            function.id,
+            FunctionSite {
+                name: function.name.clone(),
+                line: self.line,
+                position: *position,
+                hits: 0,
+            },
+        );
+        *position += 1;
+        walk_function(self, function);
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+        match &expr.kind {
+            ExprKind::Binary(binary) if matches!(binary.op, TokenType::And | TokenType::Or) => {
+                self.visit_expr(&binary.left);
+                self.branch(Branch::Logical(expr.id, binary));
+                self.visit_expr(&binary.right);
+            }
+            _ => walk_expr(self, expr),
+        }
+    }
+}
+
+/// Coverage of one file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileCoverage {
+    /// Hits of each line with a statement.
+    pub lines: BTreeMap<u32, u64>,
+    /// Hits of each function, by line of declaration, position among the
+    /// functions declared in the line, and name.
+    pub functions: BTreeMap<(u32, u32, String), u64>,
+    /// Times each branch was taken, by line, block and branch. `None` when
+    /// the condition never ran.
+    pub branches: BTreeMap<(u32, u32, u32), Option<u64>>,
+}
+
+impl FileCoverage {
+    /// Adds the hits of another run of the same file.
+    pub fn merge(&mut self, other: FileCoverage) {
+        for (line, hits) in other.lines {
+            *self.lines.entry(line).or_default() += hits;
+        }
+        for (function, hits) in other.functions {
+            *self.functions.entry(function).or_default() += hits;
+        }
+        for (branch, taken) in other.branches {
+            let total = self.branches.entry(branch).or_default();
+            *total = match (*total, taken) {
+                (Some(a), Some(b)) => Some(a + b),
+                (a, b) => a.or(b),
+            };
+        }
+    }
+
+    /// Lines hit and lines with a statement.
+    pub fn line_counts(&self) -> (usize, usize) {
+        count(self.lines.values().map(|&hits| hits > 0))
+    }
+
+    /// Branches taken and branches.
+    pub fn branch_counts(&self) -> (usize, usize) {
+        count(self.branches.values().map(|&taken| taken.unwrap_or(0) > 0))
+    }
+
+    /// Functions called and functions.
+    pub fn function_counts(&self) -> (usize, usize) {
+        count(self.functions.values().map(|&hits| hits > 0))
+    }
+}
+
+fn count(hits: impl Iterator<Item = bool>) -> (usize, usize) {
+    hits.fold((0, 0), |(hit, total), is_hit| {
+        (hit + usize::from(is_hit), total + 1)
+    })
+}
+
+/// Coverage of several files, as found in an LCOV tracefile.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Lcov {
+    /// Coverage of each file, by path.
+    pub files: BTreeMap<String, FileCoverage>,
+}
+
+impl Lcov {
+    /// Reads the records this module writes, ignoring the others.
+    pub fn parse(src: &str) -> Result<Lcov> {
+        let mut lcov = Lcov::default();
+        let mut file: Option<(String, FileCoverage)> = None;
+        // line and position of the functions of the current file, by name, in
+        // the order of their `FN` records, which the `FNDA` records follow
+        let mut function_lines: HashMap<String, VecDeque<(u32, u32)>> = HashMap::new();
+        // number of `FN` records so far in each line of the current file
+        let mut functions_in_line: HashMap<u32, u32> = HashMap::new();
+
+        for (index, line) in src.lines().enumerate() {
+            let invalid = || {
+                ice(ICE::Generic(format!(
+                    "Invalid LCOV record at line {}: {}",
+                    index + 1,
+                    line
+                )))
+            };
+            let (record, value) = line.split_once(':').unwrap_or((line.trim(), ""));
+            let fields: Vec<&str> = value.split(',').collect();
+            let number = |field: usize| -> Result<u32> {
+                fields
+                    .get(field)
+                    .and_then(|field| field.parse().ok())
+                    .ok_or_else(invalid)
+            };
+
+            match (record, &mut file) {
+                ("SF", _) => file = Some((value.into(), FileCoverage::default())),
+                ("end_of_record", _) => {
+                    let (path, coverage) = file.take().ok_or_else(invalid)?;
+                    lcov.files.entry(path).or_default().merge(coverage);
+                    function_lines.clear();
+                    functions_in_line.clear();
+                }
+                ("DA", Some((_, coverage))) => {
+                    let hits = fields.get(1).and_then(|hits| hits.parse().ok());
+                    coverage.lines.insert(number(0)?, hits.ok_or_else(invalid)?);
+                }
+                ("FN", Some((_, coverage))) => {
+                    let name = fields.get(1).ok_or_else(invalid)?.to_string();
+                    let line = number(0)?;
+                    let position = functions_in_line.entry(line).or_default();
+                    function_lines
+                        .entry(name.clone())
+                        .or_default()
+                        .push_back((line, *position));
+                    coverage.functions.insert((line, *position, name), 0);
+                    *position += 1;
+                }
+                ("FNDA", Some((_, coverage))) => {
+                    let name = fields.get(1).ok_or_else(invalid)?.to_string();
+                    let (line, position) = function_lines
+                        .get_mut(&name)
+                        .and_then(VecDeque::pop_front)
+                        .ok_or_else(invalid)?;
+                    let hits = fields[0].parse().map_err(|_| invalid())?;
+                    coverage.functions.insert((line, position, name), hits);
+                }
+                ("BRDA", Some((_, coverage))) => {
+                    let taken = match fields.get(3) {
+                        Some(&"-") => None,
+                        Some(taken) => Some(taken.parse().map_err(|_| invalid())?),
+                        None => return Err(invalid()),
+                    };
+                    coverage
+                        .branches
+                        .insert((number(0)?, number(1)?, number(2)?), taken);
+                }
+                (_, Some(_)) | ("TN", None) | ("", None) => (),
+                _ => return Err(invalid()),
+            }
+        }
+
+        match file {
+            Some(_) => Err(ice(ICE::Generic(
+                "Missing end_of_record at the end of the LCOV tracefile".into(),
+            ))),
+            None => Ok(lcov),
+        }
+    }
+
+    pub fn add(&mut self, path: &str, coverage: FileCoverage) {
+        self.files.entry(path.into()).or_default().merge(coverage);
+    }
+
+    /// Adds the hits of other runs.
+    pub fn merge(&mut self, other: Lcov) {
+        for (path, coverage) in other.files {
+            self.add(&path, coverage);
+        }
+    }
+
+    /// Writes the lines, branches and functions covered in each file.
+    pub fn write_summary(&self, output: &mut impl Write) -> Result<()> {
+        let percent = |(hit, total): (usize, usize)| {
+            if total == 0 {
+                "-".to_string()
+            } else {
+                format!(
+                    "{:.1}% ({}/{})",
+                    hit as f64 * 100.0 / total as f64,
+                    hit,
+                    total
+                )
+            }
+        };
+
+        writeln!(
+            output,
+            "{:<30} {:>18} {:>18} {:>18}",
+            "File", "Lines", "Branches", "Functions"
+        )?;
+        for (path, coverage) in &self.files {
+            writeln!(
+                output,
+                "{:<30} {:>18} {:>18} {:>18}",
+                path,
+                percent(coverage.line_counts()),
+                percent(coverage.branch_counts()),
+                percent(coverage.function_counts())
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes the LCOV tracefile.
+impl fmt::Display for Lcov {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (path, coverage) in &self.files {
+            writeln!(fmt, "TN:")?;
+            writeln!(fmt, "SF:{}", path)?;
+
+            // one record per declaration, even with the same line and name
+            for (line, _, name) in coverage.functions.keys() {
+                writeln!(fmt, "FN:{},{}", line, name)?;
+            }
+            // in the same order, which tells apart the functions with the same name
+            for ((_, _, name), hits) in &coverage.functions {
+                writeln!(fmt, "FNDA:{},{}", hits, name)?;
+            }
+            let (hit, total) = coverage.function_counts();
+            writeln!(fmt, "FNF:{}", total)?;
+            writeln!(fmt, "FNH:{}", hit)?;
+
+            for ((line, block, branch), taken) in &coverage.branches {
+                match taken {
+                    Some(taken) => writeln!(fmt, "BRDA:{},{},{},{}", line, block, branch, taken)?,
+                    None => writeln!(fmt, "BRDA:{},{},{},-", line, block, branch)?,
+                }
+            }
+            let (hit, total) = coverage.branch_counts();
+            writeln!(fmt, "BRF:{}", total)?;
+            writeln!(fmt, "BRH:{}", hit)?;
+
+            for (line, hits) in &coverage.lines {
+                writeln!(fmt, "DA:{},{}", line, hits)?;
+            }
+            let (hit, total) = coverage.line_counts();
+            writeln!(fmt, "LF:{}", total)?;
+            writeln!(fmt, "LH:{}", hit)?;
+
+            writeln!(fmt, "end_of_record")?;
+        }
+        Ok(())
+    }
+}
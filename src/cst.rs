@@ -0,0 +1,421 @@
+//! Lossless concrete syntax tree.
+//!
+//! The tree keeps every token of the source code, comments and whitespace
+//! included, so printing it gives back the source exactly. It is split in two
+//! layers, like in rowan: immutable green nodes, which only know their length
+//! and can be shared, and cheap syntax nodes on top of them, which know where
+//! they are in the source code.
+
+use std::{fmt, ops::Range, sync::Arc};
+
+use crate::{scanner::TokenType, with_stack};
+
+/// Kinds of the inner nodes of the tree. Leaves are tokens, kept with their `TokenType`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum NodeKind {
+    Program,
+
+    // Declarations and statements
+    VarDecl,
+    FunDecl,
+    ParamList,
+    Block,
+    If,
+    While,
+    For,
+    /// Condition clause of a `for` loop, without its `;`.
+    ForCondition,
+    /// Increment clause of a `for` loop.
+    ForIncrement,
+    Print,
+    Return,
+    ExprStmt,
+
+    // Expressions
+    Literal,
+    Name,
+    Paren,
+    Unary,
+    Binary,
+    Assignment,
+    Call,
+    ArgList,
+}
+
+impl NodeKind {
+    pub fn is_expr(self) -> bool {
+        use NodeKind::*;
+        matches!(
+            self,
+            Literal | Name | Paren | Unary | Binary | Assignment | Call
+        )
+    }
+}
+
+/// Whether the token is a comment or whitespace.
+pub fn is_trivia(kind: TokenType) -> bool {
+    matches!(
+        kind,
+        TokenType::Comment | TokenType::DocComment | TokenType::Whitespace
+    )
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct GreenToken {
+    kind: TokenType,
+    text: Arc<str>,
+}
+
+impl GreenToken {
+    pub fn new(kind: TokenType, text: &str) -> Self {
+        GreenToken {
+            kind,
+            text: text.into(),
+        }
+    }
+
+    pub fn kind(&self) -> TokenType {
+        self.kind
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum GreenElement {
+    Node(Arc<GreenNode>),
+    Token(GreenToken),
+}
+
+impl GreenElement {
+    pub fn text_len(&self) -> usize {
+        match self {
+            GreenElement::Node(node) => node.text_len(),
+            GreenElement::Token(token) => token.text().len(),
+        }
+    }
+}
+
+/// A node that does not know where it is, so it can be shared between trees.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct GreenNode {
+    kind: NodeKind,
+    text_len: usize,
+    children: Vec<GreenElement>,
+}
+
+/// Dropped on a native stack that grows on demand, as trees get as deep as the
+/// longest chain of operators.
+impl Drop for GreenNode {
+    fn drop(&mut self) {
+        let children = std::mem::take(&mut self.children);
+        with_stack(|| drop(children));
+    }
+}
+
+impl GreenNode {
+    pub fn new(kind: NodeKind, children: Vec<GreenElement>) -> Self {
+        GreenNode {
+            kind,
+            text_len: children.iter().map(GreenElement::text_len).sum(),
+            children,
+        }
+    }
+
+    pub fn kind(&self) -> NodeKind {
+        self.kind
+    }
+
+    pub fn text_len(&self) -> usize {
+        self.text_len
+    }
+
+    pub fn children(&self) -> &[GreenElement] {
+        &self.children
+    }
+}
+
+/// Builds a green tree from the events of a parser.
+#[derive(Debug, Default)]
+pub struct GreenNodeBuilder {
+    /// Open nodes, with the position of their first child in `children`.
+    parents: Vec<(NodeKind, usize)>,
+    children: Vec<GreenElement>,
+}
+
+/// A position in the builder where a node can be opened later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint(usize);
+
+impl GreenNodeBuilder {
+    pub fn new() -> Self {
+        GreenNodeBuilder::default()
+    }
+
+    pub fn start_node(&mut self, kind: NodeKind) {
+        self.parents.push((kind, self.children.len()));
+    }
+
+    pub fn token(&mut self, kind: TokenType, text: &str) {
+        self.children
+            .push(GreenElement::Token(GreenToken::new(kind, text)));
+    }
+
+    pub fn finish_node(&mut self) {
+        let (kind, first_child) = self.parents.pop().expect("no node to finish");
+        let children = self.children.split_off(first_child);
+        self.children
+            .push(GreenElement::Node(Arc::new(GreenNode::new(kind, children))));
+    }
+
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint(self.children.len())
+    }
+
+    /// Opens a node wrapping everything added since the checkpoint.
+    pub fn start_node_at(&mut self, checkpoint: Checkpoint, kind: NodeKind) {
+        let Checkpoint(first_child) = checkpoint;
+        assert!(
+            first_child <= self.children.len(),
+            "checkpoint is no longer valid"
+        );
+        if let Some(&(_, parent_first_child)) = self.parents.last() {
+            assert!(
+                first_child >= parent_first_child,
+                "checkpoint is before the open node"
+            );
+        }
+        self.parents.push((kind, first_child));
+    }
+
+    /// The last element added to the open node.
+    pub fn last_child(&self) -> Option<&GreenElement> {
+        let first_child = self.parents.last().map_or(0, |&(_, first)| first);
+        self.children[first_child..].last()
+    }
+
+    /// Returns the root of the tree, which must be the only element left.
+    pub fn finish(mut self) -> Arc<GreenNode> {
+        assert!(self.parents.is_empty(), "unfinished nodes");
+        match (self.children.pop(), self.children.is_empty()) {
+            (Some(GreenElement::Node(root)), true) => root,
+            _ => panic!("the tree must have a single root node"),
+        }
+    }
+}
+
+/// A green node together with its position in the source code.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SyntaxNode {
+    green: Arc<GreenNode>,
+    offset: usize,
+}
+
+/// A token together with its position in the source code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxToken {
+    green: GreenToken,
+    offset: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+impl SyntaxNode {
+    pub fn new_root(green: Arc<GreenNode>) -> Self {
+        SyntaxNode { green, offset: 0 }
+    }
+
+    pub fn kind(&self) -> NodeKind {
+        self.green.kind
+    }
+
+    pub fn green(&self) -> &Arc<GreenNode> {
+        &self.green
+    }
+
+    /// Byte range of the node in the source code, trivia inside it included.
+    pub fn text_range(&self) -> Range<usize> {
+        self.offset..self.offset + self.green.text_len
+    }
+
+    pub fn children_with_tokens(&self) -> impl Iterator<Item = SyntaxElement> + '_ {
+        let mut offset = self.offset;
+        self.green.children.iter().map(move |child| {
+            let element = SyntaxElement::new(child, offset);
+            offset += child.text_len();
+            element
+        })
+    }
+
+    pub fn children(&self) -> impl Iterator<Item = SyntaxNode> + '_ {
+        self.children_with_tokens()
+            .filter_map(SyntaxElement::into_node)
+    }
+
+    /// Tokens that are direct children of the node, without trivia.
+    pub fn tokens(&self) -> impl Iterator<Item = SyntaxToken> + '_ {
+        self.children_with_tokens()
+            .filter_map(SyntaxElement::into_token)
+            .filter(|token| !token.is_trivia())
+    }
+
+    pub fn child_token(&self, kind: TokenType) -> Option<SyntaxToken> {
+        self.tokens().find(|token| token.kind() == kind)
+    }
+
+    /// Every node and token below this node, in source order.
+    pub fn descendants_with_tokens(&self) -> impl Iterator<Item = SyntaxElement> {
+        Preorder::new(self).map(|(_, element)| element)
+    }
+
+    /// Every token of the node, trivia included, in source order.
+    pub fn descendant_tokens(&self) -> impl Iterator<Item = SyntaxToken> {
+        self.descendants_with_tokens()
+            .filter_map(SyntaxElement::into_token)
+    }
+
+    /// The source code covered by the node.
+    pub fn text(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for SyntaxNode {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.descendant_tokens()
+            .try_for_each(|token| fmt.write_str(token.text()))
+    }
+}
+
+/// Shows `Kind@start..end`, or the whole tree in the alternate form (`{:#?}`).
+impl fmt::Debug for SyntaxNode {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let range = self.text_range();
+        write!(fmt, "{:?}@{}..{}", self.kind(), range.start, range.end)?;
+        if !fmt.alternate() {
+            return Ok(());
+        }
+
+        writeln!(fmt)?;
+        for (depth, element) in Preorder::new(self) {
+            write!(fmt, "{:width$}", "", width = depth * 2)?;
+            match element {
+                SyntaxElement::Node(node) => writeln!(fmt, "{:?}", node)?,
+                SyntaxElement::Token(token) => {
+                    let range = token.text_range();
+                    writeln!(
+                        fmt,
+                        "{:?}@{}..{} {:?}",
+                        token.kind(),
+                        range.start,
+                        range.end,
+                        token.text()
+                    )?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl SyntaxToken {
+    pub fn kind(&self) -> TokenType {
+        self.green.kind
+    }
+
+    pub fn text(&self) -> &str {
+        self.green.text()
+    }
+
+    pub fn text_range(&self) -> Range<usize> {
+        self.offset..self.offset + self.green.text().len()
+    }
+
+    pub fn is_trivia(&self) -> bool {
+        is_trivia(self.kind())
+    }
+}
+
+impl SyntaxElement {
+    fn new(green: &GreenElement, offset: usize) -> Self {
+        match green {
+            GreenElement::Node(node) => SyntaxElement::Node(SyntaxNode {
+                green: node.clone(),
+                offset,
+            }),
+            GreenElement::Token(token) => SyntaxElement::Token(SyntaxToken {
+                green: token.clone(),
+                offset,
+            }),
+        }
+    }
+
+    pub fn text_range(&self) -> Range<usize> {
+        match self {
+            SyntaxElement::Node(node) => node.text_range(),
+            SyntaxElement::Token(token) => token.text_range(),
+        }
+    }
+
+    pub fn into_node(self) -> Option<SyntaxNode> {
+        match self {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None,
+        }
+    }
+
+    pub fn into_token(self) -> Option<SyntaxToken> {
+        match self {
+            SyntaxElement::Node(_) => None,
+            SyntaxElement::Token(token) => Some(token),
+        }
+    }
+}
+
+/// Walks the tree without recursion, yielding the depth of each element.
+struct Preorder {
+    /// Nodes being walked, with the offset and index of their next child.
+    stack: Vec<(Arc<GreenNode>, usize, usize)>,
+}
+
+impl Preorder {
+    fn new(root: &SyntaxNode) -> Self {
+        Preorder {
+            stack: vec![(root.green.clone(), root.offset, 0)],
+        }
+    }
+}
+
+impl Iterator for Preorder {
+    type Item = (usize, SyntaxElement);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let depth = self.stack.len();
+            let (node, offset, index) = self.stack.last_mut()?;
+            let child = match node.children.get(*index) {
+                Some(child) => child.clone(),
+                None => {
+                    self.stack.pop();
+                    continue;
+                }
+            };
+
+            let element = SyntaxElement::new(&child, *offset);
+            *offset += child.text_len();
+            *index += 1;
+
+            if let GreenElement::Node(child) = child {
+                let start = element.text_range().start;
+                self.stack.push((child, start, 0));
+            }
+            return Some((depth, element));
+        }
+    }
+}
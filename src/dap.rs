@@ -0,0 +1,439 @@
+//! Debug Adapter Protocol server, so editors can drive the debugger.
+
+use std::{
+    cell::RefCell,
+    io::{BufRead, Write},
+    rc::Rc,
+};
+
+use serde_json::{json, Value as Json};
+
+use crate::{
+    ast::Statement,
+    debugger::{Debugger, Resume, Script, Session, StopReason},
+    error::{ice, runtime_error, ErrorOrEarlyReturn, RuntimeError, ICE},
+    interpreter::Interpreter,
+    Result,
+};
+
+/// Thread of the script, the only one there is.
+const THREAD_ID: i64 = 1;
+/// Variables reference of the globals. Locals of frame `n` use `n + 1`.
+const GLOBALS: i64 = 1;
+
+/// Serves the editor connected to the standard input and output until it disconnects.
+pub fn run() -> Result<()> {
+    serve(std::io::stdin().lock(), std::io::stdout())
+}
+
+/// Serves requests read from `input`, writing responses and events to `output`.
+pub fn serve(input: impl BufRead, output: impl Write) -> Result<()> {
+    let connection = Rc::new(RefCell::new(Connection {
+        input: Box::new(input),
+        output: Box::new(output),
+        seq: 0,
+        disconnected: false,
+    }));
+
+    let mut launch: Option<Launch> = None;
+    let mut breakpoints: Vec<u32> = vec![];
+    let mut configured = false;
+
+    loop {
+        let request = match connection.borrow_mut().read()? {
+            Some(request) => request,
+            None => return Ok(()),
+        };
+
+        match request.command.as_str() {
+            "initialize" => {
+                let mut connection = connection.borrow_mut();
+                connection.respond(
+                    &request,
+                    json!({
+                        "supportsConfigurationDoneRequest": true,
+                        "supportsEvaluateForHovers": true,
+                    }),
+                )?;
+                connection.event("initialized", json!({}))?;
+            }
+            "launch" => match Launch::new(&request.arguments) {
+                Ok(program) => {
+                    launch = Some(program);
+                    connection.borrow_mut().respond(&request, json!({}))?;
+                }
+                Err(error) => connection.borrow_mut().fail(&request, &error.to_string())?,
+            },
+            "setBreakpoints" => {
+                breakpoints = requested_lines(&request.arguments);
+                let body = verify(launch.as_ref().map(|launch| &launch.script), &breakpoints);
+                connection.borrow_mut().respond(&request, body)?;
+            }
+            "configurationDone" => {
+                configured = true;
+                connection.borrow_mut().respond(&request, json!({}))?;
+            }
+            "threads" => connection.borrow_mut().respond(&request, threads())?,
+            "disconnect" => return connection.borrow_mut().respond(&request, json!({})),
+            _ => connection
+                .borrow_mut()
+                .fail(&request, "The script is not paused")?,
+        }
+
+        if let (Some(program), true) = (&launch, configured) {
+            program.run(&connection, &breakpoints)?;
+            launch = None;
+            if connection.borrow().disconnected {
+                return Ok(());
+            }
+        }
+    }
+}
+
+struct Request {
+    seq: i64,
+    command: String,
+    arguments: Json,
+}
+
+struct Connection<'io> {
+    input: Box<dyn BufRead + 'io>,
+    output: Box<dyn Write + 'io>,
+    seq: i64,
+    /// Whether the editor asked to disconnect while the script was running.
+    disconnected: bool,
+}
+
+impl Connection<'_> {
+    /// Reads the next request, or `None` at the end of the input.
+    fn read(&mut self) -> Result<Option<Request>> {
+        let mut length = None;
+        loop {
+            let mut header = String::new();
+            if self.input.read_line(&mut header)? == 0 {
+                return Ok(None);
+            }
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some(value) = header.strip_prefix("Content-Length:") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+
+        let length = length.ok_or_else(|| protocol_error("missing Content-Length"))?;
+        let mut content = vec![0; length];
+        self.input.read_exact(&mut content)?;
+
+        let message: Json = serde_json::from_slice(&content).map_err(protocol_error)?;
+        Ok(Some(Request {
+            seq: message["seq"].as_i64().unwrap_or_default(),
+            command: message["command"].as_str().unwrap_or_default().into(),
+            arguments: message["arguments"].clone(),
+        }))
+    }
+
+    fn send(&mut self, mut message: Json) -> Result<()> {
+        self.seq += 1;
+        message["seq"] = self.seq.into();
+        let content = message.to_string();
+        write!(
+            self.output,
+            "Content-Length: {}\r\n\r\n{}",
+            content.len(),
+            content
+        )?;
+        self.output.flush()?;
+        Ok(())
+    }
+
+    fn respond(&mut self, request: &Request, body: Json) -> Result<()> {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request.seq,
+            "success": true,
+            "command": request.command,
+            "body": body,
+        }))
+    }
+
+    fn fail(&mut self, request: &Request, message: &str) -> Result<()> {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request.seq,
+            "success": false,
+            "command": request.command,
+            "message": message,
+        }))
+    }
+
+    fn event(&mut self, event: &str, body: Json) -> Result<()> {
+        self.send(json!({
+            "type": "event",
+            "event": event,
+            "body": body,
+        }))
+    }
+}
+
+fn protocol_error(error: impl std::fmt::Display) -> ErrorOrEarlyReturn {
+    ice(ICE::Generic(format!(
+        "Debug adapter protocol error: {}",
+        error
+    )))
+}
+
+/// The script to debug, as given in the `launch` request.
+struct Launch {
+    path: String,
+    script: Script,
+    args: Vec<String>,
+    stop_on_entry: bool,
+}
+
+impl Launch {
+    fn new(arguments: &Json) -> Result<Launch> {
+        let path = arguments["program"]
+            .as_str()
+            .ok_or_else(|| protocol_error("missing the program to launch"))?;
+        let src = std::fs::read_to_string(path)?;
+
+        Ok(Launch {
+            path: path.into(),
+            script: Script::compile(&src)?,
+            args: arguments["args"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|arg| arg.as_str().map(String::from))
+                .collect(),
+            stop_on_entry: arguments["stopOnEntry"].as_bool().unwrap_or_default(),
+        })
+    }
+
+    /// Runs the script until it ends, handling the requests made while it is paused.
+    fn run(&self, connection: &Rc<RefCell<Connection>>, breakpoints: &[u32]) -> Result<()> {
+        let mut output = Output {
+            connection: connection.clone(),
+        };
+        let mut adapter = Adapter {
+            launch: self,
+            session: Session::new(self.stop_on_entry),
+            connection: connection.clone(),
+        };
+        adapter.session.set_breakpoints(breakpoints.iter().copied());
+
+        let result = {
+            let mut interpreter = Interpreter::new(&mut output);
+            interpreter.set_args(self.args.clone());
+            interpreter.set_debugger(&mut adapter);
+            self.script.run(&mut interpreter)
+        };
+
+        let mut connection = connection.borrow_mut();
+        let exit_code = match result {
+            Ok(()) => 0,
+            Err(ErrorOrEarlyReturn::RuntimeError(RuntimeError::Terminated)) => 0,
+            Err(error) => {
+                let message = format!("Error: {}\n", error);
+                connection.event("output", json!({ "category": "stderr", "output": message }))?;
+                error.exit_code()
+            }
+        };
+        if !connection.disconnected {
+            connection.event("exited", json!({ "exitCode": exit_code }))?;
+            connection.event("terminated", json!({}))?;
+        }
+        Ok(())
+    }
+}
+
+/// Sends what the script prints as output events.
+struct Output<'io> {
+    connection: Rc<RefCell<Connection<'io>>>,
+}
+
+impl Write for Output<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let output = String::from_utf8_lossy(buf);
+        self.connection
+            .borrow_mut()
+            .event("output", json!({ "category": "stdout", "output": output }))
+            .map_err(|error| std::io::Error::other(error.to_string()))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Debugger answering the requests of the editor while the script is paused.
+struct Adapter<'launch, 'io> {
+    launch: &'launch Launch,
+    session: Session,
+    connection: Rc<RefCell<Connection<'io>>>,
+}
+
+impl Adapter<'_, '_> {
+    fn pause(&mut self, interpreter: &mut Interpreter, reason: StopReason) -> Result<()> {
+        let reason = match reason {
+            StopReason::Entry => "entry",
+            StopReason::Breakpoint => "breakpoint",
+            StopReason::Step => "step",
+        };
+        self.connection.borrow_mut().event(
+            "stopped",
+            json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true }),
+        )?;
+
+        loop {
+            let request = match self.connection.borrow_mut().read()? {
+                Some(request) => request,
+                None => return Err(runtime_error(RuntimeError::Terminated)),
+            };
+
+            let resume = match request.command.as_str() {
+                "continue" => Resume::Continue,
+                "next" => Resume::StepOver,
+                "stepIn" => Resume::StepIn,
+                "stepOut" => Resume::StepOut,
+                "disconnect" => {
+                    let mut connection = self.connection.borrow_mut();
+                    connection.disconnected = true;
+                    connection.respond(&request, json!({}))?;
+                    return Err(runtime_error(RuntimeError::Terminated));
+                }
+                _ => {
+                    match self.inspect(interpreter, &request) {
+                        Ok(body) => self.connection.borrow_mut().respond(&request, body)?,
+                        Err(error) => self
+                            .connection
+                            .borrow_mut()
+                            .fail(&request, &error.to_string())?,
+                    }
+                    continue;
+                }
+            };
+
+            let body = match resume {
+                Resume::Continue => json!({ "allThreadsContinued": true }),
+                _ => json!({}),
+            };
+            self.connection.borrow_mut().respond(&request, body)?;
+            self.session.resume(resume);
+            return Ok(());
+        }
+    }
+
+    /// Answers a request that does not resume the script.
+    fn inspect(&mut self, interpreter: &mut Interpreter, request: &Request) -> Result<Json> {
+        let arguments = &request.arguments;
+        let frames = self.session.frames();
+        // frames are numbered from 1, the script first
+        let frame = |id: Option<i64>| match id {
+            Some(id) if id >= 1 && (id as usize) <= frames.len() => Ok(id as usize - 1),
+            Some(id) => Err(protocol_error(format!("unknown frame {}", id))),
+            None => Ok(frames.len().saturating_sub(1)),
+        };
+
+        match request.command.as_str() {
+            "threads" => Ok(threads()),
+            "stackTrace" => {
+                let stack_frames: Vec<Json> = frames
+                    .iter()
+                    .enumerate()
+                    .rev()
+                    .map(|(index, frame)| {
+                        json!({
+                            "id": index + 1,
+                            "name": frame.name,
+                            "line": frame.line,
+                            "column": 1,
+                            "source": { "path": self.launch.path },
+                        })
+                    })
+                    .collect();
+                Ok(json!({ "stackFrames": stack_frames, "totalFrames": frames.len() }))
+            }
+            "scopes" => {
+                let id = frame(arguments["frameId"].as_i64())? as i64 + 1;
+                Ok(json!({ "scopes": [
+                    { "name": "Locals", "variablesReference": id + GLOBALS, "expensive": false },
+                    { "name": "Globals", "variablesReference": GLOBALS, "expensive": false },
+                ]}))
+            }
+            "variables" => {
+                let variables = match arguments["variablesReference"].as_i64() {
+                    Some(GLOBALS) => self.session.globals(interpreter),
+                    reference => {
+                        let frame = frame(reference.map(|reference| reference - GLOBALS))?;
+                        self.session.locals(interpreter, frame)
+                    }
+                };
+                let variables: Vec<Json> = variables
+                    .into_iter()
+                    .map(|(name, value)| {
+                        json!({ "name": name, "value": value.to_string(), "variablesReference": 0 })
+                    })
+                    .collect();
+                Ok(json!({ "variables": variables }))
+            }
+            "evaluate" => {
+                let frame = frame(arguments["frameId"].as_i64())?;
+                let expression = arguments["expression"].as_str().unwrap_or_default();
+                let value = self.session.evaluate(interpreter, frame, expression)?;
+                Ok(json!({ "result": value.to_string(), "variablesReference": 0 }))
+            }
+            "setBreakpoints" => {
+                let lines = requested_lines(arguments);
+                self.session.set_breakpoints(lines.iter().copied());
+                Ok(verify(Some(&self.launch.script), &lines))
+            }
+            _ => Err(protocol_error(format!(
+                "unsupported request '{}'",
+                request.command
+            ))),
+        }
+    }
+}
+
+impl Debugger for Adapter<'_, '_> {
+    fn before_stmt(&mut self, interpreter: &mut Interpreter<'_>, stmt: &Statement) -> Result<()> {
+        match self
+            .session
+            .check(interpreter, self.launch.script.line(stmt))
+        {
+            Some(reason) => self.pause(interpreter, reason),
+            None => Ok(()),
+        }
+    }
+}
+
+fn threads() -> Json {
+    json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] })
+}
+
+fn requested_lines(arguments: &Json) -> Vec<u32> {
+    arguments["breakpoints"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|breakpoint| breakpoint["line"].as_u64())
+        .map(|line| line as u32)
+        .collect()
+}
+
+/// Breakpoints are verified when there is a statement in their line.
+fn verify(script: Option<&Script>, lines: &[u32]) -> Json {
+    let valid = script.map(Script::lines);
+    let breakpoints: Vec<Json> = lines
+        .iter()
+        .map(|line| {
+            let verified = valid.as_ref().is_none_or(|valid| valid.contains(line));
+            json!({ "verified": verified, "line": line })
+        })
+        .collect();
+    json!({ "breakpoints": breakpoints })
+}
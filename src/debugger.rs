@@ -0,0 +1,586 @@
+//! Step debugger: breakpoints, stepping and inspection of a paused script.
+
+use std::{
+    collections::BTreeSet,
+    io::{BufRead, Write},
+    sync::Arc,
+};
+
+use crate::{
+    ast::{
+        BinaryExpr, FunctionDecl, IfStatement, NodeId, NodeMap, Statement, StatementKind,
+        WhileStatement,
+    },
+    cst::{NodeKind, SyntaxElement, SyntaxNode},
+    environment::{Env, NativeFunction, Value},
+    error::{runtime_error, RuntimeError},
+    interpreter::Interpreter,
+    lower, parser,
+    scanner::{self, Backend, Scanner, TokenType},
+    with_stack, Result,
+};
+
+/// Hooks called by the interpreter while it runs. Returning an error stops the
+/// execution of the script with it.
+pub trait Debugger {
+    /// Called before every statement.
+    fn before_stmt(&mut self, interpreter: &mut Interpreter<'_>, stmt: &Statement) -> Result<()>;
+
+    /// Called when a function is called, with the depth of the caller.
+    fn on_call(
+        &mut self,
+        _interpreter: &Interpreter<'_>,
+        _function: Callee,
+        _args: &[Value],
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called when a function returns normally, with the depth of the caller.
+    fn on_return(
+        &mut self,
+        _interpreter: &Interpreter<'_>,
+        _function: Callee,
+        _value: &Value,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called when a condition decides which way execution goes.
+    fn on_branch(
+        &mut self,
+        _interpreter: &Interpreter<'_>,
+        _branch: Branch,
+        _taken: bool,
+    ) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Where execution can go one of two ways.
+#[derive(Debug, Clone, Copy)]
+pub enum Branch<'a> {
+    /// `taken` is whether the `then` branch runs.
+    If(NodeId, &'a IfStatement),
+    /// `taken` is whether the body runs once more.
+    While(NodeId, &'a WhileStatement),
+    /// `and` or `or`, `taken` is whether the left operand is truthy.
+    Logical(NodeId, &'a BinaryExpr),
+}
+
+impl Branch<'_> {
+    /// The statement or expression making the branch.
+    pub fn id(&self) -> NodeId {
+        match *self {
+            Branch::If(id, _) | Branch::While(id, _) | Branch::Logical(id, _) => id,
+        }
+    }
+}
+
+/// A function being called.
+#[derive(Debug, Clone, Copy)]
+pub enum Callee<'a> {
+    Function(&'a Arc<FunctionDecl>),
+    Native(&'a NativeFunction),
+}
+
+impl Callee<'_> {
+    pub fn name(&self) -> &str {
+        match self {
+            Callee::Function(decl) => &decl.name,
+            Callee::Native(native) => native.name,
+        }
+    }
+}
+
+/// A program together with the lines of its statements.
+pub struct Script {
+    statements: Vec<Statement>,
+    lines: NodeMap<u32>,
+    /// Lines where functions are declared.
+    functions: NodeMap<u32>,
+    source: Vec<String>,
+}
+
+impl Script {
+    pub fn compile(src: &str) -> Result<Script> {
+        Self::compile_with(Backend::default().scanner(), src)
+    }
+
+    /// Compiles the script with the tokens of `scanner`.
+    pub fn compile_with(scanner: &dyn Scanner, src: &str) -> Result<Script> {
+        let root = parser::parse_cst(&scanner.scan_tokens_lossless(src)?)?;
+        let mut script = Script {
+            statements: lower::program(&root)?,
+            lines: NodeMap::new(),
+            functions: NodeMap::new(),
+            source: src.lines().map(String::from).collect(),
+        };
+
+        let line_starts: Vec<usize> = std::iter::once(0)
+            .chain(src.match_indices('\n').map(|(offset, _)| offset + 1))
+            .collect();
+        let mut lines = NodeMap::new();
+        let mut functions = NodeMap::new();
+        let mut recorder = LineRecorder {
+            lines: &mut lines,
+            functions: &mut functions,
+            line_starts: &line_starts,
+        };
+        for (stmt, node) in script.statements.iter().zip(root.children()) {
+            recorder.statement(stmt, &node);
+        }
+        script.lines = lines;
+        script.functions = functions;
+
+        Ok(script)
+    }
+
+    pub fn statements(&self) -> &[Statement] {
+        &self.statements
+    }
+
+    /// Line of the statement, if execution may pause before it.
+    pub fn line(&self, stmt: &Statement) -> Option<u32> {
+        self.lines.get(&stmt.id).copied()
+    }
+
+    /// Line where the function is declared.
+    pub fn function_line(&self, decl: &FunctionDecl) -> Option<u32> {
+        self.functions.get(&decl.id).copied()
+    }
+
+    /// Lines where execution may pause, so breakpoints can be set.
+    pub fn lines(&self) -> BTreeSet<u32> {
+        self.lines.values().copied().collect()
+    }
+
+    /// Source code of the line, starting at 1.
+    pub fn source_line(&self, line: u32) -> Option<&str> {
+        self.source
+            .get((line as usize).checked_sub(1)?)
+            .map(String::as_str)
+    }
+
+    pub fn run(&self, interpreter: &mut Interpreter) -> Result<()> {
+        for stmt in &self.statements {
+            interpreter.exec_stmt(stmt)?;
+        }
+        Ok(())
+    }
+}
+
+/// Walks the statements together with the syntax nodes they came from.
+struct LineRecorder<'a> {
+    lines: &'a mut NodeMap<u32>,
+    functions: &'a mut NodeMap<u32>,
+    line_starts: &'a [usize],
+}
+
+impl LineRecorder<'_> {
+    fn statement(&mut self, stmt: &Statement, node: &SyntaxNode) {
+        with_stack(|| {
+            let line = self
+                .line_starts
+                .partition_point(|&start| start <= node.text_range().start);
+            let line = line as u32;
+            let children: Vec<SyntaxNode> = node.children().collect();
+
+            match (&stmt.kind, node.kind()) {
+                // blocks only hold other statements, so there is nothing to pause at
+                (StatementKind::Block(statements), NodeKind::Block) => {
+                    for (stmt, node) in statements.iter().zip(&children) {
+                        self.statement(stmt, node);
+                    }
+                }
+                (StatementKind::Block(statements), NodeKind::For) => {
+                    self.for_stmt(statements, node, line)
+                }
+                (StatementKind::If(if_stmt), NodeKind::If) => {
+                    self.record(stmt, line);
+                    if let Some(node) = children.get(1) {
+                        self.statement(&if_stmt.then_branch, node);
+                    }
+                    if let (Some(else_branch), Some(node)) = (&if_stmt.else_branch, children.get(2))
+                    {
+                        self.statement(else_branch, node);
+                    }
+                }
+                (StatementKind::While(while_stmt), NodeKind::While) => {
+                    self.record(stmt, line);
+                    if let Some(node) = children.get(1) {
+                        self.statement(&while_stmt.stmt, node);
+                    }
+                }
+                (StatementKind::FunDecl(function), NodeKind::FunDecl) => {
+                    self.record(stmt, line);
+                    self.functions.insert(function.id, line);
+                    if let Some(body) = children
+                        .iter()
+                        .find(|child| child.kind() == NodeKind::Block)
+                    {
+                        for (stmt, node) in function.body.iter().zip(body.children()) {
+                            self.statement(stmt, &node);
+                        }
+                    }
+                }
+                _ => self.record(stmt, line),
+            }
+        })
+    }
+
+    /// A `for` loop is lowered into a block with its initialization and a `while` loop.
+    fn for_stmt(&mut self, statements: &[Statement], node: &SyntaxNode, line: u32) {
+        let mut initialization = None;
+        let mut body = None;
+        let mut after_clauses = false;
+        for element in node.children_with_tokens() {
+            match element {
+                SyntaxElement::Token(token) => {
+                    after_clauses = after_clauses || token.kind() == TokenType::RightParen
+                }
+                SyntaxElement::Node(child) if after_clauses => body = Some(child),
+                SyntaxElement::Node(child)
+                    if !matches!(
+                        child.kind(),
+                        NodeKind::ForCondition | NodeKind::ForIncrement
+                    ) =>
+                {
+                    initialization = Some(child)
+                }
+                SyntaxElement::Node(_) => (),
+            }
+        }
+
+        for stmt in statements {
+            match (&stmt.kind, &initialization) {
+                (StatementKind::While(while_stmt), _) => {
+                    self.record(stmt, line);
+                    if let (StatementKind::Block(loop_body), Some(body)) =
+                        (&while_stmt.stmt.kind, &body)
+                    {
+                        if let Some(stmt) = loop_body.first() {
+                            self.statement(stmt, body);
+                        }
+                    }
+                }
+                (_, Some(initialization)) => self.statement(stmt, initialization),
+                _ => (),
+            }
+        }
+    }
+
+    fn record(&mut self, stmt: &Statement, line: u32) {
+        self.lines.insert(stmt.id, line);
+    }
+}
+
+/// Why execution paused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    Entry,
+    Breakpoint,
+    Step,
+}
+
+/// How execution goes on after a pause.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    Continue,
+    StepIn,
+    StepOver,
+    StepOut,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    Entry,
+    Continue,
+    In,
+    /// Pauses at a call depth up to this one.
+    Over(usize),
+    /// Pauses at a call depth below this one.
+    Out(usize),
+}
+
+/// A function being executed, or the script itself.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub name: String,
+    /// Line of the statement being executed.
+    pub line: u32,
+    pub env: Env,
+}
+
+/// Decides when to pause, and keeps what is needed to inspect the paused script.
+pub struct Session {
+    breakpoints: BTreeSet<u32>,
+    step: Step,
+    /// The script first, the innermost function last.
+    frames: Vec<Frame>,
+}
+
+impl Session {
+    pub fn new(stop_on_entry: bool) -> Self {
+        Session {
+            breakpoints: BTreeSet::new(),
+            step: if stop_on_entry {
+                Step::Entry
+            } else {
+                Step::Continue
+            },
+            frames: vec![],
+        }
+    }
+
+    pub fn breakpoints(&self) -> &BTreeSet<u32> {
+        &self.breakpoints
+    }
+
+    pub fn set_breakpoints(&mut self, lines: impl IntoIterator<Item = u32>) {
+        self.breakpoints = lines.into_iter().collect();
+    }
+
+    pub fn add_breakpoint(&mut self, line: u32) {
+        self.breakpoints.insert(line);
+    }
+
+    pub fn remove_breakpoint(&mut self, line: u32) -> bool {
+        self.breakpoints.remove(&line)
+    }
+
+    /// Tells whether to pause before the statement, which is at `line` if it has one.
+    pub fn check(&mut self, interpreter: &Interpreter, line: Option<u32>) -> Option<StopReason> {
+        let line = line?;
+        let depth = interpreter.call_depth();
+
+        let name = match depth {
+            0 => "<script>".into(),
+            _ => interpreter.calls()[depth - 1].name.clone(),
+        };
+        self.frames.truncate(depth);
+        self.frames.push(Frame {
+            name,
+            line,
+            env: interpreter.current_env(),
+        });
+
+        let reason = match self.step {
+            Step::Entry => Some(StopReason::Entry),
+            Step::In => Some(StopReason::Step),
+            Step::Over(max) if depth <= max => Some(StopReason::Step),
+            Step::Out(max) if depth < max => Some(StopReason::Step),
+            _ => None,
+        };
+        if self.breakpoints.contains(&line) {
+            Some(StopReason::Breakpoint)
+        } else {
+            reason
+        }
+    }
+
+    pub fn resume(&mut self, resume: Resume) {
+        let depth = self.frames.len().saturating_sub(1);
+        self.step = match resume {
+            Resume::Continue => Step::Continue,
+            Resume::StepIn => Step::In,
+            Resume::StepOver => Step::Over(depth),
+            Resume::StepOut => Step::Out(depth),
+        };
+    }
+
+    /// Frames of the paused script, the script first.
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    /// Variables visible in the frame, not counting the globals, the innermost first.
+    pub fn locals(&self, interpreter: &Interpreter, frame: usize) -> Vec<(String, Value)> {
+        let global_env = interpreter.global_env();
+        let mut env = self.frames.get(frame).map(|frame| frame.env.clone());
+        let mut locals: Vec<(String, Value)> = vec![];
+
+        while let Some(current) = env {
+            if std::rc::Rc::ptr_eq(&current, &global_env) {
+                break;
+            }
+            let mut values = variables(&current);
+            values.retain(|(name, _)| locals.iter().all(|(shadowing, _)| shadowing != name));
+            locals.extend(values);
+            env = current.borrow().parent.clone();
+        }
+
+        locals
+    }
+
+    /// Globals defined by the script.
+    pub fn globals(&self, interpreter: &Interpreter) -> Vec<(String, Value)> {
+        let mut globals = variables(&interpreter.global_env());
+        globals.retain(|(_, value)| !matches!(value, Value::NativeFunction(_)));
+        globals
+    }
+
+    /// Evaluates an expression in the environment of the frame.
+    pub fn evaluate(
+        &self,
+        interpreter: &mut Interpreter,
+        frame: usize,
+        src: &str,
+    ) -> Result<Value> {
+        let expr = parser::parse_expr(&scanner::scan_tokens(src)?)?;
+        match self.frames.get(frame) {
+            Some(frame) => interpreter.calc_expr_in(frame.env.clone(), &expr),
+            None => interpreter.calc_expr(&expr),
+        }
+    }
+}
+
+/// Variables of one environment, sorted by name.
+fn variables(env: &Env) -> Vec<(String, Value)> {
+    let mut values: Vec<(String, Value)> = env
+        .borrow()
+        .values()
+        .map(|(name, value)| (name.to_string(), value.clone()))
+        .collect();
+    values.sort_by(|(a, _), (b, _)| a.cmp(b));
+    values
+}
+
+const HELP: &str = "\
+Commands:
+  break N, b N      pause at line N
+  delete N, d N     remove the breakpoint at line N
+  continue, c       run until the next breakpoint
+  step, s           run until the next statement, entering calls
+  next, n           run until the next statement in this function
+  finish, f         run until the current function returns
+  backtrace, bt     show the functions being called
+  locals            show the local variables
+  globals           show the global variables
+  print E, p E      evaluate the expression E
+  quit, q           stop the script
+  help, h           show this help";
+
+/// Debugger driven by commands typed in a terminal.
+pub struct Console<'script, R, W> {
+    script: &'script Script,
+    session: Session,
+    input: R,
+    output: W,
+}
+
+impl<'script, R: BufRead, W: Write> Console<'script, R, W> {
+    /// The console pauses before the first statement, so breakpoints can be set.
+    pub fn new(script: &'script Script, input: R, output: W) -> Self {
+        Console {
+            script,
+            session: Session::new(true),
+            input,
+            output,
+        }
+    }
+
+    fn pause(&mut self, interpreter: &mut Interpreter, reason: StopReason) -> Result<()> {
+        let line = self.session.frames().last().map_or(0, |frame| frame.line);
+        let reason = match reason {
+            StopReason::Entry => "entry",
+            StopReason::Breakpoint => "breakpoint",
+            StopReason::Step => "step",
+        };
+        writeln!(
+            self.output,
+            "Paused at line {} ({}): {}",
+            line,
+            reason,
+            self.script.source_line(line).unwrap_or_default().trim()
+        )?;
+
+        loop {
+            write!(self.output, "(debug) ")?;
+            self.output.flush()?;
+
+            let mut command = String::new();
+            if self.input.read_line(&mut command)? == 0 {
+                return Err(runtime_error(RuntimeError::Terminated));
+            }
+            let (command, argument) = match command.trim().split_once(' ') {
+                Some((command, argument)) => (command, argument.trim()),
+                None => (command.trim(), ""),
+            };
+
+            let resume = match command {
+                "continue" | "c" => Resume::Continue,
+                "step" | "s" => Resume::StepIn,
+                "next" | "n" => Resume::StepOver,
+                "finish" | "f" => Resume::StepOut,
+                "quit" | "q" => return Err(runtime_error(RuntimeError::Terminated)),
+                _ => {
+                    self.command(interpreter, command, argument)?;
+                    continue;
+                }
+            };
+            self.session.resume(resume);
+            return Ok(());
+        }
+    }
+
+    /// Runs a command that does not resume the script.
+    fn command(
+        &mut self,
+        interpreter: &mut Interpreter,
+        command: &str,
+        argument: &str,
+    ) -> Result<()> {
+        let frame = self.session.frames().len().saturating_sub(1);
+        match command {
+            "break" | "b" | "delete" | "d" => {
+                let line = match argument.parse::<u32>() {
+                    Ok(line) => line,
+                    Err(_) => return Ok(writeln!(self.output, "Expected a line number")?),
+                };
+                if matches!(command, "delete" | "d") {
+                    if !self.session.remove_breakpoint(line) {
+                        writeln!(self.output, "No breakpoint at line {}", line)?;
+                    }
+                } else if self.script.lines().contains(&line) {
+                    self.session.add_breakpoint(line);
+                    writeln!(self.output, "Breakpoint at line {}", line)?;
+                } else {
+                    writeln!(self.output, "No statement at line {}", line)?;
+                }
+            }
+            "backtrace" | "bt" => {
+                for frame in self.session.frames().iter().rev() {
+                    writeln!(self.output, "  {} at line {}", frame.name, frame.line)?;
+                }
+            }
+            "locals" => {
+                for (name, value) in self.session.locals(interpreter, frame) {
+                    writeln!(self.output, "  {} = {}", name, value)?;
+                }
+            }
+            "globals" => {
+                for (name, value) in self.session.globals(interpreter) {
+                    writeln!(self.output, "  {} = {}", name, value)?;
+                }
+            }
+            "print" | "p" => match self.session.evaluate(interpreter, frame, argument) {
+                Ok(value) => writeln!(self.output, "{}", value)?,
+                Err(error) => writeln!(self.output, "Error: {}", error)?,
+            },
+            "help" | "h" => writeln!(self.output, "{}", HELP)?,
+            "" => (),
+            _ => writeln!(self.output, "Unknown command '{}', try 'help'", command)?,
+        }
+        Ok(())
+    }
+}
+
+impl<R: BufRead, W: Write> Debugger for Console<'_, R, W> {
+    fn before_stmt(&mut self, interpreter: &mut Interpreter<'_>, stmt: &Statement) -> Result<()> {
+        match self.session.check(interpreter, self.script.line(stmt)) {
+            Some(reason) => self.pause(interpreter, reason),
+            None => Ok(()),
+        }
+    }
+}
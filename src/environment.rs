@@ -1,8 +1,9 @@
-use std::{cell::RefCell, collections::HashMap, fmt::Display, rc::Rc};
+use std::{cell::RefCell, collections::HashMap, fmt::Display, rc::Rc, sync::Arc};
 
 use crate::{
     ast::FunctionDecl,
     error::{runtime_error, RuntimeError},
+    native::Host,
     Result,
 };
 
@@ -19,14 +20,15 @@ pub enum Value {
 
 #[derive(Debug, Clone)]
 pub struct Function {
-    pub ast: Rc<FunctionDecl>,
+    pub ast: Arc<FunctionDecl>,
     pub closure: Env,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, Clone)]
 pub struct NativeFunction {
     pub name: &'static str,
-    pub exec: fn(Env) -> Result<Value>,
+    pub arity: usize,
+    pub exec: fn(&mut Host, &[Value]) -> Result<Value>,
 }
 
 pub type Env = Rc<RefCell<Environment>>;
@@ -56,6 +58,13 @@ impl Environment {
         }
     }
 
+    /// The values defined in this environment, not including its parents.
+    pub fn values(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.values
+            .iter()
+            .map(|(name, value)| (name.as_str(), value))
+    }
+
     pub fn assign(&mut self, name: &str, value: Value) -> Result<()> {
         match self.values.get_mut(name) {
             Some(dest) => *dest = value,
@@ -87,3 +96,9 @@ impl PartialEq for Function {
         self.ast == other.ast && self.closure.as_ptr() == other.closure.as_ptr()
     }
 }
+
+impl PartialEq for NativeFunction {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
@@ -26,6 +26,8 @@ pub enum CompilationError {
     ExpectedToken(String, String),
     #[error("Unterminated string")]
     UnterminatedString,
+    #[error("Unterminated block comment, opened at line {0}")]
+    UnterminatedComment(u32),
     #[error("Invalid {0} literal {1}")]
     InvalidLiteral(String, String),
     #[error("Expected a variable name after 'var'")]
@@ -34,6 +36,8 @@ pub enum CompilationError {
     ExpectedSemicolonAfterVarDecl,
     #[error("Return statement must be inside a function.")]
     ReturnOutsideFunction,
+    #[error("Code is nested too deeply. The limit is {0} levels.")]
+    TooDeeplyNested(usize),
 }
 
 /// Internal Compiler Error
@@ -61,8 +65,38 @@ pub enum RuntimeError {
     InvalidOperator(TokenType, String, String),
     #[error("Expected {0} arguments in call for {1}, but got {2}.")]
     NumberOfArgumentsMismatch(usize, String, usize),
-    #[error("Stack overflow")]
-    StackOverflow,
+    #[error("Stack overflow: call depth {0} exceeds the limit when entering '{1}'")]
+    StackOverflow(usize, String),
+    #[error("Execution was terminated by the debugger")]
+    Terminated,
+    #[error("Interrupted")]
+    Interrupted,
+}
+
+/// Exit codes of the `rlox` binary, following the conventions of `sysexits.h`.
+pub mod exit_code {
+    /// The command line was used incorrectly.
+    pub const USAGE: u8 = 64;
+    /// The script has a compilation error.
+    pub const DATA_ERROR: u8 = 65;
+    /// The script could not be read.
+    pub const NO_INPUT: u8 = 66;
+    /// The script failed at runtime, or the interpreter itself failed.
+    pub const SOFTWARE: u8 = 70;
+    /// Reading from or writing to the terminal failed.
+    pub const IO_ERROR: u8 = 74;
+}
+
+impl ErrorOrEarlyReturn {
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            ErrorOrEarlyReturn::CompilationError(_) => exit_code::DATA_ERROR,
+            ErrorOrEarlyReturn::ICE(ICE::IOError(_)) => exit_code::IO_ERROR,
+            ErrorOrEarlyReturn::ICE(_)
+            | ErrorOrEarlyReturn::RuntimeError(_)
+            | ErrorOrEarlyReturn::EarlyReturn(_) => exit_code::SOFTWARE,
+        }
+    }
 }
 
 pub fn ice(kind: ICE) -> ErrorOrEarlyReturn {
@@ -0,0 +1,306 @@
+use crate::{
+    parser,
+    scanner::{self, Token, TokenType, TriviaToken},
+    Result,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormatOptions {
+    /// Lines longer than this are wrapped, where possible.
+    pub width: usize,
+    /// Number of spaces per indentation level.
+    pub indent: usize,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            width: 80,
+            indent: 4,
+        }
+    }
+}
+
+/// Formats the source code, keeping its comments.
+///
+/// Code that does not parse is rejected instead of being formatted.
+pub fn format(src: &str, options: &FormatOptions) -> Result<String> {
+    parser::parse(&scanner::scan_tokens(src)?)?;
+
+    let mut formatter = Formatter::new(options);
+    for token in scanner::scan_tokens_with_trivia(src)? {
+        formatter.token(&token);
+    }
+
+    Ok(formatter.finish())
+}
+
+/// Tells whether the source code is already formatted.
+pub fn is_formatted(src: &str, options: &FormatOptions) -> Result<bool> {
+    Ok(format(src, options)? == src)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Separator {
+    Nothing,
+    Space,
+    NewLine,
+}
+
+struct Formatter<'options> {
+    options: &'options FormatOptions,
+    out: String,
+    /// The line being built, without its indentation.
+    line: String,
+    /// Indentation level of the block of the line being built.
+    line_indent: usize,
+    /// Whether the line being built continues a statement.
+    line_continues: bool,
+    /// Positions of spaces in `line` where it may be wrapped.
+    breaks: Vec<usize>,
+    /// Whether the next line continues a statement started in a previous line.
+    continuation: bool,
+    indent: usize,
+    parens: usize,
+    previous: Option<TokenType>,
+    previous_is_unary: bool,
+}
+
+impl<'options> Formatter<'options> {
+    fn new(options: &'options FormatOptions) -> Self {
+        Formatter {
+            options,
+            out: String::new(),
+            line: String::new(),
+            line_indent: 0,
+            line_continues: false,
+            breaks: vec![],
+            continuation: false,
+            indent: 0,
+            parens: 0,
+            previous: None,
+            previous_is_unary: false,
+        }
+    }
+
+    fn token(&mut self, token: &TriviaToken) {
+        self.leading_trivia(token);
+
+        if token.token.type_ != TokenType::Eof {
+            self.significant_token(&token.token);
+        }
+
+        for trivia in &token.trailing {
+            if matches!(trivia.type_, TokenType::Comment | TokenType::DocComment) {
+                self.push(" ");
+                self.push(trivia.lexeme.trim_end());
+                let continuation = self.separator(None) != Separator::NewLine;
+                self.end_line(continuation);
+            }
+        }
+    }
+
+    fn leading_trivia(&mut self, token: &TriviaToken) {
+        let mut new_lines = 0;
+        // the line break right after a block comment ends its line
+        let mut block_comment = false;
+
+        for trivia in &token.leading {
+            match trivia.type_ {
+                TokenType::Whitespace if trivia.lexeme == "\n" && block_comment => {
+                    block_comment = false
+                }
+                TokenType::Whitespace if trivia.lexeme == "\n" => new_lines += 1,
+                TokenType::Comment | TokenType::DocComment => {
+                    // a line comment has its own line break
+                    let blank_line = new_lines > 0 && self.previous.is_some();
+                    new_lines = 0;
+                    block_comment = !trivia.lexeme.ends_with('\n');
+
+                    if !self.line.is_empty() {
+                        let continuation = self.separator(None) != Separator::NewLine;
+                        self.end_line(continuation);
+                    }
+                    if blank_line && self.previous != Some(TokenType::LeftBrace) {
+                        self.blank_line();
+                    }
+                    self.push(trivia.lexeme.trim_end());
+                    let continuation = self.continuation;
+                    self.end_line(continuation);
+                }
+                _ => (),
+            }
+        }
+
+        // the line break of the previous line is in its trailing trivia, so any
+        // other one makes a blank line, which is kept unless it opens or closes a block
+        if new_lines > 0
+            && self.previous.is_some()
+            && self.previous != Some(TokenType::LeftBrace)
+            && !matches!(token.token.type_, TokenType::RightBrace | TokenType::Eof)
+            && self.separator(Some(token.token.type_)) == Separator::NewLine
+        {
+            if !self.line.is_empty() {
+                self.end_line(false);
+            }
+            self.blank_line();
+        }
+    }
+
+    fn significant_token(&mut self, token: &Token) {
+        use TokenType::*;
+
+        if token.type_ == RightBrace {
+            self.indent = self.indent.saturating_sub(1);
+        }
+
+        match self.separator(Some(token.type_)) {
+            Separator::NewLine if !self.line.is_empty() => self.end_line(false),
+            Separator::Space if !self.line.is_empty() => {
+                if self
+                    .previous
+                    .is_some_and(|previous| self.may_break_after(previous))
+                {
+                    self.breaks.push(self.line.len());
+                }
+                self.push(" ");
+            }
+            _ => (),
+        }
+
+        let is_unary = matches!(token.type_, Minus | Bang) && !self.after_operand();
+
+        self.push(token.lexeme);
+        self.previous = Some(token.type_);
+        self.previous_is_unary = is_unary;
+
+        match token.type_ {
+            LeftBrace => self.indent += 1,
+            LeftParen => self.parens += 1,
+            RightParen => self.parens = self.parens.saturating_sub(1),
+            _ => (),
+        }
+    }
+
+    /// What goes between the previous token and the next one.
+    fn separator(&self, next: Option<TokenType>) -> Separator {
+        use Separator::*;
+        use TokenType::*;
+
+        let previous = match self.previous {
+            Some(previous) => previous,
+            None => return Nothing,
+        };
+
+        match (previous, next) {
+            (LeftBrace, Some(RightBrace)) => Nothing,
+            (LeftBrace, _) | (_, Some(RightBrace)) => NewLine,
+            (RightBrace, Some(Else)) => Space,
+            (RightBrace, Some(Semicolon | RightParen | Comma)) => Nothing,
+            (RightBrace, _) => NewLine,
+            (Semicolon, _) if self.parens == 0 => NewLine,
+            (Semicolon, Some(Semicolon | RightParen)) => Nothing,
+            (Semicolon, _) => Space,
+            (LeftParen | Dot, _) => Nothing,
+            (_, Some(RightParen | Semicolon | Comma | Dot)) => Nothing,
+            (Identifier | RightParen | This | Super, Some(LeftParen)) => Nothing,
+            (Bang | Minus, _) if self.previous_is_unary => Nothing,
+            _ => Space,
+        }
+    }
+
+    /// Whether the previous token ends an operand, making a following `-` binary.
+    fn after_operand(&self) -> bool {
+        use TokenType::*;
+        matches!(
+            self.previous,
+            Some(
+                Identifier
+                    | String
+                    | NumberLiteral
+                    | True
+                    | False
+                    | Nil
+                    | This
+                    | Super
+                    | RightParen
+            )
+        )
+    }
+
+    fn may_break_after(&self, previous: TokenType) -> bool {
+        use TokenType::*;
+        match previous {
+            Minus => !self.previous_is_unary,
+            Comma | Plus | Star | Slash | Equal | EqualEqual | BangEqual | Greater
+            | GreaterEqual | Less | LessEqual | And | Or => true,
+            _ => false,
+        }
+    }
+
+    fn push(&mut self, text: &str) {
+        if self.line.is_empty() {
+            self.line_indent = self.indent;
+            self.line_continues = self.continuation;
+        }
+        self.line.push_str(text);
+    }
+
+    fn blank_line(&mut self) {
+        if !self.out.ends_with("\n\n") {
+            self.out.push('\n');
+        }
+    }
+
+    /// Writes the line being built, wrapping it if it is too long.
+    ///
+    /// `continuation` tells whether the next line continues the same statement.
+    fn end_line(&mut self, continuation: bool) {
+        let levels = self.line_indent + usize::from(self.line_continues);
+        let indentation = " ".repeat(levels * self.options.indent);
+        let wrap_indentation = " ".repeat((self.line_indent + 1) * self.options.indent);
+
+        let mut start = 0;
+        let mut prefix = indentation.as_str();
+        loop {
+            let rest = &self.line[start..];
+            let fits = |end: usize| prefix.len() + self.line[start..end].chars().count();
+
+            if fits(self.line.len()) <= self.options.width {
+                break;
+            }
+
+            let mut candidates = self.breaks.iter().copied().filter(|b| *b > start);
+            let first = match candidates.next() {
+                Some(first) => first,
+                None => break,
+            };
+            let end = std::iter::once(first)
+                .chain(candidates)
+                .take_while(|end| fits(*end) <= self.options.width)
+                .last()
+                .unwrap_or(first);
+
+            self.out.push_str(prefix);
+            self.out.push_str(&rest[..end - start]);
+            self.out.push('\n');
+            start = end + 1;
+            prefix = wrap_indentation.as_str();
+        }
+
+        self.out.push_str(prefix);
+        self.out.push_str(&self.line[start..]);
+        self.out.push('\n');
+
+        self.line.clear();
+        self.breaks.clear();
+        self.continuation = continuation;
+    }
+
+    fn finish(mut self) -> String {
+        if !self.line.is_empty() {
+            self.end_line(false);
+        }
+        self.out
+    }
+}
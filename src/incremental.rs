@@ -0,0 +1,158 @@
+//! Scanning that keeps up with edits of the source code, scanning again only the
+//! tokens around each edit.
+
+use std::ops::Range;
+
+use crate::{
+    error::ErrorOrEarlyReturn,
+    scanner::{Lexer, Token, TokenType},
+};
+
+/// How far past the end of a token the scanner looks to decide where it ends,
+/// as in `1.` followed by a digit or not.
+const LOOKAHEAD: usize = 2;
+
+/// Replacement of a range of the source code, in bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Lexed {
+    type_: TokenType,
+    start: usize,
+    end: usize,
+    line: u32,
+}
+
+/// Source code with all its tokens, including comments, whitespace and `Eof`, as
+/// `scanner::scan_tokens_until_error` gives them.
+pub struct IncrementalLexer {
+    source: String,
+    tokens: Vec<Lexed>,
+    error: Option<ErrorOrEarlyReturn>,
+}
+
+impl IncrementalLexer {
+    pub fn new(source: String) -> Self {
+        let mut lexer = IncrementalLexer {
+            source: String::new(),
+            tokens: vec![],
+            error: None,
+        };
+        lexer.edit(Edit {
+            range: 0..0,
+            text: source,
+        });
+        lexer
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The tokens up to the first error, if any.
+    pub fn tokens(&self) -> impl Iterator<Item = Token<'_>> {
+        self.tokens.iter().map(|token| {
+            Token::new(
+                token.type_,
+                &self.source[token.start..token.end],
+                token.line,
+            )
+        })
+    }
+
+    /// The error ending the tokens.
+    pub fn error(&self) -> Option<&ErrorOrEarlyReturn> {
+        self.error.as_ref()
+    }
+
+    /// Applies the edit, and returns the indexes of the tokens that were scanned again.
+    ///
+    /// Panics if the range is not within the source code, or does not fall on
+    /// character boundaries.
+    pub fn edit(&mut self, edit: Edit) -> Range<usize> {
+        let Edit { range, text } = edit;
+
+        // the tokens before `first` did not look at what changed
+        let first = self
+            .tokens
+            .partition_point(|token| token.end + LOOKAHEAD <= range.start);
+        let (start, line) = self.position_of(first);
+
+        // the tokens from `resync` on start after the edit, and are still valid if the
+        // new tokens reach the start of one of them
+        let resync = self.tokens.partition_point(|token| token.start < range.end);
+        let delta = text.len() as isize - range.len() as isize;
+        let line_delta = text.matches('\n').count() as i64
+            - self.source[range.clone()].matches('\n').count() as i64;
+        let old_error = self.error.take();
+
+        self.source.replace_range(range.clone(), &text);
+
+        let mut lexed = vec![];
+        let mut kept = None;
+        let mut lexer = Lexer::resume(&self.source, start, line);
+        loop {
+            let offset = lexer.offset();
+            // tokens after an error were never scanned
+            if offset >= range.start + text.len() && old_error.is_none() {
+                let old_start = (offset as isize - delta) as usize;
+                if let Ok(index) =
+                    self.tokens[resync..].binary_search_by_key(&old_start, |token| token.start)
+                {
+                    kept = Some(resync + index);
+                    break;
+                }
+            }
+
+            match lexer.next() {
+                Some(Ok(token)) => lexed.push(Lexed {
+                    type_: token.type_,
+                    start: offset,
+                    end: offset + token.lexeme.len(),
+                    line: token.line,
+                }),
+                Some(Err(error)) => {
+                    self.error = Some(error);
+                    break;
+                }
+                None => break,
+            }
+        }
+
+        let kept: Vec<Lexed> = match kept {
+            Some(index) => self.tokens[index..]
+                .iter()
+                .map(|token| Lexed {
+                    start: (token.start as isize + delta) as usize,
+                    end: (token.end as isize + delta) as usize,
+                    line: (token.line as i64 + line_delta) as u32,
+                    ..*token
+                })
+                .collect(),
+            None => vec![],
+        };
+
+        let scanned = first..first + lexed.len();
+        self.tokens.truncate(first);
+        self.tokens.extend(lexed);
+        self.tokens.extend(kept);
+        scanned
+    }
+
+    /// Offset and line where the token at `index` starts, or would start.
+    fn position_of(&self, index: usize) -> (usize, u32) {
+        match (self.tokens.get(index), index.checked_sub(1)) {
+            (Some(token), _) => (token.start, token.line),
+            (None, Some(last)) => {
+                let last = self.tokens[last];
+                let lines = self.source[last.start..last.end].matches('\n').count();
+                (last.end, last.line + lines as u32)
+            }
+            (None, None) => (0, 1),
+        }
+    }
+}
@@ -2,78 +2,200 @@ use std::{
     cell::RefCell,
     io::Write,
     rc::Rc,
-    time::{SystemTime, UNIX_EPOCH},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
 };
 
 use crate::{
-    ast::{Expr, FunctionDecl, IfStatement, LiteralExpr, Statement, WhileStatement},
+    ast::{
+        BinaryExpr, Expr, ExprKind, FunctionDecl, IfStatement, LiteralExpr, NodeId, Statement,
+        StatementKind, WhileStatement,
+    },
+    debugger::{Branch, Callee, Debugger},
     environment::{Env, Environment, Function, NativeFunction, Value},
     error::{ice, runtime_error, ErrorOrEarlyReturn, RuntimeError, ICE},
-    scanner::TokenType,
-    Result,
+    native::{self, Host, Random, TimeSource},
+    parser,
+    scanner::{self, TokenType},
+    with_stack, Result,
 };
 
-const MAX_STACK_SIZE: usize = 512;
+/// Default limit of nested function calls, see [`Interpreter::set_max_call_depth`].
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 512;
 
 pub struct Interpreter<'stdout> {
     stack: Vec<Env>,
+    calls: Vec<Arc<FunctionDecl>>,
+    max_call_depth: usize,
     current_env: Env,
     global_env: Env,
     stdout: &'stdout mut dyn Write,
+    host: Host,
+    debugger: Option<&'stdout mut dyn Debugger>,
+    interrupt: Option<Arc<AtomicBool>>,
 }
 
 impl<'output> Interpreter<'output> {
     pub fn new(stdout: &'output mut dyn Write) -> Self {
-        let global_env = Rc::new(RefCell::new(Environment::new(None)));
-
-        Self::define_native_functions(global_env.clone());
+        let global_env = Self::new_global_env();
 
         Interpreter {
             current_env: global_env.clone(),
             stack: vec![global_env.clone()],
+            calls: vec![],
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
             global_env,
             stdout,
+            host: Host::default(),
+            debugger: None,
+            interrupt: None,
         }
     }
 
+    /// Forgets every definition made so far, keeping the settings of the interpreter.
+    pub fn reset(&mut self) {
+        self.global_env = Self::new_global_env();
+        self.current_env = self.global_env.clone();
+        self.stack = vec![self.global_env.clone()];
+        self.calls.clear();
+    }
+
+    fn new_global_env() -> Env {
+        let global_env = Rc::new(RefCell::new(Environment::new(None)));
+        Self::define_native_functions(global_env.clone());
+        global_env
+    }
+
+    /// Replaces the source of the time returned by `clock()`.
+    pub fn set_time_source(&mut self, time: Box<dyn TimeSource>) {
+        self.host.time = time;
+    }
+
+    /// Sets the arguments of the script, available through `argc()` and `argv(i)`.
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.host.args = args;
+    }
+
+    /// Reseeds the generator behind `random()`, making its sequence replayable.
+    pub fn seed_random(&mut self, seed: u64) {
+        self.host.random = Random::with_seed(seed);
+    }
+
+    /// Limits how many function calls may be active at once. Blocks do not count.
+    pub fn set_max_call_depth(&mut self, max_call_depth: usize) {
+        self.max_call_depth = max_call_depth;
+    }
+
+    /// Number of function calls currently being executed.
+    pub fn call_depth(&self) -> usize {
+        self.calls.len()
+    }
+
+    /// Functions currently being executed, the innermost last.
+    pub fn calls(&self) -> &[Arc<FunctionDecl>] {
+        &self.calls
+    }
+
+    /// Calls `debugger` before executing each statement, and on function calls and returns.
+    pub fn set_debugger(&mut self, debugger: &'output mut dyn Debugger) {
+        self.debugger = Some(debugger);
+    }
+
+    /// Stops the execution with `RuntimeError::Interrupted` before the next
+    /// statement once `flag` is set, as a Ctrl-C handler does. The flag is
+    /// cleared when it stops.
+    pub fn set_interrupt(&mut self, flag: Arc<AtomicBool>) {
+        self.interrupt = Some(flag);
+    }
+
+    pub fn global_env(&self) -> Env {
+        self.global_env.clone()
+    }
+
+    /// Environment of the code being executed.
+    pub fn current_env(&self) -> Env {
+        self.current_env.clone()
+    }
+
+    /// Calculates the expression as if it were in the code that has `env` as environment.
+    pub fn calc_expr_in(&mut self, env: Env, expr: &Expr) -> Result<Value> {
+        let current_env = std::mem::replace(&mut self.current_env, env);
+        let result = self.calc_expr(expr);
+        self.current_env = current_env;
+        result
+    }
+
     pub fn exec_stmt(&mut self, stmt: &Statement) -> Result<Value> {
-        match stmt {
-            Statement::Expr(expr) => self.calc_expr(expr),
-            Statement::Print(expr) => self.print_stmt(expr),
-            Statement::VariableDecl(name, value) => self.var_decl(name, value),
-            Statement::Block(statements) => self.exec_block(statements),
-            Statement::If(if_statement) => self.if_stmt(if_statement),
-            Statement::While(while_statement) => self.while_stmt(while_statement),
-            Statement::FunDecl(function) => self.declare_fun(function),
-            Statement::Return(expr) => self.return_stmt(expr.as_ref()),
+        if let Some(flag) = &self.interrupt {
+            if flag.swap(false, Ordering::Relaxed) {
+                return Err(runtime_error(RuntimeError::Interrupted));
+            }
         }
+        self.debug(|debugger, int| debugger.before_stmt(int, stmt))?;
+
+        self.nested(|int| match &stmt.kind {
+            StatementKind::Expr(expr) => int.calc_expr(expr),
+            StatementKind::Print(expr) => int.print_stmt(expr),
+            StatementKind::VariableDecl(name, value) => int.var_decl(name, value),
+            StatementKind::Block(statements) => int.exec_block(statements),
+            StatementKind::If(if_statement) => int.if_stmt(stmt.id, if_statement),
+            StatementKind::While(while_statement) => int.while_stmt(stmt.id, while_statement),
+            StatementKind::FunDecl(function) => int.declare_fun(function),
+            StatementKind::Return(expr) => int.return_stmt(expr.as_ref()),
+        })
+    }
+
+    /// Scans, parses and calculates a single expression.
+    pub fn eval_expr(&mut self, src: &str) -> Result<Value> {
+        let expr = parser::parse_expr(&scanner::scan_tokens(src)?)?;
+        self.calc_expr(&expr)
     }
 
     pub fn calc_expr(&mut self, expr: &Expr) -> Result<Value> {
-        match expr {
-            Expr::Literal(lit) => Ok(calc_lit(lit)),
-            Expr::Identifier(id) => self.calc_identifier(id),
-            Expr::Unary(unary) => self.calc_unary(unary.op, unary.expr.as_ref()),
-            Expr::Binary(bin) => self.calc_binary(bin.left.as_ref(), bin.op, bin.right.as_ref()),
-            Expr::Grouping(expr) => self.calc_expr(expr.as_ref()),
-            Expr::Assignment(var_name, rvalue) => self.calc_assignment(var_name, rvalue),
-            Expr::Call(call) => self.call_fun(&call.callee, &call.args),
+        self.nested(|int| match &expr.kind {
+            ExprKind::Literal(lit) => Ok(calc_lit(lit)),
+            ExprKind::Identifier(id) => int.calc_identifier(id),
+            ExprKind::Unary(unary) => int.calc_unary(unary.op, unary.expr.as_ref()),
+            ExprKind::Binary(bin) if matches!(bin.op, TokenType::And | TokenType::Or) => {
+                int.calc_logical(expr.id, bin)
+            }
+            ExprKind::Binary(bin) => int.calc_binary(bin.left.as_ref(), bin.op, bin.right.as_ref()),
+            ExprKind::Grouping(expr) => int.calc_expr(expr.as_ref()),
+            ExprKind::Assignment(var_name, rvalue) => int.calc_assignment(var_name, rvalue),
+            ExprKind::Call(call) => int.call_fun(&call.callee, &call.args),
+        })
+    }
+
+    /// Runs `eval` one level deeper in the tree. Every call stacks the depth of
+    /// the called function on top of the caller's, so the native stack is
+    /// grown on demand instead of relying on the nesting limit of the parser.
+    fn nested(&mut self, eval: impl FnOnce(&mut Self) -> Result<Value>) -> Result<Value> {
+        with_stack(|| eval(self))
+    }
+
+    /// Runs `hook` if there is a debugger.
+    fn debug(
+        &mut self,
+        hook: impl FnOnce(&mut dyn Debugger, &mut Self) -> Result<()>,
+    ) -> Result<()> {
+        // taken out while it runs, so it is not called for the code it evaluates
+        match self.debugger.take() {
+            Some(debugger) => {
+                let result = hook(&mut *debugger, self);
+                self.debugger = Some(debugger);
+                result
+            }
+            None => Ok(()),
         }
     }
 
     fn define_native_functions(env: Env) {
-        env.borrow_mut().define(
-            "clock",
-            Value::NativeFunction(NativeFunction {
-                name: "clock",
-                exec: |_env| match SystemTime::now().duration_since(UNIX_EPOCH) {
-                    Ok(duration) => Ok(Value::Number(duration.as_millis() as f64 / 1000.0)),
-                    Err(_) => Err(runtime_error(RuntimeError::GenericError(
-                        "Time went backwards when calling native function `clock`.".into(),
-                    ))),
-                },
-            }),
-        );
+        for native in native::functions() {
+            env.borrow_mut()
+                .define(native.name, Value::NativeFunction(native));
+        }
     }
 
     fn print_stmt(&mut self, expr: &Expr) -> Result<Value> {
@@ -137,47 +259,46 @@ impl<'output> Interpreter<'output> {
         use Value::*;
 
         let left = self.calc_expr(left)?;
-
-        if op == And {
-            Ok(Boolean(
-                is_truthy(&left) && is_truthy(&self.calc_expr(right)?),
-            ))
-        } else if op == Or {
-            Ok(Boolean(
-                is_truthy(&left) || is_truthy(&self.calc_expr(right)?),
-            ))
-        } else {
-            let right = self.calc_expr(right)?;
-            match (left, op, right) {
-                // numbers
-                (Number(l), Plus, Number(r)) => Ok(Number(l + r)),
-                (Number(l), Minus, Number(r)) => Ok(Number(l - r)),
-                (Number(l), Star, Number(r)) => Ok(Number(l * r)),
-                (Number(_), Slash, Number(r)) if r == 0.0 => {
-                    Err(runtime_error(RuntimeError::DivisionByZero))
-                }
-                (Number(l), Slash, Number(r)) => Ok(Number(l / r)),
-
-                // comparisons
-                (l, EqualEqual, r) => Ok(Boolean(l == r)),
-                (l, BangEqual, r) => Ok(Boolean(l != r)),
-                (Number(l), Greater, Number(r)) => Ok(Boolean(l > r)),
-                (Number(l), GreaterEqual, Number(r)) => Ok(Boolean(l >= r)),
-                (Number(l), Less, Number(r)) => Ok(Boolean(l < r)),
-                (Number(l), LessEqual, Number(r)) => Ok(Boolean(l <= r)),
-
-                // strings
-                (Value::String(l), Plus, Value::String(r)) => Ok(Value::String(l + &r)),
-
-                (left, op, right) => Err(runtime_error(RuntimeError::InvalidOperator(
-                    op,
-                    format!("{}", left),
-                    format!("{}", right),
-                ))),
-            }
+        let right = self.calc_expr(right)?;
+        match (left, op, right) {
+            // numbers
+            (Number(l), Plus, Number(r)) => Ok(Number(l + r)),
+            (Number(l), Minus, Number(r)) => Ok(Number(l - r)),
+            (Number(l), Star, Number(r)) => Ok(Number(l * r)),
+            (Number(_), Slash, Number(0.0)) => Err(runtime_error(RuntimeError::DivisionByZero)),
+            (Number(l), Slash, Number(r)) => Ok(Number(l / r)),
+
+            // comparisons
+            (l, EqualEqual, r) => Ok(Boolean(l == r)),
+            (l, BangEqual, r) => Ok(Boolean(l != r)),
+            (Number(l), Greater, Number(r)) => Ok(Boolean(l > r)),
+            (Number(l), GreaterEqual, Number(r)) => Ok(Boolean(l >= r)),
+            (Number(l), Less, Number(r)) => Ok(Boolean(l < r)),
+            (Number(l), LessEqual, Number(r)) => Ok(Boolean(l <= r)),
+
+            // strings
+            (Value::String(l), Plus, Value::String(r)) => Ok(Value::String(l + &r)),
+
+            (left, op, right) => Err(runtime_error(RuntimeError::InvalidOperator(
+                op,
+                format!("{}", left),
+                format!("{}", right),
+            ))),
         }
     }
 
+    /// `and` and `or` only evaluate their right operand when the left one does not decide.
+    fn calc_logical(&mut self, id: NodeId, bin: &BinaryExpr) -> Result<Value> {
+        let left = is_truthy(&self.calc_expr(&bin.left)?);
+        self.debug(|debugger, int| debugger.on_branch(int, Branch::Logical(id, bin), left))?;
+
+        let value = match bin.op {
+            TokenType::And => left && is_truthy(&self.calc_expr(&bin.right)?),
+            _ => left || is_truthy(&self.calc_expr(&bin.right)?),
+        };
+        Ok(Value::Boolean(value))
+    }
+
     fn var_decl(&mut self, name: &str, expr: &Option<Expr>) -> Result<Value> {
         let value = expr
             .as_ref()
@@ -213,10 +334,13 @@ impl<'output> Interpreter<'output> {
         Ok(Value::Nil)
     }
 
-    fn if_stmt(&mut self, if_statement: &IfStatement) -> Result<Value> {
-        let cond_value = self.calc_expr(&if_statement.cond)?;
+    fn if_stmt(&mut self, id: NodeId, if_statement: &IfStatement) -> Result<Value> {
+        let cond_value = is_truthy(&self.calc_expr(&if_statement.cond)?);
+        self.debug(|debugger, int| {
+            debugger.on_branch(int, Branch::If(id, if_statement), cond_value)
+        })?;
 
-        if is_truthy(&cond_value) {
+        if cond_value {
             self.exec_stmt(&if_statement.then_branch)?;
         } else if let Some(else_branch) = &if_statement.else_branch {
             self.exec_stmt(else_branch)?;
@@ -225,18 +349,22 @@ impl<'output> Interpreter<'output> {
         Ok(Value::Nil)
     }
 
-    fn while_stmt(&mut self, while_statement: &WhileStatement) -> Result<Value> {
-        let mut cond_value = self.calc_expr(&while_statement.cond)?;
-
-        while is_truthy(&cond_value) {
+    fn while_stmt(&mut self, id: NodeId, while_statement: &WhileStatement) -> Result<Value> {
+        loop {
+            let cond_value = is_truthy(&self.calc_expr(&while_statement.cond)?);
+            self.debug(|debugger, int| {
+                debugger.on_branch(int, Branch::While(id, while_statement), cond_value)
+            })?;
+            if !cond_value {
+                break;
+            }
             self.exec_stmt(&while_statement.stmt)?;
-            cond_value = self.calc_expr(&while_statement.cond)?;
         }
 
         Ok(Value::Nil)
     }
 
-    fn declare_fun(&mut self, function: &Rc<FunctionDecl>) -> Result<Value> {
+    fn declare_fun(&mut self, function: &Arc<FunctionDecl>) -> Result<Value> {
         self.current_env.borrow_mut().define(
             &function.name,
             Value::Function(Function {
@@ -254,25 +382,19 @@ impl<'output> Interpreter<'output> {
         Err(ErrorOrEarlyReturn::EarlyReturn(value))
     }
 
-    fn call_fun(&mut self, callee: &Expr, args: &Vec<Expr>) -> Result<Value> {
-        if self.stack.len() > MAX_STACK_SIZE {
-            return Err(runtime_error(RuntimeError::StackOverflow));
-        }
-
+    fn call_fun(&mut self, callee: &Expr, args: &[Expr]) -> Result<Value> {
         let calculated_fun = self.calc_expr(callee)?;
         match calculated_fun {
             Value::Function(fun) => self.call_user_defined_function(&fun, args),
-            Value::NativeFunction(fun) => self.call_native_function(&fun),
-            _ => {
-                return Err(runtime_error(RuntimeError::UndefinedFunction(format!(
-                    "{}",
-                    calculated_fun
-                ))))
-            }
+            Value::NativeFunction(fun) => self.call_native_function(&fun, args),
+            _ => Err(runtime_error(RuntimeError::UndefinedFunction(format!(
+                "{}",
+                calculated_fun
+            )))),
         }
     }
 
-    fn call_user_defined_function(&mut self, fun: &Function, args: &Vec<Expr>) -> Result<Value> {
+    fn call_user_defined_function(&mut self, fun: &Function, args: &[Expr]) -> Result<Value> {
         if args.len() != fun.ast.params.len() {
             return Err(runtime_error(RuntimeError::NumberOfArgumentsMismatch(
                 fun.ast.params.len(),
@@ -288,6 +410,18 @@ impl<'output> Interpreter<'output> {
             .map(|expr| self.calc_expr(expr))
             .collect::<Result<Vec<Value>>>()?;
 
+        if self.calls.len() >= self.max_call_depth {
+            return Err(runtime_error(RuntimeError::StackOverflow(
+                self.calls.len() + 1,
+                fun.ast.name.clone(),
+            )));
+        }
+
+        self.debug(|debugger, int| {
+            debugger.on_call(int, Callee::Function(&fun.ast), &computed_args)
+        })?;
+
+        self.calls.push(fun.ast.clone());
         self.push_new_env(Some(fun.closure.clone()));
 
         for (value, name) in computed_args.iter().zip(&fun.ast.params) {
@@ -297,17 +431,37 @@ impl<'output> Interpreter<'output> {
         // execute function
 
         let result = self.exec_statements(&fun.ast.body);
+        self.calls.pop();
         self.pop_env()?;
 
-        match result {
-            Ok(value) => Ok(value),
-            Err(ErrorOrEarlyReturn::EarlyReturn(value)) => Ok(value),
-            Err(err) => Err(err),
-        }
+        let value = match result {
+            Ok(value) => value,
+            Err(ErrorOrEarlyReturn::EarlyReturn(value)) => value,
+            Err(err) => return Err(err),
+        };
+
+        self.debug(|debugger, int| debugger.on_return(int, Callee::Function(&fun.ast), &value))?;
+        Ok(value)
     }
 
-    fn call_native_function(&self, fun: &NativeFunction) -> Result<Value> {
-        (fun.exec)(self.current_env.clone())
+    fn call_native_function(&mut self, fun: &NativeFunction, args: &[Expr]) -> Result<Value> {
+        if args.len() != fun.arity {
+            return Err(runtime_error(RuntimeError::NumberOfArgumentsMismatch(
+                fun.arity,
+                fun.name.into(),
+                args.len(),
+            )));
+        }
+
+        let computed_args = args
+            .iter()
+            .map(|expr| self.calc_expr(expr))
+            .collect::<Result<Vec<Value>>>()?;
+
+        self.debug(|debugger, int| debugger.on_call(int, Callee::Native(fun), &computed_args))?;
+        let value = (fun.exec)(&mut self.host, &computed_args)?;
+        self.debug(|debugger, int| debugger.on_return(int, Callee::Native(fun), &value))?;
+        Ok(value)
     }
 }
 
@@ -1,14 +1,34 @@
+pub mod analysis;
 pub mod error;
+pub mod formatter;
 
-#[cfg(feature = "scanner_recursive_descent")]
 pub mod scanner;
-#[cfg(feature = "scanner_logos")]
 #[path = "./alt/scanner_logos.rs"]
-pub mod scanner;
+pub mod scanner_logos;
 
 pub mod ast;
+pub mod cli;
+pub mod coverage;
+pub mod cst;
+pub mod dap;
+pub mod debugger;
 pub mod environment;
+pub mod incremental;
 pub mod interpreter;
+pub mod lower;
+pub mod lsp;
+pub mod native;
 pub mod parser;
+pub mod profile;
+pub mod repl;
+pub mod source_map;
+pub mod trace;
+pub mod visit;
 
 pub type Result<T> = std::result::Result<T, error::ErrorOrEarlyReturn>;
+
+/// Runs `f`, moving to a fresh segment of native stack first if the current one is
+/// almost exhausted. The parser and the interpreter recurse once per nesting level.
+pub(crate) fn with_stack<T>(f: impl FnOnce() -> T) -> T {
+    stacker::maybe_grow(128 * 1024, 2 * 1024 * 1024, f)
+}
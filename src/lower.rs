@@ -0,0 +1,207 @@
+//! Derives the abstract syntax tree from the concrete one.
+
+use std::sync::Arc;
+
+use crate::{
+    ast::*,
+    cst::{NodeKind, SyntaxElement, SyntaxNode, SyntaxToken},
+    error::{compilation_error, ice, CompilationError, ErrorOrEarlyReturn, ICE},
+    scanner::{self, TokenType},
+    with_stack, Result,
+};
+
+/// Lowers a `Program` node into its statements.
+pub fn program(node: &SyntaxNode) -> Result<Vec<Statement>> {
+    expect(node, NodeKind::Program)?;
+    node.children().map(|child| statement(&child)).collect()
+}
+
+pub fn statement(node: &SyntaxNode) -> Result<Statement> {
+    with_stack(|| match node.kind() {
+        NodeKind::VarDecl => Ok(StatementKind::VariableDecl(
+            token(node, TokenType::Identifier)?.text().into(),
+            node.children()
+                .find(|child| child.kind().is_expr())
+                .map(|child| expr(&child))
+                .transpose()?,
+        )
+        .into()),
+        NodeKind::FunDecl => fun_decl(node),
+        NodeKind::Block => Ok(StatementKind::Block(block(node)?).into()),
+        NodeKind::If => Ok(StatementKind::If(IfStatement {
+            cond: expr(&child(node, 0)?)?,
+            then_branch: Box::new(statement(&child(node, 1)?)?),
+            else_branch: match node.children().nth(2) {
+                Some(else_branch) => Some(Box::new(statement(&else_branch)?)),
+                None => None,
+            },
+        })
+        .into()),
+        NodeKind::While => Ok(StatementKind::While(WhileStatement {
+            cond: expr(&child(node, 0)?)?,
+            stmt: Box::new(statement(&child(node, 1)?)?),
+        })
+        .into()),
+        NodeKind::For => for_stmt(node),
+        NodeKind::Print => Ok(StatementKind::Print(expr(&child(node, 0)?)?).into()),
+        NodeKind::Return => Ok(StatementKind::Return(
+            node.children()
+                .next()
+                .map(|value| expr(&value))
+                .transpose()?,
+        )
+        .into()),
+        NodeKind::ExprStmt => Ok(StatementKind::Expr(expr(&child(node, 0)?)?).into()),
+        _ => Err(malformed(node)),
+    })
+}
+
+fn fun_decl(node: &SyntaxNode) -> Result<Statement> {
+    let params = node
+        .children()
+        .find(|child| child.kind() == NodeKind::ParamList)
+        .ok_or_else(|| malformed(node))?;
+    let body = node
+        .children()
+        .find(|child| child.kind() == NodeKind::Block)
+        .ok_or_else(|| malformed(node))?;
+
+    Ok(StatementKind::FunDecl(Arc::new(FunctionDecl::new(
+        token(node, TokenType::Identifier)?.text().into(),
+        params
+            .tokens()
+            .filter(|param| param.kind() == TokenType::Identifier)
+            .map(|param| param.text().into())
+            .collect(),
+        block(&body)?,
+    )))
+    .into())
+}
+
+fn block(node: &SyntaxNode) -> Result<Vec<Statement>> {
+    expect(node, NodeKind::Block)?;
+    node.children().map(|child| statement(&child)).collect()
+}
+
+/// Desugars a `for` loop into a `while` loop.
+fn for_stmt(node: &SyntaxNode) -> Result<Statement> {
+    let mut initialization = None;
+    let mut condition = None;
+    let mut increment = None;
+    let mut body = None;
+    // both the initialization clause and the body are statements
+    let mut after_clauses = false;
+
+    for element in node.children_with_tokens() {
+        match element {
+            SyntaxElement::Token(token) if token.kind() == TokenType::RightParen => {
+                after_clauses = true
+            }
+            SyntaxElement::Token(_) => (),
+            SyntaxElement::Node(clause) if clause.kind() == NodeKind::ForCondition => {
+                condition = Some(expr(&child(&clause, 0)?)?)
+            }
+            SyntaxElement::Node(clause) if clause.kind() == NodeKind::ForIncrement => {
+                increment = Some(StatementKind::Expr(expr(&child(&clause, 0)?)?).into())
+            }
+            SyntaxElement::Node(stmt) if after_clauses => body = Some(statement(&stmt)?),
+            SyntaxElement::Node(stmt) => initialization = Some(statement(&stmt)?),
+        }
+    }
+
+    let mut while_body = vec![body.ok_or_else(|| malformed(node))?];
+    while_body.extend(increment);
+
+    let mut gen_body = vec![];
+    gen_body.extend(initialization);
+    gen_body.push(
+        StatementKind::While(WhileStatement {
+            cond: condition.unwrap_or(ExprKind::Literal(LiteralExpr::Boolean(true)).into()),
+            stmt: Box::new(StatementKind::Block(while_body).into()),
+        })
+        .into(),
+    );
+
+    Ok(StatementKind::Block(gen_body).into())
+}
+
+pub fn expr(node: &SyntaxNode) -> Result<Expr> {
+    with_stack(|| match node.kind() {
+        NodeKind::Literal => literal(node),
+        NodeKind::Name => Ok(ExprKind::Identifier(first_token(node)?.text().into()).into()),
+        NodeKind::Paren => Ok(ExprKind::Grouping(Box::new(expr(&child(node, 0)?)?)).into()),
+        NodeKind::Unary => Ok(ExprKind::Unary(UnaryExpr {
+            op: first_token(node)?.kind(),
+            expr: Box::new(expr(&child(node, 0)?)?),
+        })
+        .into()),
+        NodeKind::Binary => Ok(ExprKind::Binary(BinaryExpr {
+            left: Box::new(expr(&child(node, 0)?)?),
+            op: first_token(node)?.kind(),
+            right: Box::new(expr(&child(node, 1)?)?),
+        })
+        .into()),
+        NodeKind::Assignment => Ok(ExprKind::Assignment(
+            first_token(&child(node, 0)?)?.text().into(),
+            Box::new(expr(&child(node, 1)?)?),
+        )
+        .into()),
+        NodeKind::Call => Ok(ExprKind::Call(CallExpr {
+            callee: Box::new(expr(&child(node, 0)?)?),
+            args: child(node, 1)?
+                .children()
+                .map(|arg| expr(&arg))
+                .collect::<Result<_>>()?,
+        })
+        .into()),
+        _ => Err(malformed(node)),
+    })
+}
+
+fn literal(node: &SyntaxNode) -> Result<Expr> {
+    let token = first_token(node)?;
+    let literal = match token.kind() {
+        TokenType::False => LiteralExpr::Boolean(false),
+        TokenType::True => LiteralExpr::Boolean(true),
+        TokenType::Nil => LiteralExpr::Nil,
+        TokenType::String => {
+            let lexeme = token.text();
+            LiteralExpr::String(lexeme[1..(lexeme.len() - 1)].into()) // remove the ""
+        }
+        TokenType::NumberLiteral => match scanner::parse_number(token.text()) {
+            Some(number) => LiteralExpr::Number(number),
+            None => {
+                return Err(compilation_error(CompilationError::InvalidLiteral(
+                    "number".into(),
+                    token.text().into(),
+                )))
+            }
+        },
+        _ => return Err(malformed(node)),
+    };
+    Ok(ExprKind::Literal(literal).into())
+}
+
+fn expect(node: &SyntaxNode, kind: NodeKind) -> Result<()> {
+    if node.kind() == kind {
+        Ok(())
+    } else {
+        Err(malformed(node))
+    }
+}
+
+fn child(node: &SyntaxNode, index: usize) -> Result<SyntaxNode> {
+    node.children().nth(index).ok_or_else(|| malformed(node))
+}
+
+fn first_token(node: &SyntaxNode) -> Result<SyntaxToken> {
+    node.tokens().next().ok_or_else(|| malformed(node))
+}
+
+fn token(node: &SyntaxNode, kind: TokenType) -> Result<SyntaxToken> {
+    node.child_token(kind).ok_or_else(|| malformed(node))
+}
+
+fn malformed(node: &SyntaxNode) -> ErrorOrEarlyReturn {
+    ice(ICE::Generic(format!("Malformed syntax tree at {:?}", node)))
+}
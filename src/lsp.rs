@@ -0,0 +1,438 @@
+//! Language server speaking the Language Server Protocol.
+
+use std::{collections::HashMap, ops::Range};
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+        Notification as LspNotification, PublishDiagnostics,
+    },
+    request::{
+        Completion, DocumentSymbolRequest, GotoDefinition, HoverRequest, References,
+        Request as LspRequest,
+    },
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse,
+    DiagnosticSeverity, DidChangeTextDocumentParams, DidCloseTextDocumentParams,
+    DidOpenTextDocumentParams, DocumentSymbol, DocumentSymbolParams, DocumentSymbolResponse,
+    GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents, HoverParams,
+    HoverProviderCapability, Location, MarkupContent, MarkupKind, OneOf, Position,
+    PublishDiagnosticsParams, ReferenceParams, ServerCapabilities, TextDocumentSyncCapability,
+    TextDocumentSyncKind, Url,
+};
+
+use crate::{
+    analysis::{self, Analysis, Definition, SymbolKind},
+    error::{ice, ErrorOrEarlyReturn, ICE},
+    native, Result,
+};
+
+const KEYWORDS: [&str; 16] = [
+    "and", "class", "else", "false", "for", "fun", "if", "nil", "or", "print", "return", "super",
+    "this", "true", "var", "while",
+];
+
+/// Serves the editor connected to the standard input and output until it exits.
+pub fn run() -> Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+    serve(&connection)?;
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
+
+/// Serves the editor on the other end of the connection until it asks to shut down.
+pub fn serve(connection: &Connection) -> Result<()> {
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        completion_provider: Some(CompletionOptions::default()),
+        ..ServerCapabilities::default()
+    };
+    connection
+        .initialize(json(capabilities)?)
+        .map_err(protocol_error)?;
+
+    let mut server = Server::default();
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection
+                    .handle_shutdown(&request)
+                    .map_err(protocol_error)?
+                {
+                    return Ok(());
+                }
+                let response = server.request(request);
+                send(connection, Message::Response(response))?;
+            }
+            Message::Notification(notification) => {
+                if let Some(diagnostics) = server.notification(notification)? {
+                    let notification =
+                        Notification::new(PublishDiagnostics::METHOD.into(), diagnostics);
+                    send(connection, Message::Notification(notification))?;
+                }
+            }
+            Message::Response(_) => (),
+        }
+    }
+
+    Ok(())
+}
+
+fn send(connection: &Connection, message: Message) -> Result<()> {
+    connection.sender.send(message).map_err(protocol_error)
+}
+
+fn json(value: impl serde::Serialize) -> Result<serde_json::Value> {
+    serde_json::to_value(value).map_err(protocol_error)
+}
+
+fn protocol_error(error: impl std::fmt::Display) -> ErrorOrEarlyReturn {
+    ice(ICE::Generic(format!(
+        "Language server protocol error: {}",
+        error
+    )))
+}
+
+#[derive(Default)]
+struct Server {
+    documents: HashMap<Url, Document>,
+}
+
+struct Document {
+    text: String,
+    /// Offsets where the lines start.
+    lines: Vec<usize>,
+    /// `None` while the document has a syntax error.
+    analysis: Option<Analysis>,
+    /// Names of the globals of the last version without syntax errors.
+    last_globals: Vec<Definition>,
+}
+
+impl Server {
+    /// Handles a notification, returning the diagnostics to publish, if any.
+    fn notification(
+        &mut self,
+        notification: Notification,
+    ) -> Result<Option<PublishDiagnosticsParams>> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: DidOpenTextDocumentParams = params(notification.params)?;
+                let document = params.text_document;
+                Ok(Some(self.update(
+                    document.uri,
+                    document.text,
+                    Some(document.version),
+                )))
+            }
+            DidChangeTextDocument::METHOD => {
+                let params: DidChangeTextDocumentParams = params(notification.params)?;
+                // the whole text comes in the last change, as the sync is full
+                match params.content_changes.into_iter().last() {
+                    Some(change) => Ok(Some(self.update(
+                        params.text_document.uri,
+                        change.text,
+                        Some(params.text_document.version),
+                    ))),
+                    None => Ok(None),
+                }
+            }
+            DidCloseTextDocument::METHOD => {
+                let params: DidCloseTextDocumentParams = params(notification.params)?;
+                self.documents.remove(&params.text_document.uri);
+                Ok(Some(PublishDiagnosticsParams::new(
+                    params.text_document.uri,
+                    vec![],
+                    None,
+                )))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn update(&mut self, uri: Url, text: String, version: Option<i32>) -> PublishDiagnosticsParams {
+        let last_globals = self
+            .documents
+            .remove(&uri)
+            .map(|document| document.last_globals)
+            .unwrap_or_default();
+        let mut document = Document::new(text, last_globals);
+
+        let diagnostics = match analysis::analyze_source(&document.text) {
+            Ok(analysis) => {
+                document.last_globals = analysis.scopes[0]
+                    .definitions
+                    .iter()
+                    .map(|&definition| analysis.definitions[definition].clone())
+                    .collect();
+                let diagnostics = analysis.diagnostics.clone();
+                document.analysis = Some(analysis);
+                diagnostics
+            }
+            Err(diagnostic) => vec![diagnostic],
+        };
+
+        let diagnostics = diagnostics
+            .into_iter()
+            .map(|diagnostic| lsp_types::Diagnostic {
+                range: document.range(&diagnostic.range),
+                severity: Some(DiagnosticSeverity::ERROR),
+                source: Some("rlox".into()),
+                message: diagnostic.message,
+                ..lsp_types::Diagnostic::default()
+            })
+            .collect();
+
+        self.documents.insert(uri.clone(), document);
+        PublishDiagnosticsParams::new(uri, diagnostics, version)
+    }
+
+    fn request(&mut self, request: Request) -> Response {
+        let Request { id, method, params } = request;
+        let result = match method.as_str() {
+            GotoDefinition::METHOD => self.handle::<GotoDefinition>(params, Self::definition),
+            References::METHOD => self.handle::<References>(params, Self::references),
+            HoverRequest::METHOD => self.handle::<HoverRequest>(params, Self::hover),
+            DocumentSymbolRequest::METHOD => {
+                self.handle::<DocumentSymbolRequest>(params, Self::document_symbols)
+            }
+            Completion::METHOD => self.handle::<Completion>(params, Self::completion),
+            _ => {
+                return Response::new_err(
+                    id,
+                    ErrorCode::MethodNotFound as i32,
+                    format!("Unknown method '{}'", method),
+                )
+            }
+        };
+
+        match result {
+            Ok(result) => Response::new_ok(id, result),
+            Err(error) => Response::new_err(id, ErrorCode::InvalidParams as i32, error.to_string()),
+        }
+    }
+
+    fn handle<R: LspRequest>(
+        &self,
+        params: serde_json::Value,
+        handler: fn(&Self, R::Params) -> R::Result,
+    ) -> Result<serde_json::Value> {
+        json(handler(self, self::params(params)?))
+    }
+
+    /// The analysis of the document and the offset of the position in it.
+    fn locate(&self, uri: &Url, position: Position) -> Option<(&Document, &Analysis, usize)> {
+        let document = self.documents.get(uri)?;
+        let analysis = document.analysis.as_ref()?;
+        Some((document, analysis, document.offset(position)))
+    }
+
+    fn definition(&self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let position = params.text_document_position_params;
+        let uri = position.text_document.uri;
+        let (document, analysis, offset) = self.locate(&uri, position.position)?;
+
+        let definition = &analysis.definitions[analysis.definition_at(offset)?];
+        Some(GotoDefinitionResponse::Scalar(Location::new(
+            uri.clone(),
+            document.range(&definition.range),
+        )))
+    }
+
+    fn references(&self, params: ReferenceParams) -> Option<Vec<Location>> {
+        let position = params.text_document_position;
+        let uri = position.text_document.uri;
+        let (document, analysis, offset) = self.locate(&uri, position.position)?;
+
+        let definition = analysis.definition_at(offset)?;
+        let declaration = params
+            .context
+            .include_declaration
+            .then(|| &analysis.definitions[definition].range);
+        let references = analysis
+            .references_to(definition)
+            .map(|reference| &reference.range);
+
+        Some(
+            declaration
+                .into_iter()
+                .chain(references)
+                .map(|range| Location::new(uri.clone(), document.range(range)))
+                .collect(),
+        )
+    }
+
+    fn hover(&self, params: HoverParams) -> Option<Hover> {
+        let position = params.text_document_position_params;
+        let (document, analysis, offset) =
+            self.locate(&position.text_document.uri, position.position)?;
+
+        let (signature, range) = match analysis.definition_at(offset) {
+            Some(definition) => {
+                let definition = &analysis.definitions[definition];
+                let range = analysis
+                    .reference_at(offset)
+                    .map_or(&definition.range, |reference| &reference.range);
+                (describe(definition), range)
+            }
+            None => {
+                let reference = analysis.reference_at(offset)?;
+                let native = native::functions()
+                    .into_iter()
+                    .find(|native| native.name == reference.name)?;
+                (
+                    native_signature(native.name, native.arity),
+                    &reference.range,
+                )
+            }
+        };
+
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: format!("```lox\n{}\n```", signature),
+            }),
+            range: Some(document.range(range)),
+        })
+    }
+
+    fn document_symbols(&self, params: DocumentSymbolParams) -> Option<DocumentSymbolResponse> {
+        let document = self.documents.get(&params.text_document.uri)?;
+        let analysis = document.analysis.as_ref()?;
+        Some(DocumentSymbolResponse::Nested(symbols(
+            document, analysis, None,
+        )))
+    }
+
+    fn completion(&self, params: CompletionParams) -> Option<CompletionResponse> {
+        let position = params.text_document_position;
+        let document = self.documents.get(&position.text_document.uri)?;
+
+        let definitions = match &document.analysis {
+            Some(analysis) => analysis.visible_at(document.offset(position.position)),
+            // the code is being edited, so offer what was there before
+            None => document.last_globals.iter().collect(),
+        };
+
+        let names = definitions.into_iter().map(|definition| CompletionItem {
+            label: definition.name.clone(),
+            kind: Some(match definition.kind {
+                SymbolKind::Function => CompletionItemKind::FUNCTION,
+                SymbolKind::Variable | SymbolKind::Parameter => CompletionItemKind::VARIABLE,
+            }),
+            detail: Some(describe(definition)),
+            ..CompletionItem::default()
+        });
+        let natives = native::functions()
+            .into_iter()
+            .map(|native| CompletionItem {
+                label: native.name.into(),
+                kind: Some(CompletionItemKind::FUNCTION),
+                detail: Some(native_signature(native.name, native.arity)),
+                ..CompletionItem::default()
+            });
+        let keywords = KEYWORDS.iter().map(|keyword| CompletionItem {
+            label: keyword.to_string(),
+            kind: Some(CompletionItemKind::KEYWORD),
+            ..CompletionItem::default()
+        });
+
+        Some(CompletionResponse::Array(
+            names.chain(natives).chain(keywords).collect(),
+        ))
+    }
+}
+
+fn params<T: serde::de::DeserializeOwned>(params: serde_json::Value) -> Result<T> {
+    serde_json::from_value(params).map_err(protocol_error)
+}
+
+fn describe(definition: &Definition) -> String {
+    match (&definition.signature, definition.kind) {
+        (Some(signature), _) => signature.clone(),
+        (None, SymbolKind::Function) => format!("fun {}", definition.name),
+        (None, SymbolKind::Variable) => format!("var {}", definition.name),
+        (None, SymbolKind::Parameter) => format!("(parameter) {}", definition.name),
+    }
+}
+
+fn native_signature(name: &str, arity: usize) -> String {
+    let params: Vec<String> = (1..=arity).map(|param| format!("arg{}", param)).collect();
+    format!("fun {}({}) // native", name, params.join(", "))
+}
+
+/// Symbols declared directly in the function, or at the top level.
+fn symbols(document: &Document, analysis: &Analysis, parent: Option<usize>) -> Vec<DocumentSymbol> {
+    analysis
+        .definitions
+        .iter()
+        .enumerate()
+        .filter(|(_, definition)| {
+            definition.parent == parent && definition.kind != SymbolKind::Parameter
+        })
+        .map(|(index, definition)| {
+            let is_function = definition.kind == SymbolKind::Function;
+            #[allow(deprecated)]
+            DocumentSymbol {
+                name: definition.name.clone(),
+                detail: definition.signature.clone(),
+                kind: if is_function {
+                    lsp_types::SymbolKind::FUNCTION
+                } else {
+                    lsp_types::SymbolKind::VARIABLE
+                },
+                tags: None,
+                deprecated: None,
+                range: document.range(&definition.full_range),
+                selection_range: document.range(&definition.range),
+                children: is_function.then(|| symbols(document, analysis, Some(index))),
+            }
+        })
+        .collect()
+}
+
+impl Document {
+    fn new(text: String, last_globals: Vec<Definition>) -> Self {
+        let lines = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(offset, _)| offset + 1))
+            .collect();
+        Document {
+            text,
+            lines,
+            analysis: None,
+            last_globals,
+        }
+    }
+
+    /// Positions count UTF-16 code units, as the protocol asks by default.
+    fn position(&self, offset: usize) -> Position {
+        let line = self.lines.partition_point(|&start| start <= offset) - 1;
+        let character = self.text[self.lines[line]..offset].encode_utf16().count();
+        Position::new(line as u32, character as u32)
+    }
+
+    fn offset(&self, position: Position) -> usize {
+        let start = match self.lines.get(position.line as usize) {
+            Some(&start) => start,
+            None => return self.text.len(),
+        };
+        let line = self.text[start..].split('\n').next().unwrap_or_default();
+
+        let mut units = 0;
+        for (offset, c) in line.char_indices() {
+            if units >= position.character as usize {
+                return start + offset;
+            }
+            units += c.len_utf16();
+        }
+        start + line.len()
+    }
+
+    fn range(&self, range: &Range<usize>) -> lsp_types::Range {
+        lsp_types::Range::new(self.position(range.start), self.position(range.end))
+    }
+}
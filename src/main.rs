@@ -1,55 +1,384 @@
-use std::io::Write;
+use std::{
+    io::{Read, Write},
+    path::PathBuf,
+    process::ExitCode,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
-use rlox::{interpreter::Interpreter, parser, scanner, Result};
+use rlox::{
+    cli::{self, Cli, Command, Input},
+    coverage::{Coverage, Lcov},
+    dap,
+    debugger::{Console, Script},
+    error::{exit_code, ErrorOrEarlyReturn, RuntimeError},
+    formatter::{self, FormatOptions},
+    interpreter::Interpreter,
+    lsp,
+    native::VirtualTimeSource,
+    parser,
+    profile::{ProfileFormat, Profiler},
+    repl,
+    scanner::{Scanner, Token},
+    trace::{TraceFormat, Tracer},
+    Result,
+};
+use rustyline::{error::ReadlineError, DefaultEditor};
+
+fn main() -> ExitCode {
+    let cli = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(cli) => cli,
+        Err(error) => {
+            eprintln!("Error: {}\n\n{}", error, cli::USAGE);
+            return ExitCode::from(exit_code::USAGE);
+        }
+    };
+
+    match cli.command {
+        Command::Fmt => return fmt(&cli),
+        Command::Lsp => return exit(lsp::run()),
+        Command::Dap => return exit(dap::run()),
+        _ => (),
+    }
 
-fn main() -> Result<()> {
-    let mut args = std::env::args();
     let stdout = &mut std::io::stdout();
-    let mut interpreter = Interpreter::new(stdout);
-    match args.len() {
-        1 => run_prompt(&mut interpreter),
-        2 => run_file(args.nth(1).unwrap().as_str(), &mut interpreter),
-        _ => {
-            println!("Usage: rlox [script]");
-            std::process::exit(64);
+    let mut interpreter = interpreter(stdout, &cli.args, cli.options.seed);
+
+    let src = match cli.input {
+        Some(ref input) => match read_input(input) {
+            Ok(src) => src,
+            Err(error) => {
+                eprintln!("Error: could not read {}: {}", input, error);
+                return ExitCode::from(exit_code::NO_INPUT);
+            }
+        },
+        None => return run_prompt(&mut interpreter),
+    };
+
+    let scanner = cli.options.scanner.unwrap_or_default().scanner();
+    let result = match cli.command {
+        Command::Repl | Command::Run => match &cli.options {
+            cli::Options {
+                trace: Some(format),
+                ..
+            } => trace(scanner, &src, &cli.args, cli.options.seed, *format),
+            cli::Options {
+                profile: Some(format),
+                ..
+            } => profile(scanner, &src, &cli.args, cli.options.seed, *format),
+            cli::Options {
+                coverage: Some(tracefile),
+                ..
+            } => {
+                let input = cli.input.as_ref().unwrap();
+                coverage(scanner, &src, input, &cli.args, cli.options.seed, tracefile)
+            }
+            _ => run(scanner, &src, &mut interpreter),
+        },
+        Command::Check => return check(scanner, &src),
+        Command::Tokens => repl::tokens(scanner, &src).map(|tokens| println!("{}", tokens)),
+        Command::Ast => repl::ast(scanner, &src).map(|ast| println!("{}", ast)),
+        Command::Debug => debug(scanner, &src, &cli.args),
+        Command::Fmt | Command::Lsp | Command::Dap => unreachable!(),
+    };
+
+    exit(result)
+}
+
+fn exit(result: Result<()>) -> ExitCode {
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            ExitCode::from(error.exit_code())
         }
     }
 }
 
-fn run_file(filename: &str, interpreter: &mut Interpreter) -> Result<()> {
-    let src = std::fs::read_to_string(filename).expect("Could not read file");
-    run(src.as_str(), interpreter)
+fn fmt(cli: &Cli) -> ExitCode {
+    let mut options = FormatOptions::default();
+    if let Some(width) = cli.options.width {
+        options.width = width;
+    }
+
+    let inputs = cli
+        .input
+        .iter()
+        .cloned()
+        .chain(cli.args.iter().cloned().map(Input::File));
+
+    let mut exit = ExitCode::SUCCESS;
+    for input in inputs {
+        let src = match read_input(&input) {
+            Ok(src) => src,
+            Err(error) => {
+                eprintln!("Error: could not read {}: {}", input, error);
+                return ExitCode::from(exit_code::NO_INPUT);
+            }
+        };
+
+        let formatted = match formatter::format(&src, &options) {
+            Ok(formatted) => formatted,
+            Err(error) => {
+                eprintln!("Error: could not format {}: {}", input, error);
+                return ExitCode::from(error.exit_code());
+            }
+        };
+
+        if cli.options.check {
+            if formatted != src {
+                println!("{} is not formatted", input);
+                exit = ExitCode::FAILURE;
+            }
+        } else if let (true, Input::File(filename)) = (cli.options.write, &input) {
+            if formatted != src {
+                if let Err(error) = std::fs::write(filename, formatted) {
+                    eprintln!("Error: could not write {}: {}", input, error);
+                    return ExitCode::from(exit_code::IO_ERROR);
+                }
+            }
+        } else {
+            print!("{}", formatted);
+        }
+    }
+
+    exit
+}
+
+fn read_input(input: &Input) -> std::io::Result<String> {
+    match input {
+        Input::File(filename) => std::fs::read_to_string(filename),
+        Input::Stdin => {
+            let mut src = String::new();
+            std::io::stdin().read_to_string(&mut src)?;
+            Ok(src)
+        }
+        Input::Inline(src) => Ok(src.clone()),
+    }
 }
 
-fn run_prompt(interpreter: &mut Interpreter) -> Result<()> {
+fn run_prompt(interpreter: &mut Interpreter) -> ExitCode {
+    let mut editor = match DefaultEditor::new() {
+        Ok(editor) => editor,
+        Err(error) => {
+            eprintln!("Error: could not start the prompt: {}", error);
+            return ExitCode::from(exit_code::IO_ERROR);
+        }
+    };
+
+    let history = history_file();
+    if let Some(ref history) = history {
+        // there is no history yet on the first run
+        let _ = editor.load_history(history);
+    }
+
+    // rustyline reads Ctrl-C as a key while it reads a line, so the signal only
+    // comes while the code runs, and stops the code instead of the session
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let flag = interrupted.clone();
+    if let Err(error) = ctrlc::set_handler(move || flag.store(true, Ordering::Relaxed)) {
+        eprintln!("Error: could not handle Ctrl-C: {}", error);
+    }
+    interpreter.set_interrupt(interrupted.clone());
+
     let mut src = String::new();
+    let mut exit = ExitCode::SUCCESS;
 
     loop {
-        print!("> ");
-        std::io::stdout().flush()?;
-
-        src.clear();
-        match std::io::stdin().read_line(&mut src) {
-            Ok(_) => {
-                let result = run(src.as_str(), interpreter);
-                match result {
-                    Ok(_value) => (),
+        let prompt = if src.is_empty() { "> " } else { ". " };
+
+        match editor.readline(prompt) {
+            Ok(line) => {
+                src.push_str(&line);
+                src.push('\n');
+
+                if !repl::is_complete(&src) {
+                    continue;
+                }
+
+                let _ = editor.add_history_entry(src.trim_end());
+                interrupted.store(false, Ordering::Relaxed);
+                match repl::execute(interpreter, &src) {
+                    Ok(Some(echo)) => println!("{}", echo),
+                    Ok(None) => (),
                     Err(error) => eprintln!("Error: {}", error),
                 }
+                src.clear();
+            }
+            // Ctrl-C drops what was typed so far, but keeps the session
+            Err(ReadlineError::Interrupted) => src.clear(),
+            // Ctrl-D
+            Err(ReadlineError::Eof) => break,
+            Err(error) => {
+                eprintln!("Error: {}", error);
+                exit = ExitCode::from(exit_code::IO_ERROR);
+                break;
             }
-            // TODO show error if return is not Ok
-            Err(_) => panic!("Input error"),
         }
-        println!();
     }
+
+    if let Some(ref history) = history {
+        if let Err(error) = editor.save_history(history) {
+            eprintln!("Error: could not save the history: {}", error);
+        }
+    }
+
+    exit
+}
+
+fn history_file() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".rlox_history"))
+}
+
+/// Scans, parses and resolves the script, reporting the problems the resolver finds.
+fn check(scanner: &dyn Scanner, src: &str) -> ExitCode {
+    let tokens = match scanner.scan_tokens(src) {
+        Ok(tokens) => tokens,
+        Err(error) => return exit(Err(error)),
+    };
+    let program = match parser::parse(&tokens) {
+        Ok(program) => program,
+        Err(error) => return exit(Err(error)),
+    };
+
+    for diagnostic in program.diagnostics() {
+        eprintln!(
+            "[line {}] Error: {}",
+            line_at(&tokens, diagnostic.range.start),
+            diagnostic.message
+        );
+    }
+    if program.diagnostics().is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(exit_code::DATA_ERROR)
+    }
+}
+
+/// Line of the token at the offset in the text of the tokens.
+fn line_at(tokens: &[Token], offset: usize) -> u32 {
+    let mut start = 0;
+    for token in tokens {
+        start += token.lexeme.len();
+        if start > offset {
+            return token.line;
+        }
+    }
+    tokens.last().map_or(1, |token| token.line)
+}
+
+/// Creates the interpreter of a run of the script. With a seed, `random()` and
+/// `clock()` give the same results on every run.
+fn interpreter<'stdout>(
+    stdout: &'stdout mut dyn Write,
+    args: &[String],
+    seed: Option<u64>,
+) -> Interpreter<'stdout> {
+    let mut interpreter = Interpreter::new(stdout);
+    interpreter.set_args(args.to_vec());
+    if let Some(seed) = seed {
+        interpreter.seed_random(seed);
+        interpreter.set_time_source(Box::new(VirtualTimeSource::new(0.0)));
+    }
+    interpreter
+}
+
+fn debug(scanner: &dyn Scanner, src: &str, args: &[String]) -> Result<()> {
+    let script = Script::compile_with(scanner, src)?;
+    let mut console = Console::new(&script, std::io::stdin().lock(), std::io::stdout());
+    let stdout = &mut std::io::stdout();
+    let mut interpreter = interpreter(stdout, args, None);
+    interpreter.set_debugger(&mut console);
+
+    match script.run(&mut interpreter) {
+        // the user quit the debugger
+        Err(ErrorOrEarlyReturn::RuntimeError(RuntimeError::Terminated)) => Ok(()),
+        result => result,
+    }
+}
+
+/// Runs the script, tracing it on the standard error.
+fn trace(
+    scanner: &dyn Scanner,
+    src: &str,
+    args: &[String],
+    seed: Option<u64>,
+    format: TraceFormat,
+) -> Result<()> {
+    let script = Script::compile_with(scanner, src)?;
+    let mut tracer = Tracer::new(&script, std::io::stderr().lock(), format);
+    let stdout = &mut std::io::stdout();
+    let mut interpreter = interpreter(stdout, args, seed);
+    interpreter.set_debugger(&mut tracer);
+    script.run(&mut interpreter)
+}
+
+/// Runs the script, then reports the profile on the standard error, even if it failed.
+fn profile(
+    scanner: &dyn Scanner,
+    src: &str,
+    args: &[String],
+    seed: Option<u64>,
+    format: ProfileFormat,
+) -> Result<()> {
+    let script = Script::compile_with(scanner, src)?;
+    let mut profiler = Profiler::new(&script);
+    let result = {
+        let stdout = &mut std::io::stdout();
+        let mut interpreter = interpreter(stdout, args, seed);
+        interpreter.set_debugger(&mut profiler);
+        script.run(&mut interpreter)
+    };
+
+    profiler.finish()?;
+    profiler.write(format, &mut std::io::stderr().lock())?;
+    result
+}
+
+/// Runs the script, then adds its coverage to the tracefile, even if it failed.
+fn coverage(
+    scanner: &dyn Scanner,
+    src: &str,
+    input: &Input,
+    args: &[String],
+    seed: Option<u64>,
+    tracefile: &str,
+) -> Result<()> {
+    let script = Script::compile_with(scanner, src)?;
+    let mut coverage = Coverage::new(&script);
+    let result = {
+        let stdout = &mut std::io::stdout();
+        let mut interpreter = interpreter(stdout, args, seed);
+        interpreter.set_debugger(&mut coverage);
+        script.run(&mut interpreter)
+    };
+
+    // merged with the previous runs
+    let mut lcov = match std::fs::read_to_string(tracefile) {
+        Ok(previous) => Lcov::parse(&previous)?,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Lcov::default(),
+        Err(error) => return Err(error.into()),
+    };
+    let path = match input {
+        Input::File(filename) => filename.as_str(),
+        Input::Stdin => "<stdin>",
+        Input::Inline(_) => "<inline>",
+    };
+    lcov.add(path, coverage.report());
+
+    std::fs::write(tracefile, lcov.to_string())?;
+    lcov.write_summary(&mut std::io::stderr().lock())?;
+    result
 }
 
-fn run(src: &str, interpreter: &mut Interpreter) -> Result<()> {
-    let tokens = scanner::scan_tokens(src)?;
-    let statements = parser::parse(&tokens)?;
+fn run(scanner: &dyn Scanner, src: &str, interpreter: &mut Interpreter) -> Result<()> {
+    let tokens = scanner.scan_tokens(src)?;
+    let program = parser::parse(&tokens)?;
 
-    for stmt in statements {
-        interpreter.exec_stmt(&stmt)?;
+    for stmt in program.statements() {
+        interpreter.exec_stmt(stmt)?;
     }
 
     Ok(())
@@ -0,0 +1,151 @@
+use std::{
+    cell::Cell,
+    rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    environment::{NativeFunction, Value},
+    error::{runtime_error, RuntimeError},
+    Result,
+};
+
+/// Source of the time returned by the `clock()` native function, in seconds.
+pub trait TimeSource {
+    fn now(&self) -> Result<f64>;
+}
+
+/// Reads the wall clock of the host system.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemTimeSource;
+
+impl TimeSource for SystemTimeSource {
+    fn now(&self) -> Result<f64> {
+        match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(duration) => Ok(duration.as_millis() as f64 / 1000.0),
+            Err(_) => Err(runtime_error(RuntimeError::GenericError(
+                "Time went backwards when calling native function `clock`.".into(),
+            ))),
+        }
+    }
+}
+
+/// Time that only advances when the host says so.
+///
+/// Clones share the same time, so the host can keep a handle and advance the
+/// clock of an interpreter that owns another clone.
+#[derive(Debug, Default, Clone)]
+pub struct VirtualTimeSource {
+    now: Rc<Cell<f64>>,
+}
+
+impl VirtualTimeSource {
+    pub fn new(start: f64) -> Self {
+        VirtualTimeSource {
+            now: Rc::new(Cell::new(start)),
+        }
+    }
+
+    pub fn advance(&self, seconds: f64) {
+        self.now.set(self.now.get() + seconds);
+    }
+
+    pub fn set(&self, seconds: f64) {
+        self.now.set(seconds);
+    }
+}
+
+impl TimeSource for VirtualTimeSource {
+    fn now(&self) -> Result<f64> {
+        Ok(self.now.get())
+    }
+}
+
+/// Seedable pseudo random number generator (SplitMix64) used by `random()`.
+///
+/// The same seed always produces the same sequence, on every platform.
+#[derive(Debug, Clone)]
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+    pub fn with_seed(seed: u64) -> Self {
+        Random { state: seed }
+    }
+
+    /// Seeds the generator from the system time.
+    pub fn from_entropy() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_nanos() as u64)
+            .unwrap_or_default();
+        Random::with_seed(nanos)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number in the range [0, 1).
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Services of the host available to native functions.
+pub struct Host {
+    pub time: Box<dyn TimeSource>,
+    pub random: Random,
+    /// Arguments passed to the script in the command line.
+    pub args: Vec<String>,
+}
+
+impl Default for Host {
+    fn default() -> Self {
+        Host {
+            time: Box::new(SystemTimeSource),
+            random: Random::from_entropy(),
+            args: vec![],
+        }
+    }
+}
+
+/// Functions implemented by the interpreter and defined in the global environment.
+pub fn functions() -> [NativeFunction; 4] {
+    [
+        NativeFunction {
+            name: "clock",
+            arity: 0,
+            exec: |host, _args| Ok(Value::Number(host.time.now()?)),
+        },
+        NativeFunction {
+            name: "random",
+            arity: 0,
+            exec: |host, _args| Ok(Value::Number(host.random.next_f64())),
+        },
+        NativeFunction {
+            name: "argc",
+            arity: 0,
+            exec: |host, _args| Ok(Value::Number(host.args.len() as f64)),
+        },
+        NativeFunction {
+            name: "argv",
+            arity: 1,
+            exec: |host, args| match &args[0] {
+                Value::Number(n) if n.fract() == 0.0 && *n >= 0.0 => Ok(host
+                    .args
+                    .get(*n as usize)
+                    .map_or(Value::Nil, |arg| Value::String(arg.clone()))),
+                other => Err(runtime_error(RuntimeError::TypeMismatch(
+                    "non-negative integer".into(),
+                    format!("{}", other),
+                ))),
+            },
+        },
+    ]
+}
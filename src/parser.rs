@@ -1,64 +1,311 @@
-use std::rc::Rc;
+use std::collections::VecDeque;
 
 use crate::{
     ast::*,
-    error::{compilation_error, ice, CompilationError, ErrorOrEarlyReturn, ICE},
+    cst::{is_trivia, Checkpoint, GreenElement, GreenNodeBuilder, NodeKind, SyntaxNode},
+    error::{compilation_error, CompilationError, ErrorOrEarlyReturn},
+    lower,
     scanner::{Token, TokenType, TokenType::*},
-    Result,
+    with_stack, Result,
 };
 
+/// Maximum nesting of statements and expressions accepted by the parser.
+///
+/// Trees are built, compared and dropped recursively, so this bounds how deep
+/// the native stack can get outside of the parser and the interpreter. Chains
+/// of left associative operators, as in `a + a + …`, do not count: they are
+/// parsed in a loop, and their expressions handle deep trees.
+pub const MAX_NESTING_DEPTH: usize = 256;
+
+/// Parses an operator and its right operand, if any. Gets the checkpoint before the
+/// left operand, or before the operator when there is none, and the binding power of
+/// the right operand.
+type Parselet = fn(&mut Parser, Checkpoint, u8) -> Result<()>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Associativity {
+    Left,
+    Right,
+}
+
+/// An operator of the expression grammar, in the tables below.
+struct Operator {
+    token: TokenType,
+    /// The higher, the tighter the operator binds.
+    precedence: u8,
+    associativity: Associativity,
+    parselet: Parselet,
+}
+
+impl Operator {
+    const fn new(
+        token: TokenType,
+        precedence: u8,
+        associativity: Associativity,
+        parselet: Parselet,
+    ) -> Self {
+        Operator {
+            token,
+            precedence,
+            associativity,
+            parselet,
+        }
+    }
+
+    /// Binding powers on the left and on the right of the operator.
+    ///
+    /// The side with the higher power wins an operand between two operators of
+    /// the same precedence.
+    fn binding_power(&self) -> (u8, u8) {
+        let power = self.precedence * 2;
+        match self.associativity {
+            Associativity::Left => (power, power + 1),
+            Associativity::Right => (power + 1, power),
+        }
+    }
+}
+
+/// Operators before their operand.
+const PREFIX: &[Operator] = &[
+    Operator::new(Bang, 8, Associativity::Right, unary),
+    Operator::new(Minus, 8, Associativity::Right, unary),
+];
+
+/// Operators between their operands.
+const INFIX: &[Operator] = &[
+    Operator::new(Equal, 1, Associativity::Right, assignment),
+    Operator::new(Or, 2, Associativity::Left, binary),
+    Operator::new(And, 3, Associativity::Left, binary),
+    Operator::new(EqualEqual, 4, Associativity::Left, binary),
+    Operator::new(BangEqual, 4, Associativity::Left, binary),
+    Operator::new(Greater, 5, Associativity::Left, binary),
+    Operator::new(GreaterEqual, 5, Associativity::Left, binary),
+    Operator::new(Less, 5, Associativity::Left, binary),
+    Operator::new(LessEqual, 5, Associativity::Left, binary),
+    Operator::new(Plus, 6, Associativity::Left, binary),
+    Operator::new(Minus, 6, Associativity::Left, binary),
+    Operator::new(Star, 7, Associativity::Left, binary),
+    Operator::new(Slash, 7, Associativity::Left, binary),
+];
+
+/// Operators after their operand.
+const POSTFIX: &[Operator] = &[Operator::new(LeftParen, 9, Associativity::Left, call)];
+
 pub struct Parser<'tokens> {
-    tokens: &'tokens Vec<Token<'tokens>>,
+    source: Box<dyn Iterator<Item = Result<Token<'tokens>>> + 'tokens>,
+    /// Tokens pulled from `source` but not yet added to the tree, from `emitted`
+    /// up to the next token that is not trivia.
+    tokens: VecDeque<Token<'tokens>>,
+    /// The error `source` ended with, and the index of the token it replaces.
+    error: Option<(ErrorOrEarlyReturn, usize)>,
+    /// Number of tokens pulled from `source`.
+    pulled: usize,
+    /// Index of the next token that is not trivia.
     next: usize,
+    /// Index of the first token not yet added to the tree.
+    emitted: usize,
+    builder: GreenNodeBuilder,
     inside_function: u32,
+    depth: usize,
+}
+
+/// Parses the tokens into a `Program`. Its `file` is not known here; use
+/// `SourceMap::parse` to have it set.
+pub fn parse(tokens: &[Token]) -> Result<Program> {
+    parse_iter(tokens.iter().copied().map(Ok))
+}
+
+/// Parses the tokens into a `Program`, pulling them one at a time, as
+/// `scanner::tokens` scans them.
+///
+/// A scan error is only reported once the parser gets to it, so a syntax error
+/// before it is reported instead.
+pub fn parse_iter<'tokens>(
+    tokens: impl Iterator<Item = Result<Token<'tokens>>> + 'tokens,
+) -> Result<Program> {
+    let root = Parser::new(Box::new(tokens))
+        .cst()
+        .map_err(|failure| failure.error)?;
+    Ok(Program::new(lower::program(&root)?, root))
+}
+
+/// Parses the tokens into a concrete syntax tree, with a `Program` root.
+///
+/// Comments and whitespace are kept in the tree when they are in `tokens`, as
+/// returned by `scanner::scan_tokens_lossless`.
+pub fn parse_cst(tokens: &[Token]) -> Result<SyntaxNode> {
+    try_parse_cst(tokens).map_err(|failure| failure.error)
+}
+
+/// A parse error together with where it was found.
+#[derive(Debug)]
+pub struct ParseFailure {
+    pub error: ErrorOrEarlyReturn,
+    /// Index in the tokens of the token the parser was looking at.
+    pub token: usize,
 }
 
-pub fn parse(tokens: &Vec<Token>) -> Result<Vec<Statement>> {
-    let mut parser = Parser::new(tokens);
-    parser.declarations()
+/// Like `parse_cst`, but tells where parsing failed.
+pub fn try_parse_cst(tokens: &[Token]) -> std::result::Result<SyntaxNode, ParseFailure> {
+    Parser::new(Box::new(tokens.iter().copied().map(Ok))).cst()
 }
 
-pub fn parse_expr(tokens: &Vec<Token>) -> Result<Expr> {
-    let mut parser = Parser::new(tokens);
-    parser.equality_expr()
+/// Parses a single expression, which must be all there is in the tokens.
+pub fn parse_expr(tokens: &[Token]) -> Result<Expr> {
+    let mut parser = Parser::new(Box::new(tokens.iter().copied().map(Ok)));
+    // the expression is the root of the tree, so there is nowhere to put leading trivia
+    parser.tokens.drain(..parser.next);
+    parser.emitted = parser.next;
+    parser.expr()?;
+
+    if !parser.is_at_end() {
+        return Err(compilation_error(CompilationError::GenericError(format!(
+            "Unexpected '{}' after the expression",
+            parser.peek().unwrap().lexeme
+        ))));
+    }
+    lower::expr(&SyntaxNode::new_root(parser.builder.finish()))
 }
 
 impl<'tokens> Parser<'tokens> {
-    fn new(tokens: &'tokens Vec<Token>) -> Parser<'tokens> {
-        Parser {
-            tokens,
+    fn new(source: Box<dyn Iterator<Item = Result<Token<'tokens>>> + 'tokens>) -> Self {
+        let mut parser = Parser {
+            source,
+            tokens: VecDeque::new(),
+            error: None,
+            pulled: 0,
             next: 0,
+            emitted: 0,
+            builder: GreenNodeBuilder::new(),
             inside_function: 0,
+            depth: 0,
+        };
+        parser.skip_trivia();
+        parser
+    }
+
+    /// Goes one nesting level deeper, failing if the tree would get too deep.
+    fn enter(&mut self) -> Result<()> {
+        if self.depth >= MAX_NESTING_DEPTH {
+            return Err(compilation_error(CompilationError::TooDeeplyNested(
+                MAX_NESTING_DEPTH,
+            )));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    /// Runs `parse` one nesting level deeper.
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        self.enter()?;
+        let result = with_stack(|| parse(self));
+        self.depth -= 1;
+        result
+    }
+
+    /// Runs `parse` inside a new node of the tree.
+    fn node(&mut self, kind: NodeKind, parse: impl FnOnce(&mut Self) -> Result<()>) -> Result<()> {
+        // trivia before the first token goes to the parent
+        self.flush_trivia();
+        self.builder.start_node(kind);
+        parse(self)?;
+        self.builder.finish_node();
+        Ok(())
+    }
+
+    /// Marks where a node that wraps what is parsed next may start.
+    fn checkpoint(&mut self) -> Checkpoint {
+        self.flush_trivia();
+        self.builder.checkpoint()
+    }
+
+    /// Parses a whole program, reporting the scan error the tokens ended with
+    /// if the parser got to it.
+    fn cst(mut self) -> std::result::Result<SyntaxNode, ParseFailure> {
+        let result = self.program();
+        let error = match (result, self.error.take()) {
+            // the parser got to the scan error, so it is the one to blame
+            (result, Some((error, index))) if result.is_ok() || self.next >= index => error,
+            (Ok(()), _) => return Ok(SyntaxNode::new_root(self.builder.finish())),
+            (Err(error), _) => error,
+        };
+        Err(ParseFailure {
+            error,
+            token: self.next,
+        })
+    }
+
+    /// The token at the index, pulling tokens from the source up to it.
+    fn pull(&mut self, index: usize) -> Option<Token<'tokens>> {
+        while self.pulled <= index && self.error.is_none() {
+            match self.source.next() {
+                Some(Ok(token)) => {
+                    self.tokens.push_back(token);
+                    self.pulled += 1;
+                }
+                Some(Err(error)) => self.error = Some((error, self.pulled)),
+                None => break,
+            }
+        }
+        self.token(index)
+    }
+
+    /// The token at the index, if it was pulled and is not in the tree yet.
+    fn token(&self, index: usize) -> Option<Token<'tokens>> {
+        self.tokens.get(index.checked_sub(self.emitted)?).copied()
+    }
+
+    fn skip_trivia(&mut self) {
+        while matches!(self.pull(self.next), Some(token) if is_trivia(token.type_)) {
+            self.next += 1;
+        }
+    }
+
+    /// Adds the trivia before the next token to the tree.
+    fn flush_trivia(&mut self) {
+        while self.emitted < self.next {
+            let token = self.tokens.pop_front().unwrap();
+            self.builder.token(token.type_, token.lexeme);
+            self.emitted += 1;
         }
     }
 
+    /// Adds the next token to the tree and moves past it.
+    fn bump(&mut self) -> Token<'tokens> {
+        self.flush_trivia();
+        let token = self.tokens.pop_front().unwrap();
+        self.builder.token(token.type_, token.lexeme);
+        self.next += 1;
+        self.emitted = self.next;
+        self.skip_trivia();
+        token
+    }
+
     fn matches(&mut self, token_type: TokenType) -> bool {
-        if let Some(token) = self.tokens.get(self.next) {
-            if token.type_ == token_type {
-                self.next += 1;
-                return true;
-            }
+        if self.check(token_type) {
+            self.bump();
+            return true;
         }
         false
     }
 
     fn check(&self, token_type: TokenType) -> bool {
-        matches!(self.tokens.get(self.next), Some(Token { type_, ..}) if *type_ == token_type)
+        matches!(self.peek(), Some(Token { type_, ..}) if type_ == token_type)
     }
 
-    fn consume(&mut self, token_type: TokenType) -> Result<&Token> {
+    fn consume(&mut self, token_type: TokenType) -> Result<Token<'tokens>> {
         if self.is_at_end() {
             Err(compilation_error(CompilationError::ExpectedToken(
                 format!("{:?}", token_type),
                 "Eof".into(),
             )))
-        } else if !self.matches(token_type) {
+        } else if !self.check(token_type) {
             Err(compilation_error(CompilationError::ExpectedToken(
                 format!("{:?}", token_type),
-                self.peek().unwrap().into(),
+                (&self.peek().unwrap()).into(),
             )))
         } else {
-            self.previous()
+            Ok(self.bump())
         }
     }
 
@@ -66,421 +313,293 @@ impl<'tokens> Parser<'tokens> {
         &mut self,
         token_type: TokenType,
         error: ErrorOrEarlyReturn,
-    ) -> Result<&Token> {
-        if !self.matches(token_type) {
+    ) -> Result<Token<'tokens>> {
+        if !self.check(token_type) {
             Err(error)
         } else {
-            self.previous()
+            Ok(self.bump())
         }
     }
 
-    fn peek(&self) -> Option<&Token> {
-        self.tokens.get(self.next)
-    }
-
-    fn previous(&self) -> Result<&Token> {
-        if let Some(token) = self.tokens.get(self.next - 1) {
-            Ok(token)
-        } else {
-            Err(ice(ICE::Generic("Could not get previous token".into())))
-        }
+    fn peek(&self) -> Option<Token<'tokens>> {
+        self.token(self.next)
     }
 
     fn is_at_end(&self) -> bool {
-        matches!(
-            self.tokens.get(self.next),
-            Some(&Token { type_: Eof, .. }) | None
-        )
+        matches!(self.peek(), Some(Token { type_: Eof, .. }) | None)
     }
 
-    fn declarations(&mut self) -> Result<Vec<Statement>> {
-        let mut decls = Vec::new();
+    fn program(&mut self) -> Result<()> {
+        self.builder.start_node(NodeKind::Program);
 
         while !self.is_at_end() {
-            decls.push(self.declaration()?);
+            self.declaration()?;
+        }
+
+        // only trivia and `Eof` are left
+        while self.pull(self.next).is_some() {
+            self.next += 1;
         }
+        self.flush_trivia();
 
-        Ok(decls)
+        self.builder.finish_node();
+        Ok(())
     }
 
-    fn declaration(&mut self) -> Result<Statement> {
-        if self.matches(Var) {
+    fn declaration(&mut self) -> Result<()> {
+        if self.check(Var) {
             self.var_declaration()
-        } else if self.matches(Fun) {
+        } else if self.check(Fun) {
             self.fun_declaration()
         } else {
             self.statement()
         }
     }
 
-    fn var_declaration(&mut self) -> Result<Statement> {
-        let name = self
-            .consume_or_error(
+    fn var_declaration(&mut self) -> Result<()> {
+        self.node(NodeKind::VarDecl, |parser| {
+            parser.consume(Var)?;
+            parser.consume_or_error(
                 Identifier,
                 compilation_error(CompilationError::ExpectedNameAfterVar),
-            )?
-            .lexeme
-            .into();
+            )?;
 
-        let mut initializer = None;
-        if self.matches(Equal) {
-            initializer = Some(self.expr()?);
-        }
-
-        self.consume_or_error(
-            Semicolon,
-            compilation_error(CompilationError::ExpectedSemicolonAfterVarDecl),
-        )?;
+            if parser.matches(Equal) {
+                parser.expr()?;
+            }
 
-        Ok(Statement::VariableDecl(name, initializer))
+            parser.consume_or_error(
+                Semicolon,
+                compilation_error(CompilationError::ExpectedSemicolonAfterVarDecl),
+            )?;
+            Ok(())
+        })
     }
 
-    fn fun_declaration(&mut self) -> Result<Statement> {
-        // function name
+    fn fun_declaration(&mut self) -> Result<()> {
+        self.node(NodeKind::FunDecl, |parser| {
+            parser.consume(Fun)?;
 
-        let name = self.consume(Identifier)?.lexeme.to_owned();
+            // function name
 
-        // parameters
+            parser.consume(Identifier)?;
 
-        self.consume(LeftParen)?;
-        let mut params = vec![];
+            // parameters
 
-        while !self.check(RightParen) {
-            params.push(self.consume(Identifier)?.lexeme.to_owned());
+            parser.node(NodeKind::ParamList, |parser| {
+                parser.consume(LeftParen)?;
+                let mut params = 0;
 
-            if params.len() > 256 {
-                return Err(compilation_error(CompilationError::GenericError(
-                    "Can't have more than 256 arguments in a function definition.".into(),
-                )));
-            }
+                while !parser.check(RightParen) {
+                    parser.consume(Identifier)?;
+                    params += 1;
 
-            if !self.matches(Comma) {
-                break;
-            }
-        }
+                    if params > 256 {
+                        return Err(compilation_error(CompilationError::GenericError(
+                            "Can't have more than 256 arguments in a function definition.".into(),
+                        )));
+                    }
 
-        self.consume(RightParen)?;
+                    if !parser.matches(Comma) {
+                        break;
+                    }
+                }
 
-        // function body
+                parser.consume(RightParen)?;
+                Ok(())
+            })?;
 
-        self.consume(LeftBrace)?;
-        self.inside_function += 1;
+            // function body
 
-        let mut body = vec![];
-        while !self.check(RightBrace) && !self.is_at_end() {
-            let maybe_stmt = self.declaration();
-            match maybe_stmt {
-                Ok(stmt) => body.push(stmt),
-                Err(err) => {
-                    self.inside_function -= 1;
-                    return Err(err);
+            parser.inside_function += 1;
+            let body = parser.node(NodeKind::Block, |parser| {
+                parser.consume(LeftBrace)?;
+                while !parser.check(RightBrace) && !parser.is_at_end() {
+                    parser.declaration()?;
                 }
+                parser.consume(RightBrace)?;
+                Ok(())
+            });
+            parser.inside_function -= 1;
+            body
+        })
+    }
+
+    fn statement(&mut self) -> Result<()> {
+        self.nested(|parser| match parser.peek().map(|token| token.type_) {
+            Some(If) => parser.if_stmt(),
+            Some(Print) => parser.print_stmt(),
+            Some(LeftBrace) => parser.block_stmt(),
+            Some(While) => parser.while_stmt(),
+            Some(For) => parser.for_stmt(),
+            Some(Return) => parser.return_stmt(),
+            _ => parser.expr_stmt(),
+        })
+    }
+
+    fn if_stmt(&mut self) -> Result<()> {
+        self.node(NodeKind::If, |parser| {
+            parser.consume(If)?;
+            parser.consume(LeftParen)?;
+            parser.expr()?;
+            parser.consume(RightParen)?;
+
+            parser.statement()?;
+
+            if parser.matches(Else) {
+                parser.statement()?;
+            }
+            Ok(())
+        })
+    }
+
+    fn print_stmt(&mut self) -> Result<()> {
+        self.node(NodeKind::Print, |parser| {
+            parser.consume(Print)?;
+            parser.expr()?;
+            parser.consume_or_error(
+                Semicolon,
+                compilation_error(CompilationError::GenericError(
+                    "Expected ';' after value.".into(),
+                )),
+            )?; // TODO create specific error
+            Ok(())
+        })
+    }
+
+    fn expr_stmt(&mut self) -> Result<()> {
+        self.node(NodeKind::ExprStmt, |parser| {
+            parser.expr()?;
+            parser.consume_or_error(
+                Semicolon,
+                compilation_error(CompilationError::GenericError(
+                    "Expected ';' after expression.".into(),
+                )),
+            )?; // TODO create specific error
+            Ok(())
+        })
+    }
+
+    fn block_stmt(&mut self) -> Result<()> {
+        self.node(NodeKind::Block, |parser| {
+            parser.consume(LeftBrace)?;
+
+            while !parser.check(RightBrace) && !parser.is_at_end() {
+                parser.declaration()?;
             }
-        }
-
-        self.consume(RightBrace)?;
-        self.inside_function -= 1;
-
-        Ok(Statement::FunDecl(Rc::new(FunctionDecl {
-            name,
-            params,
-            body,
-        })))
-    }
-
-    fn statement(&mut self) -> Result<Statement> {
-        if self.matches(If) {
-            self.if_stmt()
-        } else if self.matches(Print) {
-            self.print_stmt()
-        } else if self.matches(LeftBrace) {
-            self.block_stmt()
-        } else if self.matches(While) {
-            self.while_stmt()
-        } else if self.matches(For) {
-            self.for_stmt()
-        } else if self.matches(Return) {
-            self.return_stmt()
-        } else {
-            self.expr_stmt()
-        }
-    }
-
-    fn if_stmt(&mut self) -> Result<Statement> {
-        self.consume(LeftParen)?;
-        let cond = self.expr()?;
-        self.consume(RightParen)?;
-
-        let then_branch = Box::new(self.statement()?);
-
-        let mut else_branch = None;
-        if self.matches(Else) {
-            else_branch = Some(Box::new(self.statement()?));
-        }
-
-        Ok(Statement::If(IfStatement {
-            cond,
-            then_branch,
-            else_branch,
-        }))
-    }
-
-    fn print_stmt(&mut self) -> Result<Statement> {
-        let value = self.expr()?;
-        self.consume_or_error(
-            Semicolon,
-            compilation_error(CompilationError::GenericError(
-                "Expected ';' after value.".into(),
-            )),
-        )?; // TODO create specific error
-        Ok(Statement::Print(value))
-    }
-
-    fn expr_stmt(&mut self) -> Result<Statement> {
-        let expr = self.expr()?;
-        self.consume_or_error(
-            Semicolon,
-            compilation_error(CompilationError::GenericError(
-                "Expected ';' after expression.".into(),
-            )),
-        )?; // TODO create specific error
-        Ok(Statement::Expr(expr))
-    }
-
-    fn block_stmt(&mut self) -> Result<Statement> {
-        let mut statements = Vec::new();
-
-        while !self.check(RightBrace) && !self.is_at_end() {
-            statements.push(self.declaration()?);
-        }
-
-        self.consume_or_error(
-            RightBrace,
-            compilation_error(CompilationError::GenericError(
-                "Expected '}' at end of block.".into(),
-            )),
-        )?; // TODO create specific error
 
-        Ok(Statement::Block(statements))
+            parser.consume_or_error(
+                RightBrace,
+                compilation_error(CompilationError::GenericError(
+                    "Expected '}' at end of block.".into(),
+                )),
+            )?; // TODO create specific error
+            Ok(())
+        })
     }
 
-    fn while_stmt(&mut self) -> Result<Statement> {
-        self.consume(LeftParen)?;
-        let cond = self.expr()?;
-        self.consume(RightParen)?;
-
-        let stmt = Box::new(self.statement()?);
+    fn while_stmt(&mut self) -> Result<()> {
+        self.node(NodeKind::While, |parser| {
+            parser.consume(While)?;
+            parser.consume(LeftParen)?;
+            parser.expr()?;
+            parser.consume(RightParen)?;
 
-        Ok(Statement::While(WhileStatement { cond, stmt }))
+            parser.statement()
+        })
     }
 
-    fn for_stmt(&mut self) -> Result<Statement> {
-        self.consume(LeftParen)?;
-
-        // initialization clause
-
-        let initialization = if self.matches(Semicolon) {
-            None
-        } else if self.matches(Var) {
-            Some(self.var_declaration()?)
-        } else {
-            Some(self.expr_stmt()?)
-        };
-
-        // condition clause
-
-        let condition = if self.check(Semicolon) {
-            None
-        } else {
-            Some(self.expr()?)
-        };
-        self.consume(Semicolon)?;
-
-        // increment clause
-
-        let increment = if self.check(RightParen) {
-            None
-        } else {
-            Some(Statement::Expr(self.expr()?))
-        };
-        self.consume(RightParen)?;
-
-        // looped statement
-
-        let body = self.statement()?;
+    fn for_stmt(&mut self) -> Result<()> {
+        self.node(NodeKind::For, |parser| {
+            parser.consume(For)?;
+            parser.consume(LeftParen)?;
 
-        // desugar into a while loop
+            // initialization clause
 
-        let mut while_body = vec![body];
-        while_body.extend(increment.into_iter());
+            if parser.matches(Semicolon) {
+            } else if parser.check(Var) {
+                parser.var_declaration()?;
+            } else {
+                parser.expr_stmt()?;
+            }
 
-        let mut gen_body = vec![];
-        gen_body.extend(initialization.into_iter());
-        gen_body.push(Statement::While(WhileStatement {
-            cond: condition.unwrap_or(Expr::Literal(LiteralExpr::Boolean(true))),
-            stmt: Box::new(Statement::Block(while_body)),
-        }));
+            // condition clause
 
-        Ok(Statement::Block(gen_body))
-    }
+            if !parser.check(Semicolon) {
+                parser.node(NodeKind::ForCondition, Self::expr)?;
+            }
+            parser.consume(Semicolon)?;
 
-    fn return_stmt(&mut self) -> Result<Statement> {
-        let expr = if self.check(Semicolon) {
-            None
-        } else {
-            Some(self.expr()?)
-        };
+            // increment clause
 
-        self.consume(Semicolon)?;
+            if !parser.check(RightParen) {
+                parser.node(NodeKind::ForIncrement, Self::expr)?;
+            }
+            parser.consume(RightParen)?;
 
-        if self.inside_function > 0 {
-            Ok(Statement::Return(expr))
-        } else {
-            Err(compilation_error(CompilationError::ReturnOutsideFunction))
-        }
-    }
+            // looped statement
 
-    fn expr(&mut self) -> Result<Expr> {
-        self.assignment_expr()
+            parser.statement()
+        })
     }
 
-    fn assignment_expr(&mut self) -> Result<Expr> {
-        let expr = self.or_expr()?;
+    fn return_stmt(&mut self) -> Result<()> {
+        self.node(NodeKind::Return, |parser| {
+            parser.consume(Return)?;
 
-        if self.matches(Equal) {
-            match expr {
-                Expr::Identifier(ref name) => {
-                    let rvalue = self.assignment_expr()?;
-                    return Ok(Expr::Assignment(name.clone(), Box::new(rvalue)));
-                }
-                _ => {
-                    return Err(compilation_error(CompilationError::GenericError(format!(
-                        "Invalid assignment target {:?}",
-                        expr
-                    ))))
-                } // TODO create specific error
+            if !parser.check(Semicolon) {
+                parser.expr()?;
             }
-        }
-
-        Ok(expr)
-    }
-
-    fn or_expr(&mut self) -> Result<Expr> {
-        let mut expr = self.and_expr()?;
-
-        while self.matches(Or) {
-            expr = Expr::Binary(BinaryExpr {
-                left: Box::new(expr),
-                op: Or,
-                right: Box::new(self.and_expr()?),
-            });
-        }
 
-        Ok(expr)
-    }
-
-    fn and_expr(&mut self) -> Result<Expr> {
-        let mut expr = self.equality_expr()?;
-
-        while self.matches(And) {
-            expr = Expr::Binary(BinaryExpr {
-                left: Box::new(expr),
-                op: And,
-                right: Box::new(self.equality_expr()?),
-            });
-        }
+            parser.consume(Semicolon)?;
 
-        Ok(expr)
+            if parser.inside_function > 0 {
+                Ok(())
+            } else {
+                Err(compilation_error(CompilationError::ReturnOutsideFunction))
+            }
+        })
     }
 
-    fn equality_expr(&mut self) -> Result<Expr> {
-        let mut expr = self.comparison_expr()?;
-
-        while self.matches(EqualEqual) || self.matches(BangEqual) {
-            let op = self.previous()?;
-            expr = Expr::Binary(BinaryExpr {
-                left: Box::new(expr),
-                op: op.type_,
-                right: Box::new(self.comparison_expr()?),
-            });
-        }
-
-        Ok(expr)
+    fn expr(&mut self) -> Result<()> {
+        self.expr_bp(0)
     }
 
-    fn comparison_expr(&mut self) -> Result<Expr> {
-        let mut expr = self.term_expr()?;
-
-        while self.matches(Greater)
-            || self.matches(GreaterEqual)
-            || self.matches(Less)
-            || self.matches(LessEqual)
-        {
-            let op = self.previous()?;
-            expr = Expr::Binary(BinaryExpr {
-                left: Box::new(expr),
-                op: op.type_,
-                right: Box::new(self.term_expr()?),
-            });
-        }
-
-        Ok(expr)
+    /// Parses an expression whose operators bind at least as tightly as `min_power`.
+    fn expr_bp(&mut self, min_power: u8) -> Result<()> {
+        self.nested(|parser| parser.operators(min_power))
     }
 
-    fn term_expr(&mut self) -> Result<Expr> {
-        let mut expr = self.factor_expr()?;
+    fn operators(&mut self, min_power: u8) -> Result<()> {
+        let checkpoint = self.checkpoint();
 
-        while self.matches(Plus) || self.matches(Minus) {
-            let op = self.previous()?;
-            expr = Expr::Binary(BinaryExpr {
-                left: Box::new(expr),
-                op: op.type_,
-                right: Box::new(self.factor_expr()?),
-            });
+        match self.operator(PREFIX) {
+            Some(prefix) => (prefix.parselet)(self, checkpoint, prefix.binding_power().1)?,
+            None => self.primary_expr()?,
         }
 
-        Ok(expr)
-    }
-
-    fn factor_expr(&mut self) -> Result<Expr> {
-        let mut expr = self.unary_expr()?;
-
-        while self.matches(Star) || self.matches(Slash) {
-            let op = self.previous()?;
-            expr = Expr::Binary(BinaryExpr {
-                left: Box::new(expr),
-                op: op.type_,
-                right: Box::new(self.unary_expr()?),
-            });
+        while let Some(operator) = self.operator(POSTFIX).or_else(|| self.operator(INFIX)) {
+            let (left, right) = operator.binding_power();
+            if left < min_power {
+                break;
+            }
+            // the left leaning tree of a chain of operators is built in this loop,
+            // so the chain can be as long as it gets without nesting the parser
+            (operator.parselet)(self, checkpoint, right)?;
         }
 
-        Ok(expr)
+        Ok(())
     }
 
-    fn unary_expr(&mut self) -> Result<Expr> {
-        if self.matches(Bang) || self.matches(Minus) {
-            let op = self.previous()?.type_;
-            Ok(Expr::Unary(UnaryExpr {
-                op,
-                expr: Box::new(self.unary_expr()?),
-            }))
-        } else {
-            self.call_expr()
-        }
+    /// The operator of the table that the next token is.
+    fn operator(&self, table: &'static [Operator]) -> Option<&'static Operator> {
+        let type_ = self.peek()?.type_;
+        table.iter().find(|operator| operator.token == type_)
     }
 
-    fn call_expr(&mut self) -> Result<Expr> {
-        let mut expr = self.primary_expr()?;
-
-        while self.matches(LeftParen) {
-            expr = self.finish_call_expr(expr)?;
-        }
-
-        Ok(expr)
-    }
-
-    fn finish_call_expr(&mut self, callee: Expr) -> Result<Expr> {
-        let mut args = vec![];
+    fn call_args(&mut self) -> Result<()> {
+        self.consume(LeftParen)?;
+        let mut args = 0;
 
         if self.check(Comma) {
             return Err(compilation_error(CompilationError::GenericError(
@@ -490,9 +609,10 @@ impl<'tokens> Parser<'tokens> {
 
         if !self.check(RightParen) {
             loop {
-                args.push(self.expr()?);
+                self.expr()?;
+                args += 1;
 
-                if args.len() > 256 {
+                if args > 256 {
                     return Err(compilation_error(CompilationError::GenericError(
                         "Can't have more than 256 arguments in a function call.".into(),
                     )));
@@ -512,43 +632,76 @@ impl<'tokens> Parser<'tokens> {
         }
 
         self.consume(RightParen)?;
+        Ok(())
+    }
 
-        Ok(Expr::Call(CallExpr {
-            callee: Box::new(callee),
-            args,
-        }))
-    }
-
-    fn primary_expr(&mut self) -> Result<Expr> {
-        if self.matches(False) {
-            Ok(Expr::Literal(LiteralExpr::Boolean(false)))
-        } else if self.matches(True) {
-            Ok(Expr::Literal(LiteralExpr::Boolean(true)))
-        } else if self.matches(Nil) {
-            Ok(Expr::Literal(LiteralExpr::Nil))
-        } else if self.matches(String) {
-            let lexeme = self.previous()?.lexeme;
-            let string = lexeme[1..(lexeme.len() - 1)].into(); // remove the ""
-            Ok(Expr::Literal(LiteralExpr::String(string)))
-        } else if self.matches(NumberLiteral) {
-            let result = self.previous()?.lexeme.parse::<f64>();
-            match result {
-                Ok(number) => Ok(Expr::Literal(LiteralExpr::Number(number))),
-                Err(_) => Err(compilation_error(CompilationError::InvalidLiteral(
-                    "number".into(),
-                    self.previous()?.lexeme.into(),
-                ))),
+    fn primary_expr(&mut self) -> Result<()> {
+        match self.peek().map(|token| token.type_) {
+            Some(False | True | Nil | String | NumberLiteral) => {
+                self.node(NodeKind::Literal, |parser| {
+                    parser.bump();
+                    Ok(())
+                })
             }
-        } else if self.matches(Identifier) {
-            Ok(Expr::Identifier(self.previous()?.lexeme.into()))
-        } else if self.matches(LeftParen) {
-            let expr = self.assignment_expr()?;
-            self.consume(RightParen)?;
-            Ok(Expr::Grouping(Box::new(expr)))
-        } else {
-            Err(compilation_error(CompilationError::GenericError(
+            Some(Identifier) => self.node(NodeKind::Name, |parser| {
+                parser.bump();
+                Ok(())
+            }),
+            Some(LeftParen) => self.node(NodeKind::Paren, |parser| {
+                parser.bump();
+                parser.expr()?;
+                parser.consume(RightParen)?;
+                Ok(())
+            }),
+            _ => Err(compilation_error(CompilationError::GenericError(
                 "Expression expected".into(),
-            )))
+            ))),
         }
     }
 }
+
+// parselets of the operator tables
+
+fn unary(parser: &mut Parser, _operand: Checkpoint, right: u8) -> Result<()> {
+    parser.node(NodeKind::Unary, |parser| {
+        parser.bump();
+        parser.expr_bp(right)
+    })
+}
+
+fn binary(parser: &mut Parser, left: Checkpoint, right: u8) -> Result<()> {
+    parser.builder.start_node_at(left, NodeKind::Binary);
+    parser.bump();
+    parser.expr_bp(right)?;
+    parser.builder.finish_node();
+    Ok(())
+}
+
+fn assignment(parser: &mut Parser, target: Checkpoint, right: u8) -> Result<()> {
+    match parser.builder.last_child() {
+        Some(GreenElement::Node(target)) if target.kind() == NodeKind::Name => (),
+        target => {
+            let target = match target {
+                Some(GreenElement::Node(node)) => SyntaxNode::new_root(node.clone()).text(),
+                _ => std::string::String::new(),
+            };
+            return Err(compilation_error(CompilationError::GenericError(format!(
+                "Invalid assignment target '{}'",
+                target
+            )))); // TODO create specific error
+        }
+    }
+
+    parser.builder.start_node_at(target, NodeKind::Assignment);
+    parser.bump();
+    parser.expr_bp(right)?;
+    parser.builder.finish_node();
+    Ok(())
+}
+
+fn call(parser: &mut Parser, callee: Checkpoint, _right: u8) -> Result<()> {
+    parser.builder.start_node_at(callee, NodeKind::Call);
+    parser.node(NodeKind::ArgList, Parser::call_args)?;
+    parser.builder.finish_node();
+    Ok(())
+}
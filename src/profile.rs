@@ -0,0 +1,276 @@
+//! Instrumenting profiler: calls and time per function, and executions per line.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::Write,
+    time::Instant,
+};
+
+use crate::{
+    ast::{NodeId, Statement},
+    debugger::{Callee, Debugger, Script},
+    environment::Value,
+    interpreter::Interpreter,
+    native::TimeSource,
+    Result,
+};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum ProfileFormat {
+    /// Report of the functions and lines.
+    #[default]
+    Text,
+    /// Collapsed stacks, one per line, for flame graph tools.
+    Collapsed,
+}
+
+/// What was measured for one function.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionProfile {
+    pub name: String,
+    /// Line of the declaration, `None` for native functions.
+    pub line: Option<u32>,
+    pub calls: u64,
+    /// Seconds spent in the function, including the functions it called.
+    pub inclusive: f64,
+    /// Seconds spent in the function itself.
+    pub exclusive: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Key {
+    Function(NodeId),
+    Native(&'static str),
+}
+
+struct Frame {
+    function: usize,
+    start: f64,
+}
+
+/// Measures the script while it runs.
+pub struct Profiler<'script> {
+    script: &'script Script,
+    time: Box<dyn TimeSource>,
+    functions: Vec<FunctionProfile>,
+    keys: HashMap<Key, usize>,
+    /// Functions being called, the innermost last.
+    stack: Vec<Frame>,
+    start: Option<f64>,
+    /// Time of the last call or return.
+    last: Option<f64>,
+    /// Seconds spent in each call stack, not counting the functions it called.
+    stacks: HashMap<Vec<usize>, f64>,
+    /// Number of statements executed at each line.
+    lines: BTreeMap<u32, u64>,
+}
+
+/// Reads a monotonic clock, which is more precise than `clock()`.
+struct Stopwatch(Instant);
+
+impl TimeSource for Stopwatch {
+    fn now(&self) -> Result<f64> {
+        Ok(self.0.elapsed().as_secs_f64())
+    }
+}
+
+impl<'script> Profiler<'script> {
+    pub fn new(script: &'script Script) -> Self {
+        Profiler {
+            script,
+            time: Box::new(Stopwatch(Instant::now())),
+            functions: vec![],
+            keys: HashMap::new(),
+            stack: vec![],
+            start: None,
+            last: None,
+            stacks: HashMap::new(),
+            lines: BTreeMap::new(),
+        }
+    }
+
+    pub fn set_time_source(&mut self, time: Box<dyn TimeSource>) {
+        self.time = time;
+    }
+
+    /// Stops measuring, ending the calls left by a runtime error.
+    pub fn finish(&mut self) -> Result<()> {
+        let now = self.tick()?;
+        while let Some(frame) = self.stack.pop() {
+            self.end_call(frame, now);
+        }
+        Ok(())
+    }
+
+    /// Functions in the order they were first called.
+    pub fn functions(&self) -> &[FunctionProfile] {
+        &self.functions
+    }
+
+    /// Number of statements executed at each line.
+    pub fn lines(&self) -> &BTreeMap<u32, u64> {
+        &self.lines
+    }
+
+    /// Seconds between the first statement and the end.
+    pub fn total(&self) -> f64 {
+        match (self.start, self.last) {
+            (Some(start), Some(last)) => last - start,
+            _ => 0.0,
+        }
+    }
+
+    pub fn write_report(&self, output: &mut impl Write) -> Result<()> {
+        writeln!(output, "Total time: {:.3} ms", self.total() * 1000.0)?;
+
+        let mut functions: Vec<&FunctionProfile> = self.functions.iter().collect();
+        functions.sort_by(|a, b| b.exclusive.total_cmp(&a.exclusive));
+        writeln!(output, "\nFunctions:")?;
+        writeln!(
+            output,
+            "{:>10} {:>12} {:>12}  function",
+            "calls", "total ms", "self ms"
+        )?;
+        for function in functions {
+            let declared = match function.line {
+                Some(line) => format!("line {}", line),
+                None => "native".into(),
+            };
+            writeln!(
+                output,
+                "{:>10} {:>12.3} {:>12.3}  {} ({})",
+                function.calls,
+                function.inclusive * 1000.0,
+                function.exclusive * 1000.0,
+                function.name,
+                declared
+            )?;
+        }
+
+        writeln!(output, "\nLines:")?;
+        writeln!(output, "{:>10} {:>6}  source", "count", "line")?;
+        for (&line, count) in &self.lines {
+            let source = self.script.source_line(line).unwrap_or_default().trim();
+            writeln!(output, "{:>10} {:>6}  {}", count, line, source)?;
+        }
+        Ok(())
+    }
+
+    /// Writes `<script>;caller;callee microseconds` lines, the format of
+    /// `flamegraph.pl` and compatible tools.
+    pub fn write_collapsed(&self, output: &mut impl Write) -> Result<()> {
+        let mut stacks: Vec<(String, u64)> = self
+            .stacks
+            .iter()
+            .map(|(stack, seconds)| {
+                let names = std::iter::once("<script>")
+                    .chain(stack.iter().map(|&f| self.functions[f].name.as_str()));
+                let stack = names.collect::<Vec<&str>>().join(";");
+                (stack, (seconds * 1_000_000.0).round() as u64)
+            })
+            .filter(|(_, micros)| *micros > 0)
+            .collect();
+        stacks.sort();
+
+        for (stack, micros) in stacks {
+            writeln!(output, "{} {}", stack, micros)?;
+        }
+        Ok(())
+    }
+
+    pub fn write(&self, format: ProfileFormat, output: &mut impl Write) -> Result<()> {
+        match format {
+            ProfileFormat::Text => self.write_report(output),
+            ProfileFormat::Collapsed => self.write_collapsed(output),
+        }
+    }
+
+    /// Charges the time since the last call or return to the current function.
+    fn tick(&mut self) -> Result<f64> {
+        let now = self.time.now()?;
+        if let Some(last) = self.last {
+            let elapsed = now - last;
+            let stack: Vec<usize> = self.stack.iter().map(|frame| frame.function).collect();
+            *self.stacks.entry(stack).or_default() += elapsed;
+            if let Some(frame) = self.stack.last() {
+                self.functions[frame.function].exclusive += elapsed;
+            }
+        } else {
+            self.start = Some(now);
+        }
+        self.last = Some(now);
+        Ok(now)
+    }
+
+    fn end_call(&mut self, frame: Frame, now: f64) {
+        // a recursive call is already counted by the outermost one
+        if self
+            .stack
+            .iter()
+            .all(|other| other.function != frame.function)
+        {
+            self.functions[frame.function].inclusive += now - frame.start;
+        }
+    }
+
+    fn function(&mut self, callee: Callee) -> usize {
+        let (key, line) = match callee {
+            Callee::Function(decl) => (
+                Key::Function(decl.id),
+                self.script.function_line(decl),
+            ),
+            Callee::Native(native) => (Key::Native(native.name), None),
+        };
+
+        if let Some(&function) = self.keys.get(&key) {
+            return function;
+        }
+        self.functions.push(FunctionProfile {
+            name: callee.name().into(),
+            line,
+            calls: 0,
+            inclusive: 0.0,
+            exclusive: 0.0,
+        });
+        self.keys.insert(key, self.functions.len() - 1);
+        self.functions.len() - 1
+    }
+}
+
+impl Debugger for Profiler<'_> {
+    fn before_stmt(&mut self, _interpreter: &mut Interpreter<'_>, stmt: &Statement) -> Result<()> {
+        if self.last.is_none() {
+            self.tick()?;
+        }
+        if let Some(line) = self.script.line(stmt) {
+            *self.lines.entry(line).or_default() += 1;
+        }
+        Ok(())
+    }
+
+    fn on_call(
+        &mut self,
+        _interpreter: &Interpreter<'_>,
+        callee: Callee,
+        _args: &[Value],
+    ) -> Result<()> {
+        let start = self.tick()?;
+        let function = self.function(callee);
+        self.functions[function].calls += 1;
+        self.stack.push(Frame { function, start });
+        Ok(())
+    }
+
+    fn on_return(
+        &mut self,
+        _interpreter: &Interpreter<'_>,
+        _callee: Callee,
+        _value: &Value,
+    ) -> Result<()> {
+        let now = self.tick()?;
+        if let Some(frame) = self.stack.pop() {
+            self.end_call(frame, now);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,271 @@
+use std::time::Instant;
+
+use crate::{
+    ast::{Program, Statement, StatementKind},
+    error::{compilation_error, CompilationError, ErrorOrEarlyReturn},
+    interpreter::Interpreter,
+    parser,
+    scanner::{self, Backend, Scanner, Token, TokenType},
+    Result,
+};
+
+const HELP: &str = "\
+:env            list the global variables
+:ast <code>     show the syntax tree of the code
+:tokens <code>  show the tokens of the code
+:load <file>    run a file in the current session
+:reset          forget every definition
+:time <code>    run the code and show how long it took
+:help           show this message";
+
+/// Tells whether `src` can be run as it is, or if the prompt should keep reading
+/// lines because a string, a parenthesis or a brace is still open.
+///
+/// Any other error makes the code complete, so that running it reports the error.
+pub fn is_complete(src: &str) -> bool {
+    let tokens = match scanner::scan_tokens(src) {
+        Ok(tokens) => tokens,
+        Err(ErrorOrEarlyReturn::CompilationError(CompilationError::UnterminatedString)) => {
+            return false
+        }
+        Err(_) => return true,
+    };
+
+    let mut open = 0i32;
+    for token in tokens {
+        match token.type_ {
+            TokenType::LeftParen | TokenType::LeftBrace => open += 1,
+            TokenType::RightParen | TokenType::RightBrace => open -= 1,
+            _ => (),
+        }
+    }
+
+    open <= 0
+}
+
+/// Runs one entry of the prompt: either Lox code or a meta-command starting with `:`.
+///
+/// Returns the text to be shown to the user, if any. The output of `print`
+/// statements goes to the output of the interpreter instead.
+pub fn execute(interpreter: &mut Interpreter, input: &str) -> Result<Option<String>> {
+    let input = input.trim();
+
+    let (command, arg) = match input.strip_prefix(':') {
+        Some(command) => command
+            .split_once(char::is_whitespace)
+            .map(|(command, arg)| (command, arg.trim()))
+            .unwrap_or((command, "")),
+        None => return run(interpreter, input),
+    };
+
+    match command {
+        "env" => Ok(Some(env(interpreter))),
+        "ast" => Ok(Some(ast(Backend::default().scanner(), arg)?)),
+        "tokens" => Ok(Some(tokens(Backend::default().scanner(), arg)?)),
+        "load" => {
+            let src = std::fs::read_to_string(arg)?;
+            run(interpreter, &src).map(|_| None)
+        }
+        "reset" => {
+            interpreter.reset();
+            Ok(None)
+        }
+        "time" => {
+            let start = Instant::now();
+            let echo = run(interpreter, arg)?;
+            let elapsed = format!("Took {:?}", start.elapsed());
+            Ok(Some(match echo {
+                Some(value) => format!("{}\n{}", value, elapsed),
+                None => elapsed,
+            }))
+        }
+        "help" => Ok(Some(HELP.into())),
+        _ => Err(compilation_error(CompilationError::GenericError(format!(
+            "Unknown command ':{}'. Type :help to list the commands.",
+            command
+        )))),
+    }
+}
+
+/// Runs the code, returning the value of a bare expression to be echoed.
+fn run(interpreter: &mut Interpreter, src: &str) -> Result<Option<String>> {
+    let (program, bare_expr) = parse(Backend::default().scanner(), src)?;
+
+    let mut value = None;
+    for stmt in program.statements() {
+        value = Some(interpreter.exec_stmt(stmt)?);
+    }
+
+    Ok(value
+        .filter(|_| bare_expr)
+        .map(|value| format!("{}", value)))
+}
+
+/// Parses the code, also accepting a bare expression without its final semicolon.
+///
+/// Returns the program and whether the code was a bare expression.
+fn parse(scanner: &dyn Scanner, src: &str) -> Result<(Program, bool)> {
+    let mut tokens = scanner.scan_tokens(src)?;
+
+    let error = match parser::parse(&tokens) {
+        Ok(program) => return Ok((program, false)),
+        Err(error) => error,
+    };
+
+    let line = tokens.last().map_or(1, |token| token.line);
+    tokens.push(Token::new(TokenType::Semicolon, ";", line));
+
+    match parser::parse(&tokens) {
+        Ok(program)
+            if matches!(
+                program.statements(),
+                [Statement {
+                    kind: StatementKind::Expr(_),
+                    ..
+                }]
+            ) =>
+        {
+            Ok((program, true))
+        }
+        _ => Err(error),
+    }
+}
+
+fn env(interpreter: &Interpreter) -> String {
+    let global_env = interpreter.global_env();
+    let global_env = global_env.borrow();
+
+    let mut values = global_env
+        .values()
+        .map(|(name, value)| format!("{} = {}", name, value))
+        .collect::<Vec<_>>();
+    values.sort();
+
+    values.join("\n")
+}
+
+/// Shows the syntax tree of the code, as printed by `:ast` and `rlox ast`.
+pub fn ast(scanner: &dyn Scanner, src: &str) -> Result<String> {
+    let (program, bare_expr) = parse(scanner, src)?;
+
+    Ok(match program.statements() {
+        [Statement {
+            kind: StatementKind::Expr(expr),
+            ..
+        }] if bare_expr => format!("{:#?}", expr),
+        statements => format!("{:#?}", statements),
+    })
+}
+
+/// Shows the tokens of the code, one per line, as printed by `:tokens` and `rlox tokens`.
+pub fn tokens(scanner: &dyn Scanner, src: &str) -> Result<String> {
+    Ok(scanner
+        .scan_tokens(src)?
+        .iter()
+        .map(|token| format!("{:>4} {:?} {}", token.line, token.type_, token.lexeme))
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{execute, is_complete};
+    use crate::interpreter::Interpreter;
+
+    #[test]
+    fn test_complete_statement() {
+        assert!(is_complete("print 1;"));
+        assert!(is_complete(""));
+    }
+
+    #[test]
+    fn test_open_brace() {
+        assert!(!is_complete("fun f() {\n  print 1;\n"));
+        assert!(is_complete("fun f() {\n  print 1;\n}"));
+    }
+
+    #[test]
+    fn test_open_paren() {
+        assert!(!is_complete("print (1 +\n"));
+        assert!(is_complete("print (1 +\n 2);"));
+    }
+
+    #[test]
+    fn test_open_string() {
+        assert!(!is_complete("print \"hello\n"));
+        assert!(is_complete("print \"hello\nworld\";"));
+    }
+
+    #[test]
+    fn test_extra_closing_brace_is_complete() {
+        assert!(is_complete("}"));
+    }
+
+    #[test]
+    fn test_scanning_error_is_complete() {
+        assert!(is_complete("print 1 # 2;"));
+    }
+
+    #[test]
+    fn test_bare_expression_is_echoed() {
+        let mut out = Vec::new();
+        let mut int = Interpreter::new(&mut out);
+        assert_eq!(execute(&mut int, "1 + 2").unwrap(), Some("3".into()));
+        assert_eq!(execute(&mut int, "var a = 1;").unwrap(), None);
+        assert_eq!(execute(&mut int, "a = 5").unwrap(), Some("5".into()));
+        assert_eq!(execute(&mut int, "a + 1;").unwrap(), None);
+        assert!(execute(&mut int, "1 2").is_err());
+    }
+
+    #[test]
+    fn test_print_is_not_echoed() {
+        let mut out = Vec::new();
+        let mut int = Interpreter::new(&mut out);
+        assert_eq!(execute(&mut int, "print 1;").unwrap(), None);
+        drop(int);
+        assert_eq!(String::from_utf8(out).unwrap(), "1\n");
+    }
+
+    #[test]
+    fn test_env_and_reset() {
+        let mut out = Vec::new();
+        let mut int = Interpreter::new(&mut out);
+        execute(&mut int, "var b = 2; var a = \"x\";").unwrap();
+        let env = execute(&mut int, ":env").unwrap().unwrap();
+        assert!(env.lines().any(|line| line == "a = x"));
+        assert!(env.lines().any(|line| line == "b = 2"));
+
+        execute(&mut int, ":reset").unwrap();
+        let env = execute(&mut int, ":env").unwrap().unwrap();
+        assert!(!env.contains("a = "));
+        assert!(env.contains("clock = native fun clock"));
+    }
+
+    #[test]
+    fn test_ast_and_tokens() {
+        let mut out = Vec::new();
+        let mut int = Interpreter::new(&mut out);
+        let ast = execute(&mut int, ":ast 1 + 2").unwrap().unwrap();
+        assert!(ast.starts_with("Binary("));
+        let tokens = execute(&mut int, ":tokens print x;").unwrap().unwrap();
+        assert_eq!(
+            tokens,
+            "   1 Print print\n   1 Identifier x\n   1 Semicolon ;"
+        );
+    }
+
+    #[test]
+    fn test_time() {
+        let mut out = Vec::new();
+        let mut int = Interpreter::new(&mut out);
+        let output = execute(&mut int, ":time 2 * 3").unwrap().unwrap();
+        assert!(output.starts_with("6\nTook "));
+    }
+
+    #[test]
+    fn test_unknown_command() {
+        let mut out = Vec::new();
+        let mut int = Interpreter::new(&mut out);
+        assert!(execute(&mut int, ":nope").is_err());
+    }
+}
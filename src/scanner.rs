@@ -3,11 +3,12 @@ use std::{collections::HashMap, fmt::Display};
 use lazy_static::lazy_static;
 
 use crate::{
-    error::{compilation_error, CompilationError},
+    error::{compilation_error, CompilationError, ErrorOrEarlyReturn},
+    source_map::{SourceMap, Span},
     Result,
 };
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub struct Token<'source_code> {
     pub type_: TokenType,
     pub lexeme: &'source_code str,
@@ -63,23 +64,207 @@ pub enum TokenType {
     While,
 
     Comment,
+    /// `/// ...` or `/** ... */`, kept for tooling along with the other trivia.
+    DocComment,
     Whitespace,
     Eof,
 }
 
-pub fn scan_tokens(source_code: &str) -> Result<Vec<Token>> {
+/// Turns source code into tokens.
+pub trait Scanner {
+    /// Scans the tokens of the program, without comments, whitespace and `Eof`.
+    fn scan_tokens<'source_code>(
+        &self,
+        source_code: &'source_code str,
+    ) -> Result<Vec<Token<'source_code>>>;
+
+    /// Scans every token, including comments, whitespace and the final `Eof`,
+    /// so the lexemes put together are the source code.
+    fn scan_tokens_lossless<'source_code>(
+        &self,
+        source_code: &'source_code str,
+    ) -> Result<Vec<Token<'source_code>>>;
+}
+
+/// The hand-written scanner of this module.
+pub struct RecursiveDescentScanner;
+
+impl Scanner for RecursiveDescentScanner {
+    fn scan_tokens<'source_code>(
+        &self,
+        source_code: &'source_code str,
+    ) -> Result<Vec<Token<'source_code>>> {
+        scan_tokens_recursive_descent(source_code)
+    }
+
+    fn scan_tokens_lossless<'source_code>(
+        &self,
+        source_code: &'source_code str,
+    ) -> Result<Vec<Token<'source_code>>> {
+        scan_tokens_lossless(source_code)
+    }
+}
+
+/// The scanners compiled in, selectable at runtime.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Backend {
+    #[default]
+    RecursiveDescent,
+    Logos,
+}
+
+impl Backend {
+    pub fn scanner(self) -> &'static dyn Scanner {
+        match self {
+            Backend::RecursiveDescent => &RecursiveDescentScanner,
+            Backend::Logos => &crate::scanner_logos::LogosScanner,
+        }
+    }
+}
+
+/// Scans the tokens of the program, without comments, whitespace and `Eof`,
+/// with the default backend.
+pub fn scan_tokens(source_code: &str) -> Result<Vec<Token<'_>>> {
+    Backend::default().scanner().scan_tokens(source_code)
+}
+
+/// The hand-written scanner, whatever the features.
+pub fn scan_tokens_recursive_descent(source_code: &str) -> Result<Vec<Token<'_>>> {
+    tokens(source_code).collect()
+}
+
+/// Scans the tokens of the program lazily, without comments, whitespace and `Eof`.
+///
+/// The iteration ends after the first error.
+pub fn tokens(source_code: &str) -> impl Iterator<Item = Result<Token<'_>>> {
+    Lexer::new(source_code).filter(|token| {
+        !matches!(
+            token,
+            Ok(Token {
+                type_: TokenType::Comment
+                    | TokenType::DocComment
+                    | TokenType::Whitespace
+                    | TokenType::Eof,
+                ..
+            })
+        )
+    })
+}
+
+/// Scans every token one at a time, including comments, whitespace and the final `Eof`.
+///
+/// The iteration ends after `Eof` or after the first error.
+pub struct Lexer<'source_code> {
+    source_code: &'source_code str,
+    /// What is left to be scanned.
+    rest: &'source_code str,
+    line: u32,
+    done: bool,
+}
+
+impl<'source_code> Lexer<'source_code> {
+    pub fn new(source_code: &'source_code str) -> Self {
+        Self::resume(source_code, 0, 1)
+    }
+
+    /// Starts scanning at `offset`, which must be the start of a token at `line`.
+    pub fn resume(source_code: &'source_code str, offset: usize, line: u32) -> Self {
+        Lexer {
+            source_code,
+            rest: &source_code[offset..],
+            line,
+            done: false,
+        }
+    }
+
+    /// Offset in bytes of the next token.
+    pub fn offset(&self) -> usize {
+        self.source_code.len() - self.rest.len()
+    }
+}
+
+impl<'source_code> Iterator for Lexer<'source_code> {
+    type Item = Result<Token<'source_code>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match scan_token(self.rest, self.line) {
+            Ok((token, rest, line)) => {
+                self.rest = rest;
+                self.line = line;
+                self.done = token.type_ == TokenType::Eof;
+                Some(Ok(token))
+            }
+            Err(error) => {
+                self.done = true;
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+/// Scans every token, including comments, whitespace and the final `Eof`.
+///
+/// The lexemes of the tokens put together are exactly the source code.
+pub fn scan_tokens_lossless(source_code: &str) -> Result<Vec<Token<'_>>> {
+    Lexer::new(source_code).collect()
+}
+
+/// Like `scan_tokens_lossless`, but on error also returns the tokens before it,
+/// so the error is right after their lexemes.
+pub fn scan_tokens_until_error(source_code: &str) -> (Vec<Token<'_>>, Option<ErrorOrEarlyReturn>) {
     let mut tokens = Vec::new();
-    let mut src = source_code;
-    let mut line = 1;
 
-    loop {
-        let (token, next_src, next_line) = scan_token(src, line)?;
-        line = next_line;
-        src = next_src;
+    for token in Lexer::new(source_code) {
+        match token {
+            Ok(token) => tokens.push(token),
+            Err(error) => return (tokens, Some(error)),
+        }
+    }
+
+    (tokens, None)
+}
+
+/// A token together with the comments and whitespace around it.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TriviaToken<'source_code> {
+    /// Trivia between the previous line break, or the previous token, and this token.
+    pub leading: Vec<Token<'source_code>>,
+    pub token: Token<'source_code>,
+    /// Trivia after this token up to the end of its line, including the line break.
+    pub trailing: Vec<Token<'source_code>>,
+}
+
+/// Scans the source code keeping comments and whitespace attached to the tokens.
+///
+/// The last token is always `Eof`, holding the trivia at the end of the file.
+pub fn scan_tokens_with_trivia(source_code: &str) -> Result<Vec<TriviaToken<'_>>> {
+    let mut tokens: Vec<TriviaToken> = Vec::new();
+    let mut leading = Vec::new();
+    // whether trivia still belongs to the line of the previous token
+    let mut trailing = false;
+
+    for token in scan_tokens_lossless(source_code)? {
         match token.type_ {
-            TokenType::Comment | TokenType::Whitespace => continue,
-            TokenType::Eof => break,
-            _ => tokens.push(token),
+            TokenType::Comment | TokenType::DocComment | TokenType::Whitespace if trailing => {
+                // a block comment can be followed by more of the line
+                trailing = !token.lexeme.ends_with('\n');
+                tokens.last_mut().unwrap().trailing.push(token);
+            }
+            TokenType::Comment | TokenType::DocComment | TokenType::Whitespace => {
+                leading.push(token)
+            }
+            _ => {
+                tokens.push(TriviaToken {
+                    leading: std::mem::take(&mut leading),
+                    token,
+                    trailing: vec![],
+                });
+                trailing = true;
+            }
         }
     }
 
@@ -87,7 +272,7 @@ pub fn scan_tokens(source_code: &str) -> Result<Vec<Token>> {
 }
 
 /// Returns the token, the next position to be read, and the current line after the token
-fn scan_token(src: &str, line: u32) -> Result<(Token, &str, u32)> {
+fn scan_token(src: &str, line: u32) -> Result<(Token<'_>, &str, u32)> {
     use TokenType::*;
 
     let mut chars = src.chars();
@@ -141,6 +326,7 @@ fn scan_token(src: &str, line: u32) -> Result<(Token, &str, u32)> {
         ('>', Some('=')) => return two_char_token(GreaterEqual),
         ('>', _) => return single_char_token(Greater),
         ('/', Some('/')) => return Ok(comment(src, line)),
+        ('/', Some('*')) => return block_comment(src, line),
         ('/', _) => return single_char_token(Slash),
         _ => (),
     }
@@ -148,7 +334,7 @@ fn scan_token(src: &str, line: u32) -> Result<(Token, &str, u32)> {
     // string, numbers, keywords, identifiers
     match char_0 {
         '"' => string(src, line),
-        '0'..='9' => Ok(number(src, line)),
+        '0'..='9' => number(src, line),
         'a'..='z' | 'A'..='Z' | '_' => Ok(identifier_or_keyword(src, line)),
         other => Err(compilation_error(CompilationError::UnexpectedCharacter(
             other,
@@ -156,24 +342,76 @@ fn scan_token(src: &str, line: u32) -> Result<(Token, &str, u32)> {
     }
 }
 
-fn comment(src: &str, line: u32) -> (Token, &str, u32) {
+fn comment(src: &str, line: u32) -> (Token<'_>, &str, u32) {
     if let Some(pos) = src.find('\n') {
+        let lexeme = &src[0..(pos + 1)];
         (
-            Token::new(TokenType::Comment, &src[0..(pos + 1)], line),
+            Token::new(comment_type(lexeme), lexeme, line),
             &src[(pos + 1)..],
             line + 1,
         )
     } else {
         // end of file ends the comment
         (
-            Token::new(TokenType::Comment, src, line),
+            Token::new(comment_type(src), src, line),
             &src[src.len()..],
             line,
         )
     }
 }
 
-fn string(src: &str, line: u32) -> Result<(Token, &str, u32)> {
+fn block_comment(src: &str, line: u32) -> Result<(Token<'_>, &str, u32)> {
+    let end = block_comment_length(src)
+        .ok_or_else(|| compilation_error(CompilationError::UnterminatedComment(line)))?;
+    let lexeme = &src[..end];
+    Ok((
+        Token::new(comment_type(lexeme), lexeme, line),
+        &src[end..],
+        line + lexeme.matches('\n').count() as u32,
+    ))
+}
+
+/// Length of the block comment at the start of `src`, with the comments nested in it,
+/// or `None` if it is not closed.
+pub(crate) fn block_comment_length(src: &str) -> Option<usize> {
+    let bytes = src.as_bytes();
+    let mut depth = 0;
+    let mut end = 0;
+
+    loop {
+        match bytes.get(end..end + 2)? {
+            b"/*" => {
+                depth += 1;
+                end += 2;
+            }
+            b"*/" => {
+                depth -= 1;
+                end += 2;
+                if depth == 0 {
+                    return Some(end);
+                }
+            }
+            _ => end += 1,
+        }
+    }
+}
+
+/// Tells doc comments from other comments, as in Rust: `////` and `/***` are not
+/// doc comments, and neither is `/**/`.
+pub(crate) fn comment_type(lexeme: &str) -> TokenType {
+    let doc = if lexeme.starts_with("//") {
+        lexeme.starts_with("///") && !lexeme.starts_with("////")
+    } else {
+        lexeme.starts_with("/**") && !lexeme.starts_with("/***") && lexeme != "/**/"
+    };
+    if doc {
+        TokenType::DocComment
+    } else {
+        TokenType::Comment
+    }
+}
+
+fn string(src: &str, line: u32) -> Result<(Token<'_>, &str, u32)> {
     let end = src[1..].find('"');
     if let Some(end) = end {
         let end = end + 1;
@@ -188,51 +426,97 @@ fn string(src: &str, line: u32) -> Result<(Token, &str, u32)> {
     }
 }
 
-fn number(src: &str, line: u32) -> (Token, &str, u32) {
+fn number(src: &str, line: u32) -> Result<(Token<'_>, &str, u32)> {
     // some possibilities
     // 123<EOF>
-    // 123     ;
-    // 123.    ;
-    // 123.456 ;
+    // 123.    ;    (a number and a dot)
+    // 1_000.5e-3
+    // 0xFF, 0o755, 0b1010
 
     let bytes = src.as_bytes();
+    let run = |start: usize, accept: &dyn Fn(&u8) -> bool| {
+        start + bytes[start..].iter().take_while(|&b| accept(b)).count()
+    };
+    let is_digit = |b: &u8| b.is_ascii_digit() || *b == b'_';
+    let radix_digit: Option<fn(&u8) -> bool> = match bytes.get(..2) {
+        Some(b"0x") => Some(u8::is_ascii_hexdigit),
+        Some(b"0o") => Some(|b| (b'0'..=b'7').contains(b)),
+        Some(b"0b") => Some(|b| matches!(b, b'0' | b'1')),
+        _ => None,
+    };
 
-    let mut end = 0;
-    while end < bytes.len() && bytes[end].is_ascii_digit() {
-        end += 1;
-    }
+    // the number ends at the first character that can't be a digit, so that in
+    // `0or 1` or `1else` the keyword is a token of its own
+    let end = match radix_digit {
+        Some(accept) if bytes.get(2).is_some_and(accept) => run(2, &|b| accept(b) || *b == b'_'),
+        _ => {
+            let mut end = run(0, &is_digit);
+            if bytes.get(end) == Some(&b'.') && bytes.get(end + 1).is_some_and(u8::is_ascii_digit) {
+                end = run(end + 1, &is_digit);
+            }
+            if matches!(bytes.get(end), Some(b'e' | b'E')) {
+                let sign = usize::from(matches!(bytes.get(end + 1), Some(b'+' | b'-')));
+                if bytes.get(end + 1 + sign).is_some_and(u8::is_ascii_digit) {
+                    end = run(end + 1 + sign, &is_digit);
+                }
+            }
+            end
+        }
+    };
 
-    // no dot after integer part
-    if end >= bytes.len() || bytes[end] != b'.' {
-        return (
-            Token::new(TokenType::NumberLiteral, &src[0..end], line),
-            &src[end..],
-            line,
-        );
+    let lexeme = &src[..end];
+    if parse_number(lexeme).is_none() {
+        return Err(compilation_error(CompilationError::InvalidLiteral(
+            "number".into(),
+            lexeme.into(),
+        )));
     }
+    Ok((
+        Token::new(TokenType::NumberLiteral, lexeme, line),
+        &src[end..],
+        line,
+    ))
+}
 
-    let dot = end;
-    end += 1;
-
-    while end < bytes.len() && bytes[end].is_ascii_digit() {
-        end += 1;
+/// Value of a number literal, or `None` if it is malformed.
+///
+/// Underscores may separate digits, but not start or end a group of digits.
+pub fn parse_number(lexeme: &str) -> Option<f64> {
+    let radix = match lexeme.get(..2) {
+        Some("0x") => 16,
+        Some("0o") => 8,
+        Some("0b") => 2,
+        _ => 10,
+    };
+    if radix != 10 {
+        let digits = &lexeme[2..];
+        if !separated_digits(digits) {
+            return None;
+        }
+        return digits
+            .chars()
+            .filter(|&c| c != '_')
+            .try_fold(0.0, |value, c| {
+                Some(value * radix as f64 + c.to_digit(radix)? as f64)
+            });
     }
 
-    if end == dot + 1 {
-        // no numbers after dot
-        return (
-            Token::new(TokenType::NumberLiteral, &src[0..dot], line),
-            &src[dot..],
-            line,
-        );
-    } else {
-        // numbers after dot
-        return (
-            Token::new(TokenType::NumberLiteral, &src[0..end], line),
-            &src[end..],
-            line,
-        );
+    let (mantissa, exponent) = match lexeme.find(['e', 'E']) {
+        Some(e) => (&lexeme[..e], Some(&lexeme[e + 1..])),
+        None => (lexeme, None),
+    };
+    let exponent = exponent.map(|exponent| exponent.trim_start_matches(['+', '-']));
+    if !mantissa.split('.').chain(exponent).all(separated_digits) {
+        return None;
     }
+    lexeme.replace('_', "").parse().ok()
+}
+
+fn separated_digits(digits: &str) -> bool {
+    !digits.is_empty()
+        && !digits.starts_with('_')
+        && !digits.ends_with('_')
+        && !digits.contains("__")
 }
 
 lazy_static! {
@@ -263,7 +547,7 @@ lazy_static! {
     };
 }
 
-fn identifier_or_keyword(src: &str, line: u32) -> (Token, &str, u32) {
+fn identifier_or_keyword(src: &str, line: u32) -> (Token<'_>, &str, u32) {
     // we assume that the first char is alpha or _
     let end = src
         .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
@@ -309,6 +593,29 @@ impl<'a> From<&Token<'a>> for String {
     }
 }
 
+/// A token that refers to its lexeme in a `SourceMap`, instead of borrowing it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OwnedToken {
+    pub type_: TokenType,
+    pub span: Span,
+    pub line: u32,
+}
+
+impl OwnedToken {
+    pub fn new(type_: TokenType, span: Span, line: u32) -> Self {
+        OwnedToken { type_, span, line }
+    }
+
+    pub fn lexeme<'map>(&self, source_map: &'map SourceMap) -> &'map str {
+        source_map.text(self.span)
+    }
+
+    /// The token borrowing its lexeme, as the parser takes it.
+    pub fn to_token<'map>(&self, source_map: &'map SourceMap) -> Token<'map> {
+        Token::new(self.type_, self.lexeme(source_map), self.line)
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -393,7 +700,7 @@ mod tests {
 
     #[test]
     fn test_number_integer() {
-        let (token, after, line) = number("123; test = 10;", 10);
+        let (token, after, line) = number("123; test = 10;", 10).unwrap();
         assert_eq!(token, Token::new(TokenType::NumberLiteral, "123", 10));
         assert_eq!(after, "; test = 10;");
         assert_eq!(line, 10);
@@ -401,7 +708,7 @@ mod tests {
 
     #[test]
     fn test_number_float() {
-        let (token, after, line) = number("123.321; test = 10;", 10);
+        let (token, after, line) = number("123.321; test = 10;", 10).unwrap();
         assert_eq!(token, Token::new(TokenType::NumberLiteral, "123.321", 10));
         assert_eq!(after, "; test = 10;");
         assert_eq!(line, 10);
@@ -409,12 +716,96 @@ mod tests {
 
     #[test]
     fn test_number_integer_dot() {
-        let (token, after, line) = number("123.; test = 10;", 10);
+        let (token, after, line) = number("123.; test = 10;", 10).unwrap();
         assert_eq!(token, Token::new(TokenType::NumberLiteral, "123", 10));
         assert_eq!(after, ".; test = 10;");
         assert_eq!(line, 10);
     }
 
+    #[test]
+    fn test_number_radix_and_exponent() {
+        for src in [
+            "0xFF_ff",
+            "0o755",
+            "0b1010",
+            "1e9",
+            "6.02e-23",
+            "1_000_000",
+            "2E+3",
+        ] {
+            let (token, after, _) = number(src, 1).unwrap();
+            assert_eq!(token.lexeme, src);
+            assert_eq!(after, "");
+        }
+    }
+
+    #[test]
+    fn test_number_followed_by_a_word() {
+        for (src, lexeme) in [
+            ("0or 1", "0"),
+            ("0band 1", "0"),
+            ("1else", "1"),
+            ("1e", "1"),
+            ("1e+", "1"),
+            ("0x", "0"),
+            ("0xG", "0"),
+            ("0b12", "0b1"),
+            ("0o78", "0o7"),
+            ("0xFFor", "0xFF"),
+        ] {
+            let (token, after, _) = number(src, 1).unwrap();
+            assert_eq!(token.lexeme, lexeme, "{}", src);
+            assert_eq!(after, &src[lexeme.len()..]);
+        }
+        assert_eq!(
+            scan_tokens("print 0or 1; if (true) print 1else print 2;")
+                .unwrap()
+                .iter()
+                .filter(|token| token.type_ == TokenType::Or || token.type_ == TokenType::Else)
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_number_invalid() {
+        for src in ["0x1__F", "0b1_", "1__0", "1_", "1_.5", "1.5_e3", "1e3_"] {
+            assert!(matches!(
+                number(src, 1),
+                Err(ErrorOrEarlyReturn::CompilationError(
+                    CompilationError::InvalidLiteral(_, _)
+                ))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_block_comment() {
+        let (token, after, line) = block_comment("/* a\n/* b */\n*/x", 10).unwrap();
+        assert_eq!(
+            token,
+            Token::new(TokenType::Comment, "/* a\n/* b */\n*/", 10)
+        );
+        assert_eq!(after, "x");
+        assert_eq!(line, 12);
+
+        assert!(matches!(
+            block_comment("/* a /* b */", 3),
+            Err(ErrorOrEarlyReturn::CompilationError(
+                CompilationError::UnterminatedComment(3)
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_doc_comments() {
+        assert_eq!(comment_type("/// doc\n"), TokenType::DocComment);
+        assert_eq!(comment_type("//// not doc\n"), TokenType::Comment);
+        assert_eq!(comment_type("/** doc */"), TokenType::DocComment);
+        assert_eq!(comment_type("/*** not doc */"), TokenType::Comment);
+        assert_eq!(comment_type("/**/"), TokenType::Comment);
+    }
+
     #[test]
     fn test_keywords() {
         let mut keywords = HashMap::new();
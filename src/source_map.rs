@@ -0,0 +1,98 @@
+//! Source files owned by ID, so that tokens and spans can refer to them without
+//! borrowing.
+
+use crate::{
+    ast::Program,
+    parser,
+    scanner::{self, Lexer, OwnedToken, TokenType},
+    Result,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FileId(u32);
+
+/// Range of bytes in a source file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub file: FileId,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceFile {
+    pub name: String,
+    pub source: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, name: impl Into<String>, source: impl Into<String>) -> FileId {
+        self.files.push(SourceFile {
+            name: name.into(),
+            source: source.into(),
+        });
+        FileId(self.files.len() as u32 - 1)
+    }
+
+    /// Panics if the file is not in this map.
+    pub fn file(&self, id: FileId) -> &SourceFile {
+        &self.files[id.0 as usize]
+    }
+
+    pub fn name(&self, id: FileId) -> &str {
+        &self.file(id).name
+    }
+
+    pub fn source(&self, id: FileId) -> &str {
+        &self.file(id).source
+    }
+
+    pub fn text(&self, span: Span) -> &str {
+        &self.source(span.file)[span.start..span.end]
+    }
+
+    /// Scans the tokens of the file, without comments, whitespace and `Eof`.
+    pub fn scan_tokens(&self, id: FileId) -> Result<Vec<OwnedToken>> {
+        let mut tokens = vec![];
+        let mut lexer = Lexer::new(self.source(id));
+
+        loop {
+            let start = lexer.offset();
+            let token = match lexer.next() {
+                Some(token) => token?,
+                None => break,
+            };
+            if matches!(
+                token.type_,
+                TokenType::Comment | TokenType::DocComment | TokenType::Whitespace | TokenType::Eof
+            ) {
+                continue;
+            }
+
+            let span = Span {
+                file: id,
+                start,
+                end: start + token.lexeme.len(),
+            };
+            tokens.push(OwnedToken::new(token.type_, span, token.line));
+        }
+
+        Ok(tokens)
+    }
+
+    /// Scans and parses the file, keeping comments and whitespace so the ranges
+    /// of the diagnostics are in its source code.
+    pub fn parse(&self, id: FileId) -> Result<Program> {
+        let tokens = scanner::scan_tokens_lossless(self.source(id))?;
+        Ok(parser::parse(&tokens)?.with_file(id))
+    }
+}
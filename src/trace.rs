@@ -0,0 +1,171 @@
+//! Execution tracing: logs every statement executed and every function call and return.
+
+use std::io::Write;
+
+use serde_json::{json, Value as Json};
+
+use crate::{
+    ast::Statement,
+    debugger::{Callee, Debugger, Script},
+    environment::Value,
+    interpreter::Interpreter,
+    Result,
+};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum TraceFormat {
+    /// One entry per line, indented by call depth.
+    #[default]
+    Text,
+    /// One JSON object per line, so traces can be processed and diffed.
+    Json,
+}
+
+enum Event<'a> {
+    Statement(&'a str),
+    Call(&'a str, &'a [Value]),
+    Return(&'a str, &'a Value),
+}
+
+/// Writes the trace of a script to `output`.
+pub struct Tracer<'script, W> {
+    script: &'script Script,
+    output: W,
+    format: TraceFormat,
+    /// Line of the statement being executed at each call depth.
+    lines: Vec<u32>,
+}
+
+impl<'script, W: Write> Tracer<'script, W> {
+    pub fn new(script: &'script Script, output: W, format: TraceFormat) -> Self {
+        Tracer {
+            script,
+            output,
+            format,
+            lines: vec![],
+        }
+    }
+
+    fn write(&mut self, depth: usize, line: u32, event: Event) -> Result<()> {
+        match self.format {
+            TraceFormat::Text => {
+                let indent = "  ".repeat(depth);
+                match event {
+                    Event::Statement(source) => {
+                        writeln!(self.output, "{}{}: {}", indent, line, source)?
+                    }
+                    Event::Call(function, args) => {
+                        let args: Vec<String> = args.iter().map(repr).collect();
+                        writeln!(
+                            self.output,
+                            "{}{}: call {}({})",
+                            indent,
+                            line,
+                            function,
+                            args.join(", ")
+                        )?
+                    }
+                    Event::Return(function, value) => writeln!(
+                        self.output,
+                        "{}{}: return from {}: {}",
+                        indent,
+                        line,
+                        function,
+                        repr(value)
+                    )?,
+                }
+            }
+            TraceFormat::Json => {
+                let entry = match event {
+                    Event::Statement(source) => json!({
+                        "event": "statement",
+                        "depth": depth,
+                        "line": line,
+                        "source": source,
+                    }),
+                    Event::Call(function, args) => json!({
+                        "event": "call",
+                        "depth": depth,
+                        "line": line,
+                        "function": function,
+                        "args": args.iter().map(to_json).collect::<Vec<Json>>(),
+                    }),
+                    Event::Return(function, value) => json!({
+                        "event": "return",
+                        "depth": depth,
+                        "line": line,
+                        "function": function,
+                        "value": to_json(value),
+                    }),
+                };
+                writeln!(self.output, "{}", entry)?
+            }
+        }
+        Ok(())
+    }
+
+    fn line(&self, depth: usize) -> u32 {
+        self.lines.get(depth).copied().unwrap_or_default()
+    }
+}
+
+impl<W: Write> Debugger for Tracer<'_, W> {
+    fn before_stmt(&mut self, interpreter: &mut Interpreter<'_>, stmt: &Statement) -> Result<()> {
+        let line = match self.script.line(stmt) {
+            Some(line) => line,
+            None => return Ok(()),
+        };
+        let depth = interpreter.call_depth();
+        self.lines.resize(depth, 0);
+        self.lines.push(line);
+
+        let source = self.script.source_line(line).unwrap_or_default().trim();
+        self.write(depth, line, Event::Statement(source))
+    }
+
+    fn on_call(
+        &mut self,
+        interpreter: &Interpreter<'_>,
+        function: Callee,
+        args: &[Value],
+    ) -> Result<()> {
+        let depth = interpreter.call_depth();
+        // forget the lines of the previous call
+        self.lines.truncate(depth + 1);
+        self.write(depth, self.line(depth), Event::Call(function.name(), args))
+    }
+
+    fn on_return(
+        &mut self,
+        interpreter: &Interpreter<'_>,
+        function: Callee,
+        value: &Value,
+    ) -> Result<()> {
+        let depth = interpreter.call_depth();
+        // the line the function returned from, or the call for native functions
+        let line = self
+            .lines
+            .get(depth + 1)
+            .copied()
+            .unwrap_or_else(|| self.line(depth));
+        self.write(depth, line, Event::Return(function.name(), value))
+    }
+}
+
+/// Shows strings quoted, so they can be told apart from other values.
+fn repr(value: &Value) -> String {
+    match value {
+        Value::String(s) => format!("{:?}", s),
+        value => value.to_string(),
+    }
+}
+
+fn to_json(value: &Value) -> Json {
+    match value {
+        Value::Nil => Json::Null,
+        Value::Boolean(b) => json!(b),
+        Value::Number(n) => json!(n),
+        Value::String(s) => json!(s),
+        value => json!(value.to_string()),
+    }
+}
@@ -0,0 +1,475 @@
+//! Traversals of the syntax tree.
+//!
+//! `Visitor` reads the tree, `VisitorMut` changes it in place, and `Fold` builds a
+//! new tree out of it. Every method has a default that goes through the children
+//! of the node with the matching `walk_*` or `fold_*` function, so a pass only
+//! overrides the nodes it is interested in, and calls that function to keep going.
+
+use std::sync::Arc;
+
+use crate::{
+    ast::{
+        BinaryExpr, CallExpr, Expr, ExprKind, FunctionDecl, IfStatement, LiteralExpr, Statement,
+        StatementKind, UnaryExpr, WhileStatement,
+    },
+    with_stack,
+};
+
+pub trait Visitor<'ast> {
+    fn visit_stmt(&mut self, stmt: &'ast Statement) {
+        walk_stmt(self, stmt)
+    }
+
+    fn visit_var_decl(&mut self, name: &'ast str, init: Option<&'ast Expr>) {
+        walk_var_decl(self, name, init)
+    }
+
+    fn visit_block(&mut self, statements: &'ast [Statement]) {
+        walk_block(self, statements)
+    }
+
+    fn visit_if(&mut self, if_stmt: &'ast IfStatement) {
+        walk_if(self, if_stmt)
+    }
+
+    fn visit_while(&mut self, while_stmt: &'ast WhileStatement) {
+        walk_while(self, while_stmt)
+    }
+
+    fn visit_function(&mut self, function: &'ast Arc<FunctionDecl>) {
+        walk_function(self, function)
+    }
+
+    fn visit_return(&mut self, value: Option<&'ast Expr>) {
+        walk_return(self, value)
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+        walk_expr(self, expr)
+    }
+
+    fn visit_identifier(&mut self, _name: &'ast str) {}
+
+    fn visit_literal(&mut self, _literal: &'ast LiteralExpr) {}
+
+    fn visit_unary(&mut self, unary: &'ast UnaryExpr) {
+        walk_unary(self, unary)
+    }
+
+    fn visit_binary(&mut self, binary: &'ast BinaryExpr) {
+        walk_binary(self, binary)
+    }
+
+    fn visit_assignment(&mut self, name: &'ast str, value: &'ast Expr) {
+        walk_assignment(self, name, value)
+    }
+
+    fn visit_call(&mut self, call: &'ast CallExpr) {
+        walk_call(self, call)
+    }
+}
+
+pub fn walk_stmt<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, stmt: &'ast Statement) {
+    with_stack(|| match &stmt.kind {
+        StatementKind::Expr(expr) | StatementKind::Print(expr) => visitor.visit_expr(expr),
+        StatementKind::VariableDecl(name, init) => visitor.visit_var_decl(name, init.as_ref()),
+        StatementKind::Block(statements) => visitor.visit_block(statements),
+        StatementKind::If(if_stmt) => visitor.visit_if(if_stmt),
+        StatementKind::While(while_stmt) => visitor.visit_while(while_stmt),
+        StatementKind::FunDecl(function) => visitor.visit_function(function),
+        StatementKind::Return(value) => visitor.visit_return(value.as_ref()),
+    })
+}
+
+pub fn walk_var_decl<'ast, V: Visitor<'ast> + ?Sized>(
+    visitor: &mut V,
+    _name: &'ast str,
+    init: Option<&'ast Expr>,
+) {
+    if let Some(init) = init {
+        visitor.visit_expr(init);
+    }
+}
+
+pub fn walk_block<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, statements: &'ast [Statement]) {
+    for stmt in statements {
+        visitor.visit_stmt(stmt);
+    }
+}
+
+pub fn walk_if<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, if_stmt: &'ast IfStatement) {
+    visitor.visit_expr(&if_stmt.cond);
+    visitor.visit_stmt(&if_stmt.then_branch);
+    if let Some(else_branch) = &if_stmt.else_branch {
+        visitor.visit_stmt(else_branch);
+    }
+}
+
+pub fn walk_while<'ast, V: Visitor<'ast> + ?Sized>(
+    visitor: &mut V,
+    while_stmt: &'ast WhileStatement,
+) {
+    visitor.visit_expr(&while_stmt.cond);
+    visitor.visit_stmt(&while_stmt.stmt);
+}
+
+pub fn walk_function<'ast, V: Visitor<'ast> + ?Sized>(
+    visitor: &mut V,
+    function: &'ast Arc<FunctionDecl>,
+) {
+    visitor.visit_block(&function.body)
+}
+
+pub fn walk_return<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, value: Option<&'ast Expr>) {
+    if let Some(value) = value {
+        visitor.visit_expr(value);
+    }
+}
+
+pub fn walk_expr<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, expr: &'ast Expr) {
+    with_stack(|| match &expr.kind {
+        ExprKind::Identifier(name) => visitor.visit_identifier(name),
+        ExprKind::Literal(literal) => visitor.visit_literal(literal),
+        ExprKind::Unary(unary) => visitor.visit_unary(unary),
+        ExprKind::Binary(binary) => visitor.visit_binary(binary),
+        ExprKind::Grouping(expr) => visitor.visit_expr(expr),
+        ExprKind::Assignment(name, value) => visitor.visit_assignment(name, value),
+        ExprKind::Call(call) => visitor.visit_call(call),
+    })
+}
+
+pub fn walk_unary<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, unary: &'ast UnaryExpr) {
+    visitor.visit_expr(&unary.expr)
+}
+
+pub fn walk_binary<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, binary: &'ast BinaryExpr) {
+    visitor.visit_expr(&binary.left);
+    visitor.visit_expr(&binary.right);
+}
+
+pub fn walk_assignment<'ast, V: Visitor<'ast> + ?Sized>(
+    visitor: &mut V,
+    _name: &'ast str,
+    value: &'ast Expr,
+) {
+    visitor.visit_expr(value)
+}
+
+pub fn walk_call<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, call: &'ast CallExpr) {
+    visitor.visit_expr(&call.callee);
+    for arg in &call.args {
+        visitor.visit_expr(arg);
+    }
+}
+
+pub trait VisitorMut {
+    fn visit_stmt_mut(&mut self, stmt: &mut Statement) {
+        walk_stmt_mut(self, stmt)
+    }
+
+    fn visit_var_decl_mut(&mut self, name: &mut String, init: Option<&mut Expr>) {
+        walk_var_decl_mut(self, name, init)
+    }
+
+    fn visit_block_mut(&mut self, statements: &mut Vec<Statement>) {
+        walk_block_mut(self, statements)
+    }
+
+    fn visit_if_mut(&mut self, if_stmt: &mut IfStatement) {
+        walk_if_mut(self, if_stmt)
+    }
+
+    fn visit_while_mut(&mut self, while_stmt: &mut WhileStatement) {
+        walk_while_mut(self, while_stmt)
+    }
+
+    /// The declaration is cloned first if it is shared, by a closure for instance.
+    fn visit_function_mut(&mut self, function: &mut Arc<FunctionDecl>) {
+        walk_function_mut(self, function)
+    }
+
+    fn visit_return_mut(&mut self, value: Option<&mut Expr>) {
+        walk_return_mut(self, value)
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        walk_expr_mut(self, expr)
+    }
+
+    fn visit_identifier_mut(&mut self, _name: &mut String) {}
+
+    fn visit_literal_mut(&mut self, _literal: &mut LiteralExpr) {}
+
+    fn visit_unary_mut(&mut self, unary: &mut UnaryExpr) {
+        walk_unary_mut(self, unary)
+    }
+
+    fn visit_binary_mut(&mut self, binary: &mut BinaryExpr) {
+        walk_binary_mut(self, binary)
+    }
+
+    fn visit_assignment_mut(&mut self, name: &mut String, value: &mut Expr) {
+        walk_assignment_mut(self, name, value)
+    }
+
+    fn visit_call_mut(&mut self, call: &mut CallExpr) {
+        walk_call_mut(self, call)
+    }
+}
+
+pub fn walk_stmt_mut<V: VisitorMut + ?Sized>(visitor: &mut V, stmt: &mut Statement) {
+    with_stack(|| match &mut stmt.kind {
+        StatementKind::Expr(expr) | StatementKind::Print(expr) => visitor.visit_expr_mut(expr),
+        StatementKind::VariableDecl(name, init) => visitor.visit_var_decl_mut(name, init.as_mut()),
+        StatementKind::Block(statements) => visitor.visit_block_mut(statements),
+        StatementKind::If(if_stmt) => visitor.visit_if_mut(if_stmt),
+        StatementKind::While(while_stmt) => visitor.visit_while_mut(while_stmt),
+        StatementKind::FunDecl(function) => visitor.visit_function_mut(function),
+        StatementKind::Return(value) => visitor.visit_return_mut(value.as_mut()),
+    })
+}
+
+pub fn walk_var_decl_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    _name: &mut String,
+    init: Option<&mut Expr>,
+) {
+    if let Some(init) = init {
+        visitor.visit_expr_mut(init);
+    }
+}
+
+pub fn walk_block_mut<V: VisitorMut + ?Sized>(visitor: &mut V, statements: &mut Vec<Statement>) {
+    for stmt in statements {
+        visitor.visit_stmt_mut(stmt);
+    }
+}
+
+pub fn walk_if_mut<V: VisitorMut + ?Sized>(visitor: &mut V, if_stmt: &mut IfStatement) {
+    visitor.visit_expr_mut(&mut if_stmt.cond);
+    visitor.visit_stmt_mut(&mut if_stmt.then_branch);
+    if let Some(else_branch) = &mut if_stmt.else_branch {
+        visitor.visit_stmt_mut(else_branch);
+    }
+}
+
+pub fn walk_while_mut<V: VisitorMut + ?Sized>(visitor: &mut V, while_stmt: &mut WhileStatement) {
+    visitor.visit_expr_mut(&mut while_stmt.cond);
+    visitor.visit_stmt_mut(&mut while_stmt.stmt);
+}
+
+pub fn walk_function_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    function: &mut Arc<FunctionDecl>,
+) {
+    visitor.visit_block_mut(&mut Arc::make_mut(function).body)
+}
+
+pub fn walk_return_mut<V: VisitorMut + ?Sized>(visitor: &mut V, value: Option<&mut Expr>) {
+    if let Some(value) = value {
+        visitor.visit_expr_mut(value);
+    }
+}
+
+pub fn walk_expr_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expr: &mut Expr) {
+    with_stack(|| match &mut expr.kind {
+        ExprKind::Identifier(name) => visitor.visit_identifier_mut(name),
+        ExprKind::Literal(literal) => visitor.visit_literal_mut(literal),
+        ExprKind::Unary(unary) => visitor.visit_unary_mut(unary),
+        ExprKind::Binary(binary) => visitor.visit_binary_mut(binary),
+        ExprKind::Grouping(expr) => visitor.visit_expr_mut(expr),
+        ExprKind::Assignment(name, value) => visitor.visit_assignment_mut(name, value),
+        ExprKind::Call(call) => visitor.visit_call_mut(call),
+    })
+}
+
+pub fn walk_unary_mut<V: VisitorMut + ?Sized>(visitor: &mut V, unary: &mut UnaryExpr) {
+    visitor.visit_expr_mut(&mut unary.expr)
+}
+
+pub fn walk_binary_mut<V: VisitorMut + ?Sized>(visitor: &mut V, binary: &mut BinaryExpr) {
+    visitor.visit_expr_mut(&mut binary.left);
+    visitor.visit_expr_mut(&mut binary.right);
+}
+
+pub fn walk_assignment_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    _name: &mut String,
+    value: &mut Expr,
+) {
+    visitor.visit_expr_mut(value)
+}
+
+pub fn walk_call_mut<V: VisitorMut + ?Sized>(visitor: &mut V, call: &mut CallExpr) {
+    visitor.visit_expr_mut(&mut call.callee);
+    for arg in &mut call.args {
+        visitor.visit_expr_mut(arg);
+    }
+}
+
+/// Rebuilds the tree. A method may return a node of another kind, as constant
+/// folding does when it turns a `Binary` expression into a `Literal`. Rebuilt
+/// nodes keep their ids.
+pub trait Fold {
+    fn fold_stmt(&mut self, stmt: Statement) -> Statement {
+        fold_stmt(self, stmt)
+    }
+
+    fn fold_var_decl(&mut self, name: String, init: Option<Expr>) -> (String, Option<Expr>) {
+        fold_var_decl(self, name, init)
+    }
+
+    fn fold_block(&mut self, statements: Vec<Statement>) -> Vec<Statement> {
+        fold_block(self, statements)
+    }
+
+    fn fold_if(&mut self, if_stmt: IfStatement) -> IfStatement {
+        fold_if(self, if_stmt)
+    }
+
+    fn fold_while(&mut self, while_stmt: WhileStatement) -> WhileStatement {
+        fold_while(self, while_stmt)
+    }
+
+    fn fold_function(&mut self, function: Arc<FunctionDecl>) -> Arc<FunctionDecl> {
+        fold_function(self, function)
+    }
+
+    fn fold_return(&mut self, value: Option<Expr>) -> Option<Expr> {
+        fold_return(self, value)
+    }
+
+    fn fold_expr(&mut self, expr: Expr) -> Expr {
+        fold_expr(self, expr)
+    }
+
+    fn fold_unary(&mut self, unary: UnaryExpr) -> UnaryExpr {
+        fold_unary(self, unary)
+    }
+
+    fn fold_binary(&mut self, binary: BinaryExpr) -> BinaryExpr {
+        fold_binary(self, binary)
+    }
+
+    fn fold_assignment(&mut self, name: String, value: Expr) -> (String, Expr) {
+        fold_assignment(self, name, value)
+    }
+
+    fn fold_call(&mut self, call: CallExpr) -> CallExpr {
+        fold_call(self, call)
+    }
+}
+
+pub fn fold_stmt<F: Fold + ?Sized>(folder: &mut F, stmt: Statement) -> Statement {
+    let kind = with_stack(|| match stmt.kind {
+        StatementKind::Expr(expr) => StatementKind::Expr(folder.fold_expr(expr)),
+        StatementKind::Print(expr) => StatementKind::Print(folder.fold_expr(expr)),
+        StatementKind::VariableDecl(name, init) => {
+            let (name, init) = folder.fold_var_decl(name, init);
+            StatementKind::VariableDecl(name, init)
+        }
+        StatementKind::Block(statements) => StatementKind::Block(folder.fold_block(statements)),
+        StatementKind::If(if_stmt) => StatementKind::If(folder.fold_if(if_stmt)),
+        StatementKind::While(while_stmt) => StatementKind::While(folder.fold_while(while_stmt)),
+        StatementKind::FunDecl(function) => StatementKind::FunDecl(folder.fold_function(function)),
+        StatementKind::Return(value) => StatementKind::Return(folder.fold_return(value)),
+    });
+    Statement { id: stmt.id, kind }
+}
+
+pub fn fold_var_decl<F: Fold + ?Sized>(
+    folder: &mut F,
+    name: String,
+    init: Option<Expr>,
+) -> (String, Option<Expr>) {
+    (name, init.map(|init| folder.fold_expr(init)))
+}
+
+pub fn fold_block<F: Fold + ?Sized>(folder: &mut F, statements: Vec<Statement>) -> Vec<Statement> {
+    statements
+        .into_iter()
+        .map(|stmt| folder.fold_stmt(stmt))
+        .collect()
+}
+
+pub fn fold_if<F: Fold + ?Sized>(folder: &mut F, if_stmt: IfStatement) -> IfStatement {
+    IfStatement {
+        cond: folder.fold_expr(if_stmt.cond),
+        then_branch: Box::new(folder.fold_stmt(*if_stmt.then_branch)),
+        else_branch: if_stmt
+            .else_branch
+            .map(|else_branch| Box::new(folder.fold_stmt(*else_branch))),
+    }
+}
+
+pub fn fold_while<F: Fold + ?Sized>(folder: &mut F, while_stmt: WhileStatement) -> WhileStatement {
+    WhileStatement {
+        cond: folder.fold_expr(while_stmt.cond),
+        stmt: Box::new(folder.fold_stmt(*while_stmt.stmt)),
+    }
+}
+
+pub fn fold_function<F: Fold + ?Sized>(
+    folder: &mut F,
+    function: Arc<FunctionDecl>,
+) -> Arc<FunctionDecl> {
+    let function = Arc::unwrap_or_clone(function);
+    Arc::new(FunctionDecl {
+        body: folder.fold_block(function.body),
+        ..function
+    })
+}
+
+pub fn fold_return<F: Fold + ?Sized>(folder: &mut F, value: Option<Expr>) -> Option<Expr> {
+    value.map(|value| folder.fold_expr(value))
+}
+
+pub fn fold_expr<F: Fold + ?Sized>(folder: &mut F, expr: Expr) -> Expr {
+    let id = expr.id;
+    let kind = with_stack(|| match expr.into_kind() {
+        kind @ (ExprKind::Identifier(_) | ExprKind::Literal(_)) => kind,
+        ExprKind::Unary(unary) => ExprKind::Unary(folder.fold_unary(unary)),
+        ExprKind::Binary(binary) => ExprKind::Binary(folder.fold_binary(binary)),
+        ExprKind::Grouping(expr) => ExprKind::Grouping(Box::new(folder.fold_expr(*expr))),
+        ExprKind::Assignment(name, value) => {
+            let (name, value) = folder.fold_assignment(name, *value);
+            ExprKind::Assignment(name, Box::new(value))
+        }
+        ExprKind::Call(call) => ExprKind::Call(folder.fold_call(call)),
+    });
+    Expr { id, kind }
+}
+
+pub fn fold_unary<F: Fold + ?Sized>(folder: &mut F, unary: UnaryExpr) -> UnaryExpr {
+    UnaryExpr {
+        op: unary.op,
+        expr: Box::new(folder.fold_expr(*unary.expr)),
+    }
+}
+
+pub fn fold_binary<F: Fold + ?Sized>(folder: &mut F, binary: BinaryExpr) -> BinaryExpr {
+    BinaryExpr {
+        left: Box::new(folder.fold_expr(*binary.left)),
+        op: binary.op,
+        right: Box::new(folder.fold_expr(*binary.right)),
+    }
+}
+
+pub fn fold_assignment<F: Fold + ?Sized>(
+    folder: &mut F,
+    name: String,
+    value: Expr,
+) -> (String, Expr) {
+    (name, folder.fold_expr(value))
+}
+
+pub fn fold_call<F: Fold + ?Sized>(folder: &mut F, call: CallExpr) -> CallExpr {
+    CallExpr {
+        callee: Box::new(folder.fold_expr(*call.callee)),
+        args: call
+            .args
+            .into_iter()
+            .map(|arg| folder.fold_expr(arg))
+            .collect(),
+    }
+}
@@ -0,0 +1,194 @@
+use std::process::{Command, Output, Stdio};
+
+fn rlox(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rlox"))
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_success() {
+    let output = rlox(&["run", "-e", "print 1 + 2;"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "3\n");
+}
+
+#[test]
+fn test_usage_error() {
+    let output = rlox(&["run"]);
+    assert_eq!(output.status.code(), Some(64));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("Usage:"));
+}
+
+#[test]
+fn test_compilation_error() {
+    let output = rlox(&["run", "-e", "print ;"]);
+    assert_eq!(output.status.code(), Some(65));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "Error: Compilation error: Expression expected\n"
+    );
+}
+
+#[test]
+fn test_check_reports_compilation_errors() {
+    assert_eq!(rlox(&["check", "-e", "print 1;"]).status.code(), Some(0));
+    assert_eq!(rlox(&["check", "-e", "print 1"]).status.code(), Some(65));
+}
+
+#[test]
+fn test_check_reports_resolution_errors() {
+    let output = rlox(&["check", "-e", "var a = 1;\n{\n  var b = b;\n}\nprint c;"]);
+    assert_eq!(output.status.code(), Some(65));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "");
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "[line 3] Error: Can't read local variable 'b' in its own initializer.\n\
+         [line 5] Error: Undefined variable 'c'.\n"
+    );
+}
+
+#[test]
+fn test_runtime_error() {
+    let output = rlox(&["run", "-e", "print 1; print 1 / 0;"]);
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1\n");
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "Error: Runtime error: Division by zero\n"
+    );
+}
+
+#[test]
+fn test_missing_file() {
+    let output = rlox(&["run", "does/not/exist.lox"]);
+    assert_eq!(output.status.code(), Some(66));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .starts_with("Error: could not read 'does/not/exist.lox': "));
+}
+
+#[test]
+fn test_script_arguments() {
+    let output = rlox(&["run", "-e", "print argc(); print argv(0);", "hello"]);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1\nhello\n");
+}
+
+#[test]
+fn test_fmt() {
+    let output = rlox(&["fmt", "-e", "print  1+2 ;"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "print 1 + 2;\n");
+
+    assert_eq!(rlox(&["fmt", "-e", "print ;"]).status.code(), Some(65));
+}
+
+#[test]
+fn test_fmt_check() {
+    assert_eq!(
+        rlox(&["fmt", "--check", "-e", "print 1 + 2;\n"])
+            .status
+            .code(),
+        Some(0)
+    );
+
+    let output = rlox(&["fmt", "--check", "-e", "print 1+2;"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .contains("the inline code is not formatted"));
+}
+
+#[test]
+fn test_debug() {
+    let path = std::env::temp_dir().join(format!("rlox_debug_{}.lox", std::process::id()));
+    std::fs::write(&path, "print 1;\n").unwrap();
+
+    // the debugger stops when there are no more commands
+    let output = rlox(&["debug", path.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Paused at line 1 (entry): print 1;\n(debug) "
+    );
+
+    assert_eq!(rlox(&["debug", "-"]).status.code(), Some(64));
+}
+
+#[test]
+fn test_trace() {
+    let output = rlox(&["run", "--trace", "-e", "print 1;"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1\n");
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "1: print 1;\n");
+}
+
+#[test]
+fn test_profile() {
+    let output = rlox(&["run", "--profile", "-e", "fun f() {}\nf();\nprint 1;"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1\n");
+    let report = String::from_utf8(output.stderr).unwrap();
+    assert!(report.contains("  f (line 1)\n"));
+
+    let output = rlox(&["run", "--profile=collapsed", "-e", "print 1;"]);
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_coverage() {
+    let tracefile = std::env::temp_dir().join(format!("rlox_coverage_{}.info", std::process::id()));
+    let _ = std::fs::remove_file(&tracefile);
+    let tracefile = tracefile.to_str().unwrap();
+
+    for _ in 0..2 {
+        let output = rlox(&["run", "--coverage", tracefile, "-e", "print 1;"]);
+        assert_eq!(output.status.code(), Some(0));
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "1\n");
+    }
+
+    // the second run is merged into the first one
+    let lcov = std::fs::read_to_string(tracefile).unwrap();
+    assert!(lcov.contains("SF:<inline>\n"));
+    assert!(lcov.contains("DA:1,2\n"));
+    std::fs::remove_file(tracefile).unwrap();
+}
+
+#[test]
+fn test_scanner() {
+    let output = rlox(&["tokens", "--scanner=recursive-descent", "-e", "print x;"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "   1 Print print\n   1 Identifier x\n   1 Semicolon ;\n"
+    );
+
+    let output = rlox(&["run", "--scanner=logos", "-e", "print 1 + 2;"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "3\n");
+
+    // the backends can be compared under the profiler
+    let src = "// f\nfun f() {}\nf(); /* done */\nprint 1;";
+    let output = rlox(&["run", "--scanner=logos", "--profile", "-e", src]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1\n");
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("  f (line 2)\n"));
+}
+
+#[test]
+fn test_seed() {
+    let script = "var start = clock(); print random(); print random(); print clock() - start;";
+    let run = |seed| {
+        let output = rlox(&["run", seed, "-e", script]);
+        assert_eq!(output.status.code(), Some(0));
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    assert_eq!(run("--seed=42"), run("--seed=42"));
+    assert_ne!(run("--seed=42"), run("--seed=43"));
+    assert!(run("--seed=42").ends_with("\n0\n"));
+}
@@ -0,0 +1,171 @@
+use std::collections::BTreeMap;
+
+use rlox::{
+    coverage::{Coverage, FileCoverage, Lcov},
+    debugger::Script,
+    interpreter::Interpreter,
+};
+
+const SRC: &str = "\
+fun sign(n) {
+    if (n < 0) return -1;
+    return 1;
+}
+fun unused() {}
+var i = 0;
+while (i < 2) i = i + 1;
+print sign(i) > 0 or nil;
+";
+
+fn coverage(src: &str) -> FileCoverage {
+    let script = Script::compile(src).unwrap();
+    let mut coverage = Coverage::new(&script);
+    {
+        let mut output = Vec::new();
+        let mut int = Interpreter::new(&mut output);
+        int.set_debugger(&mut coverage);
+        script.run(&mut int).unwrap();
+    }
+    coverage.report()
+}
+
+#[test]
+fn test_lines() {
+    let lines: Vec<(u32, u64)> = coverage(SRC).lines.into_iter().collect();
+    assert_eq!(
+        lines,
+        vec![(1, 1), (2, 1), (3, 1), (5, 1), (6, 1), (7, 3), (8, 1)]
+    );
+}
+
+#[test]
+fn test_functions() {
+    let coverage = coverage(SRC);
+    assert_eq!(
+        coverage.functions,
+        BTreeMap::from([((1, 0, "sign".into()), 1), ((5, 0, "unused".into()), 0)])
+    );
+    assert_eq!(coverage.function_counts(), (1, 2));
+}
+
+#[test]
+fn test_branches() {
+    let coverage = coverage(SRC);
+    assert_eq!(
+        coverage.branches,
+        BTreeMap::from([
+            // if
+            ((2, 0, 0), Some(0)),
+            ((2, 0, 1), Some(1)),
+            // while
+            ((7, 0, 0), Some(2)),
+            ((7, 0, 1), Some(1)),
+            // or
+            ((8, 0, 0), Some(1)),
+            ((8, 0, 1), Some(0)),
+        ])
+    );
+    assert_eq!(coverage.branch_counts(), (4, 6));
+}
+
+#[test]
+fn test_branches_that_never_ran() {
+    let coverage = coverage("fun f(a) { return a and a; }");
+    assert_eq!(
+        coverage.branches,
+        BTreeMap::from([((1, 0, 0), None), ((1, 0, 1), None)])
+    );
+}
+
+#[test]
+fn test_lcov_round_trip() {
+    let mut lcov = Lcov::default();
+    lcov.add("test.lox", coverage(SRC));
+
+    let tracefile = lcov.to_string();
+    assert!(tracefile.starts_with("TN:\nSF:test.lox\nFN:1,sign\nFN:5,unused\nFNDA:1,sign\n"));
+    assert!(tracefile.contains("\nBRDA:2,0,0,0\nBRDA:2,0,1,1\n"));
+    assert!(tracefile.contains("\nDA:7,3\n"));
+    assert!(tracefile.ends_with("LF:7\nLH:7\nend_of_record\n"));
+
+    assert_eq!(Lcov::parse(&tracefile).unwrap(), lcov);
+}
+
+#[test]
+fn test_functions_with_the_same_name() {
+    let src = "\
+fun helper() {}
+helper();
+helper();
+{
+    fun helper() {}
+}
+";
+    let mut lcov = Lcov::default();
+    lcov.add("test.lox", coverage(src));
+    assert_eq!(
+        lcov.files["test.lox"].functions,
+        BTreeMap::from([((1, 0, "helper".into()), 2), ((5, 0, "helper".into()), 0)])
+    );
+
+    assert_eq!(Lcov::parse(&lcov.to_string()).unwrap(), lcov);
+}
+
+#[test]
+fn test_functions_in_the_same_line() {
+    let src = "\
+fun f() {} var g = f; fun f() {}
+f();
+g();
+g();
+";
+    let mut lcov = Lcov::default();
+    lcov.add("test.lox", coverage(src));
+    assert_eq!(
+        lcov.files["test.lox"].functions,
+        BTreeMap::from([((1, 0, "f".into()), 2), ((1, 1, "f".into()), 1)])
+    );
+    let report = lcov.to_string();
+    assert_eq!(report.matches("FN:1,f\n").count(), 2);
+    assert!(report.contains("FNDA:2,f\nFNDA:1,f\n"));
+
+    assert_eq!(Lcov::parse(&report).unwrap(), lcov);
+}
+
+#[test]
+fn test_merge() {
+    let mut lcov = Lcov::default();
+    lcov.add("test.lox", coverage(SRC));
+    lcov.add("test.lox", coverage(SRC));
+    lcov.add("other.lox", coverage("print 1;"));
+
+    let test = &lcov.files["test.lox"];
+    assert_eq!(test.lines[&7], 6);
+    assert_eq!(test.functions[&(1, 0, "sign".into())], 2);
+    assert_eq!(test.branches[&(7, 0, 0)], Some(4));
+    assert_eq!(lcov.files["other.lox"].lines[&1], 1);
+
+    let mut merged = Lcov::parse(&lcov.to_string()).unwrap();
+    merged.merge(lcov.clone());
+    assert_eq!(merged.files["test.lox"].lines[&7], 12);
+}
+
+#[test]
+fn test_summary() {
+    let mut lcov = Lcov::default();
+    lcov.add("test.lox", coverage(SRC));
+
+    let mut output = Vec::new();
+    lcov.write_summary(&mut output).unwrap();
+    let summary = String::from_utf8(output).unwrap();
+    assert!(summary.contains("test.lox"));
+    assert!(summary.contains("100.0% (7/7)"));
+    assert!(summary.contains("66.7% (4/6)"));
+    assert!(summary.contains("50.0% (1/2)"));
+}
+
+#[test]
+fn test_invalid_tracefile() {
+    assert!(Lcov::parse("SF:a.lox\nDA:x,1\nend_of_record\n").is_err());
+    assert!(Lcov::parse("SF:a.lox\nDA:1,1\n").is_err());
+}
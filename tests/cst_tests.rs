@@ -0,0 +1,126 @@
+use rlox::{
+    cst::{NodeKind, SyntaxElement, SyntaxNode},
+    parser,
+    scanner::{self, TokenType},
+};
+
+fn parse_cst(src: &str) -> SyntaxNode {
+    parser::parse_cst(&scanner::scan_tokens_lossless(src).unwrap()).unwrap()
+}
+
+const PROGRAM: &str = "\
+// Computes factorials
+fun fact(n) {
+    if (n <= 1) return 1; // base case
+    return n * fact(n - 1);
+}
+
+for (var i = 0; i < 5; i = i + 1) {
+    print ((fact(i)));
+}
+";
+
+#[test]
+fn test_round_trip() {
+    for src in [
+        PROGRAM,
+        "",
+        "  \n// only a comment",
+        "print 1;",
+        "a = (1)  +2 ;  ",
+    ] {
+        assert_eq!(parse_cst(src).text(), src);
+    }
+}
+
+#[test]
+fn test_ranges_cover_the_source() {
+    let root = parse_cst(PROGRAM);
+    assert_eq!(root.text_range(), 0..PROGRAM.len());
+
+    for element in root.descendants_with_tokens() {
+        let text = match &element {
+            SyntaxElement::Node(node) => node.text(),
+            SyntaxElement::Token(token) => token.text().into(),
+        };
+        assert_eq!(&PROGRAM[element.text_range()], text);
+    }
+}
+
+#[test]
+fn test_tree_shape() {
+    assert_eq!(
+        format!("{:#?}", parse_cst("a = -(1) + b; // c\n")),
+        "\
+Program@0..19
+  ExprStmt@0..13
+    Assignment@0..12
+      Name@0..1
+        Identifier@0..1 \"a\"
+      Whitespace@1..2 \" \"
+      Equal@2..3 \"=\"
+      Whitespace@3..4 \" \"
+      Binary@4..12
+        Unary@4..8
+          Minus@4..5 \"-\"
+          Paren@5..8
+            LeftParen@5..6 \"(\"
+            Literal@6..7
+              NumberLiteral@6..7 \"1\"
+            RightParen@7..8 \")\"
+        Whitespace@8..9 \" \"
+        Plus@9..10 \"+\"
+        Whitespace@10..11 \" \"
+        Name@11..12
+          Identifier@11..12 \"b\"
+    Semicolon@12..13 \";\"
+  Whitespace@13..14 \" \"
+  Comment@14..19 \"// c\\n\"
+  Eof@19..19 \"\"
+"
+    );
+}
+
+#[test]
+fn test_for_loop_is_not_desugared() {
+    let root = parse_cst("for (;i < 3;) print i;");
+    let for_loop = root.children().next().unwrap();
+    assert_eq!(for_loop.kind(), NodeKind::For);
+    assert_eq!(
+        for_loop
+            .children()
+            .map(|child| child.kind())
+            .collect::<Vec<_>>(),
+        vec![NodeKind::ForCondition, NodeKind::Print]
+    );
+    assert_eq!(
+        for_loop
+            .tokens()
+            .map(|token| token.kind())
+            .collect::<Vec<_>>(),
+        vec![
+            TokenType::For,
+            TokenType::LeftParen,
+            TokenType::Semicolon,
+            TokenType::Semicolon,
+            TokenType::RightParen
+        ]
+    );
+}
+
+#[test]
+fn test_ast_is_the_same_with_or_without_trivia() {
+    assert_eq!(
+        parser::parse(&scanner::scan_tokens_lossless(PROGRAM).unwrap()).unwrap(),
+        parser::parse(&scanner::scan_tokens(PROGRAM).unwrap()).unwrap()
+    );
+}
+
+#[test]
+fn test_invalid_assignment_target() {
+    let error = parser::parse_cst(&scanner::scan_tokens("(a) = 1;").unwrap()).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Compilation error: Invalid assignment target '(a)'"
+    );
+}
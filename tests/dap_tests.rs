@@ -0,0 +1,165 @@
+use rlox::dap;
+use serde_json::{json, Value};
+
+const SRC: &str = "\
+fun add(a, b) {
+    var sum = a + b;
+    return sum;
+}
+print add(1, 2);
+";
+
+/// Sends the requests to a debug adapter, returning the messages it sent back.
+fn session(requests: &[(&str, Value)]) -> Vec<Value> {
+    let mut input = String::new();
+    for (seq, (command, arguments)) in requests.iter().enumerate() {
+        let request = json!({
+            "seq": seq + 1,
+            "type": "request",
+            "command": command,
+            "arguments": arguments,
+        })
+        .to_string();
+        input.push_str(&format!(
+            "Content-Length: {}\r\n\r\n{}",
+            request.len(),
+            request
+        ));
+    }
+
+    let mut output = Vec::new();
+    dap::serve(input.as_bytes(), &mut output).unwrap();
+
+    let output = String::from_utf8(output).unwrap();
+    output
+        .split("Content-Length: ")
+        .skip(1)
+        .map(|message| {
+            let (length, content) = message.split_once("\r\n\r\n").unwrap();
+            assert_eq!(length.parse::<usize>().unwrap(), content.len());
+            serde_json::from_str(content).unwrap()
+        })
+        .collect()
+}
+
+fn script(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("rlox_dap_{}_{}.lox", name, std::process::id()));
+    std::fs::write(&path, SRC).unwrap();
+    path.to_str().unwrap().into()
+}
+
+fn response<'a>(messages: &'a [Value], command: &str) -> &'a Value {
+    messages
+        .iter()
+        .find(|message| message["type"] == "response" && message["command"] == command)
+        .unwrap_or_else(|| panic!("no response to {}", command))
+}
+
+fn events<'a>(messages: &'a [Value], event: &str) -> Vec<&'a Value> {
+    messages
+        .iter()
+        .filter(|message| message["type"] == "event" && message["event"] == event)
+        .collect()
+}
+
+#[test]
+fn test_breakpoint_and_inspection() {
+    let program = script("breakpoint");
+    let messages = session(&[
+        ("initialize", json!({ "adapterID": "rlox" })),
+        ("launch", json!({ "program": program })),
+        (
+            "setBreakpoints",
+            json!({ "source": { "path": program }, "breakpoints": [{ "line": 3 }, { "line": 4 }] }),
+        ),
+        ("configurationDone", json!({})),
+        ("stackTrace", json!({ "threadId": 1 })),
+        ("scopes", json!({ "frameId": 2 })),
+        ("variables", json!({ "variablesReference": 3 })),
+        ("evaluate", json!({ "expression": "sum * 2", "frameId": 2 })),
+        ("continue", json!({ "threadId": 1 })),
+    ]);
+
+    assert!(
+        response(&messages, "initialize")["body"]["supportsConfigurationDoneRequest"]
+            .as_bool()
+            .unwrap()
+    );
+    assert_eq!(events(&messages, "initialized").len(), 1);
+
+    let breakpoints = &response(&messages, "setBreakpoints")["body"]["breakpoints"];
+    assert_eq!(breakpoints[0]["verified"], true);
+    assert_eq!(breakpoints[1]["verified"], false);
+
+    let stopped = events(&messages, "stopped");
+    assert_eq!(stopped.len(), 1);
+    assert_eq!(stopped[0]["body"]["reason"], "breakpoint");
+
+    let frames = &response(&messages, "stackTrace")["body"]["stackFrames"];
+    assert_eq!(frames[0]["name"], "add");
+    assert_eq!(frames[0]["line"], 3);
+    assert_eq!(frames[1]["name"], "<script>");
+    assert_eq!(frames[1]["line"], 5);
+
+    let scopes = &response(&messages, "scopes")["body"]["scopes"];
+    assert_eq!(scopes[0]["name"], "Locals");
+    assert_eq!(scopes[0]["variablesReference"], 3);
+
+    let variables = &response(&messages, "variables")["body"]["variables"];
+    let variables: Vec<(&str, &str)> = variables
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|variable| {
+            (
+                variable["name"].as_str().unwrap(),
+                variable["value"].as_str().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(variables, vec![("a", "1"), ("b", "2"), ("sum", "3")]);
+
+    assert_eq!(response(&messages, "evaluate")["body"]["result"], "6");
+
+    let output = events(&messages, "output");
+    assert_eq!(output[0]["body"]["output"], "3\n");
+    assert_eq!(events(&messages, "exited")[0]["body"]["exitCode"], 0);
+    assert_eq!(events(&messages, "terminated").len(), 1);
+}
+
+#[test]
+fn test_stop_on_entry_and_step() {
+    let program = script("step");
+    let messages = session(&[
+        ("initialize", json!({})),
+        ("launch", json!({ "program": program, "stopOnEntry": true })),
+        ("configurationDone", json!({})),
+        ("next", json!({ "threadId": 1 })),
+        ("stepIn", json!({ "threadId": 1 })),
+        ("stepOut", json!({ "threadId": 1 })),
+    ]);
+
+    let reasons: Vec<&Value> = events(&messages, "stopped")
+        .iter()
+        .map(|event| &event["body"]["reason"])
+        .collect();
+    assert_eq!(reasons, vec!["entry", "step", "step"]);
+    assert_eq!(events(&messages, "exited")[0]["body"]["exitCode"], 0);
+}
+
+#[test]
+fn test_disconnect_while_paused() {
+    let program = script("disconnect");
+    let messages = session(&[
+        ("initialize", json!({})),
+        ("launch", json!({ "program": program, "stopOnEntry": true })),
+        ("configurationDone", json!({})),
+        ("disconnect", json!({})),
+    ]);
+
+    assert!(response(&messages, "disconnect")["success"]
+        .as_bool()
+        .unwrap());
+    assert!(events(&messages, "output").is_empty());
+    assert!(events(&messages, "exited").is_empty());
+}
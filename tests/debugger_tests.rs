@@ -0,0 +1,133 @@
+use rlox::{
+    debugger::{Console, Script},
+    error::{ErrorOrEarlyReturn, RuntimeError},
+    interpreter::Interpreter,
+};
+
+const SRC: &str = "\
+fun add(a, b) {
+    var sum = a + b;
+    return sum;
+}
+var x = 1;
+var y = add(x, 2);
+print y;
+";
+
+/// Runs the script in the console debugger, returning what the debugger and the script printed.
+fn debug(src: &str, commands: &str) -> (String, String, Result<(), ErrorOrEarlyReturn>) {
+    let script = Script::compile(src).unwrap();
+    let mut console_output = Vec::new();
+    let mut output = Vec::new();
+
+    let result = {
+        let mut console = Console::new(&script, commands.as_bytes(), &mut console_output);
+        let mut int = Interpreter::new(&mut output);
+        int.set_debugger(&mut console);
+        script.run(&mut int)
+    };
+
+    (
+        String::from_utf8(console_output).unwrap(),
+        String::from_utf8(output).unwrap(),
+        result,
+    )
+}
+
+/// Lines where the debugger paused.
+fn paused_lines(console: &str) -> Vec<&str> {
+    console
+        .lines()
+        .map(|line| line.trim_start_matches("(debug) "))
+        .filter(|line| line.starts_with("Paused at line "))
+        .collect()
+}
+
+#[test]
+fn test_lines() {
+    let script = Script::compile(SRC).unwrap();
+    assert_eq!(
+        script.lines().into_iter().collect::<Vec<_>>(),
+        vec![1, 2, 3, 5, 6, 7]
+    );
+
+    let script = Script::compile("for (var i = 0;\n i < 2;\n i = i + 1) {\n print i;\n}").unwrap();
+    assert_eq!(script.lines().into_iter().collect::<Vec<_>>(), vec![1, 4]);
+}
+
+#[test]
+fn test_breakpoint() {
+    let (console, output, result) = debug(SRC, "b 3\nc\nlocals\nglobals\nbt\nc\n");
+    assert!(result.is_ok());
+    assert_eq!(output, "3\n");
+    assert_eq!(
+        paused_lines(&console),
+        vec![
+            "Paused at line 1 (entry): fun add(a, b) {",
+            "Paused at line 3 (breakpoint): return sum;",
+        ]
+    );
+    assert!(console.contains("Breakpoint at line 3\n"));
+    assert!(console.contains("  a = 1\n  b = 2\n  sum = 3\n"));
+    assert!(console.contains("  add = fun add\n  x = 1\n"));
+    assert!(console.contains("  add at line 3\n  <script> at line 6\n"));
+}
+
+#[test]
+fn test_breakpoint_without_statement() {
+    let (console, _, _) = debug(SRC, "b 4\nd 4\nc\n");
+    assert!(console.contains("No statement at line 4\n"));
+    assert!(console.contains("No breakpoint at line 4\n"));
+}
+
+#[test]
+fn test_step_over() {
+    let (console, _, _) = debug(SRC, "n\nn\nn\nn\n");
+    assert_eq!(
+        paused_lines(&console),
+        vec![
+            "Paused at line 1 (entry): fun add(a, b) {",
+            "Paused at line 5 (step): var x = 1;",
+            "Paused at line 6 (step): var y = add(x, 2);",
+            "Paused at line 7 (step): print y;",
+        ]
+    );
+}
+
+#[test]
+fn test_step_in_and_out() {
+    let (console, output, _) = debug(SRC, "n\nn\ns\ns\nf\nc\n");
+    assert_eq!(output, "3\n");
+    assert_eq!(
+        paused_lines(&console),
+        vec![
+            "Paused at line 1 (entry): fun add(a, b) {",
+            "Paused at line 5 (step): var x = 1;",
+            "Paused at line 6 (step): var y = add(x, 2);",
+            "Paused at line 2 (step): var sum = a + b;",
+            "Paused at line 3 (step): return sum;",
+            "Paused at line 7 (step): print y;",
+        ]
+    );
+}
+
+#[test]
+fn test_print_in_paused_frame() {
+    let (console, _, _) = debug(SRC, "b 3\nc\np sum * 10\np missing\nc\n");
+    assert!(console.contains("(debug) 30\n"));
+    assert!(console.contains("(debug) Error: "));
+}
+
+#[test]
+fn test_quit() {
+    let (_, output, result) = debug(SRC, "q\n");
+    assert_eq!(output, "");
+    assert!(matches!(
+        result,
+        Err(ErrorOrEarlyReturn::RuntimeError(RuntimeError::Terminated))
+    ));
+
+    // the end of the commands too
+    let (_, _, result) = debug(SRC, "");
+    assert!(result.is_err());
+}
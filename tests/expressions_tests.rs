@@ -1,4 +1,5 @@
 use rlox::{environment::Value, scanner, Result, parser, interpreter};
+use rlox::{ast::{BinaryExpr, ExprKind}, scanner::TokenType};
 
 fn eval(expr: &str) -> Result<Value> {
     let tokens = scanner::scan_tokens(expr)?;
@@ -41,4 +42,29 @@ fn test_precedence() {
     // - vs /
     assert_eq!(eval("1 - 1 / 2").unwrap(), Value::Number(0.5));
     assert_eq!(eval("2 / 1 - 1").unwrap(), Value::Number(1.0));
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_assignment_and_logic() {
+    let stdout = &mut std::io::stdout();
+    let mut interpreter = interpreter::Interpreter::new(stdout);
+    interpreter.global_env().borrow_mut().define("a", Value::Nil);
+
+    assert_eq!(interpreter.eval_expr("a = 1").unwrap(), Value::Number(1.0));
+    assert_eq!(interpreter.eval_expr("a + 1").unwrap(), Value::Number(2.0));
+    assert_eq!(eval("true and false").unwrap(), Value::Boolean(false));
+
+    // the whole expression is parsed, not only `a`
+    let tokens = scanner::scan_tokens("a or b").unwrap();
+    let or = parser::parse_expr(&tokens).unwrap();
+    assert!(matches!(or.kind, ExprKind::Binary(BinaryExpr { op: TokenType::Or, .. })));
+    interpreter.global_env().borrow_mut().define("b", Value::Nil);
+    assert!(interpreter.eval_expr("a or b").is_ok());
+}
+
+#[test]
+fn test_trailing_tokens() {
+    assert!(eval("1 + 2 3").is_err());
+    assert!(eval("1;").is_err());
+    assert!(eval("(1))").is_err());
+}
@@ -0,0 +1,128 @@
+use rlox::formatter::{format, is_formatted, FormatOptions};
+
+fn fmt(src: &str) -> String {
+    format(src, &FormatOptions::default()).unwrap()
+}
+
+#[test]
+fn test_spacing_around_operators() {
+    assert_eq!(fmt("var   a=1+2*-3 ;"), "var a = 1 + 2 * -3;\n");
+    assert_eq!(fmt("print !(a==b)and c-d;"), "print !(a == b) and c - d;\n");
+}
+
+#[test]
+fn test_calls_and_declarations() {
+    assert_eq!(
+        fmt("fun add(x,y){return x+y;} print add( 1 , 2 );"),
+        "fun add(x, y) {\n    return x + y;\n}\nprint add(1, 2);\n"
+    );
+}
+
+#[test]
+fn test_indentation_and_braces() {
+    assert_eq!(
+        fmt("if(a){print 1;}else if(b){print 2;}else{while(c){c=c-1;}}"),
+        "\
+if (a) {
+    print 1;
+} else if (b) {
+    print 2;
+} else {
+    while (c) {
+        c = c - 1;
+    }
+}
+"
+    );
+}
+
+#[test]
+fn test_empty_block() {
+    assert_eq!(fmt("fun f(){}"), "fun f() {}\n");
+}
+
+#[test]
+fn test_for_loop_clauses() {
+    assert_eq!(
+        fmt("for(var i=0;i<10;i=i+1)print i;"),
+        "for (var i = 0; i < 10; i = i + 1) print i;\n"
+    );
+    assert_eq!(fmt("for(;;){}"), "for (;;) {}\n");
+}
+
+#[test]
+fn test_comments_survive() {
+    assert_eq!(
+        fmt("// header\nvar a = 1;   // trailing\n{\n// inside\nprint a;}\n// footer"),
+        "\
+// header
+var a = 1; // trailing
+{
+    // inside
+    print a;
+}
+// footer
+"
+    );
+}
+
+#[test]
+fn test_block_comments_survive() {
+    assert_eq!(
+        fmt("/** doc */\nfun f() {}\n/* one\n   two */\nprint 1; /* trailing */"),
+        "\
+/** doc */
+fun f() {}
+/* one
+   two */
+print 1; /* trailing */
+"
+    );
+}
+
+#[test]
+fn test_comment_inside_expression() {
+    assert_eq!(
+        fmt("var a = 1 + // one\n2;"),
+        "var a = 1 + // one\n    2;\n"
+    );
+}
+
+#[test]
+fn test_blank_lines_are_collapsed() {
+    assert_eq!(
+        fmt("var a;\n\n\n\nvar b;\nvar c;"),
+        "var a;\n\nvar b;\nvar c;\n"
+    );
+    assert_eq!(fmt("{\n\nprint 1;\n\n}"), "{\n    print 1;\n}\n");
+}
+
+#[test]
+fn test_line_wrapping() {
+    let options = FormatOptions {
+        width: 30,
+        ..FormatOptions::default()
+    };
+    assert_eq!(
+        format("print first + second + third + fourth;", &options).unwrap(),
+        "print first + second + third +\n    fourth;\n"
+    );
+    assert_eq!(
+        format("{ f(argument1, argument2, argument3); }", &options).unwrap(),
+        "{\n    f(argument1, argument2,\n        argument3);\n}\n"
+    );
+}
+
+#[test]
+fn test_formatting_is_idempotent() {
+    let src = "// c\nfun f(n){if(n<=1)return 1;// base\nreturn n*f(n-1);}\n\n\nprint f(10);";
+    let once = fmt(src);
+    assert_eq!(fmt(&once), once);
+    assert!(is_formatted(&once, &FormatOptions::default()).unwrap());
+    assert!(!is_formatted(src, &FormatOptions::default()).unwrap());
+}
+
+#[test]
+fn test_invalid_code_is_rejected() {
+    assert!(format("print ;", &FormatOptions::default()).is_err());
+}
@@ -0,0 +1,111 @@
+use rlox::{
+    incremental::{Edit, IncrementalLexer},
+    scanner::{self, Token},
+};
+
+/// Checks the tokens against scanning the whole source code again.
+fn assert_rescanned(lexer: &IncrementalLexer) {
+    let (expected, error) = scanner::scan_tokens_until_error(lexer.source());
+    let actual: Vec<Token> = lexer.tokens().collect();
+    assert_eq!(actual, expected, "tokens of {:?}", lexer.source());
+    assert_eq!(
+        lexer.error().map(|error| error.to_string()),
+        error.map(|error| error.to_string()),
+        "error of {:?}",
+        lexer.source()
+    );
+}
+
+fn edit(lexer: &mut IncrementalLexer, range: std::ops::Range<usize>, text: &str) -> usize {
+    let scanned = lexer.edit(Edit {
+        range,
+        text: text.into(),
+    });
+    assert_rescanned(lexer);
+    scanned.len()
+}
+
+#[test]
+fn test_new() {
+    let lexer = IncrementalLexer::new("print 1; // one\nprint 2;".into());
+    assert_rescanned(&lexer);
+}
+
+#[test]
+fn test_only_the_edited_tokens_are_scanned() {
+    let src = "var a = 1;\n".repeat(100);
+    let mut lexer = IncrementalLexer::new(src);
+
+    // `1` becomes `123`
+    let offset = 50 * 11 + 8;
+    assert!(edit(&mut lexer, offset + 1..offset + 1, "23") <= 3);
+    // a new line in the middle shifts the lines of the rest
+    assert!(edit(&mut lexer, offset..offset, "print x;\n") <= 8);
+    assert_eq!(lexer.tokens().last().unwrap().line, 102);
+}
+
+#[test]
+fn test_edit_joins_tokens() {
+    let mut lexer = IncrementalLexer::new("print 1.;".into());
+    edit(&mut lexer, 8..8, "5");
+    assert!(lexer.tokens().any(|token| token.lexeme == "1.5"));
+
+    edit(&mut lexer, 0..0, "/");
+    edit(&mut lexer, 0..0, "/");
+    assert_eq!(lexer.tokens().count(), 2);
+}
+
+#[test]
+fn test_errors() {
+    let mut lexer = IncrementalLexer::new("print 1;\nprint 2;".into());
+    edit(&mut lexer, 6..6, "\"");
+    assert!(lexer.error().is_some());
+    edit(&mut lexer, 8..8, "\"");
+    assert!(lexer.error().is_none());
+
+    edit(&mut lexer, 0..0, "/* ");
+    assert!(lexer.error().is_some());
+    let end = lexer.source().len();
+    edit(&mut lexer, end..end, " */");
+    assert!(lexer.error().is_none());
+}
+
+/// Random edits of a program, including ones that make it invalid.
+#[test]
+fn test_random_edits() {
+    const TEXTS: &[&str] = &[
+        "", "a", "1", ".", "5", "/", "*", "\"", "\n", " ", "/*", "*/", "//", "e", "0x", "_",
+        "print x;", "é",
+    ];
+
+    // SplitMix64, so the edits are the same on every run
+    let mut state: u64 = 0xed17;
+    let mut next = move || {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    };
+
+    let mut lexer = IncrementalLexer::new(
+        "fun f(a) {\n  // body\n  return a * 1.5;\n}\nprint f(2) + \"s\";\n".into(),
+    );
+    for _ in 0..2000 {
+        let source = lexer.source();
+        let boundaries: Vec<usize> = (0..=source.len())
+            .filter(|&i| source.is_char_boundary(i))
+            .collect();
+        let a = boundaries[(next() % boundaries.len() as u64) as usize];
+        let b = boundaries[(next() % boundaries.len() as u64) as usize];
+        let length = (a.max(b) - a.min(b)).min(3);
+        let start = a.min(b);
+        let end = boundaries
+            .iter()
+            .copied()
+            .find(|&i| i >= start + length)
+            .unwrap();
+        let text = TEXTS[(next() % TEXTS.len() as u64) as usize];
+        edit(&mut lexer, start..end, text);
+    }
+}
@@ -1,13 +1,22 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
 use rlox::{
-    ast::Statement,
+    ast::{Program, Statement},
     error::{ErrorOrEarlyReturn, RuntimeError},
-    interpreter::Interpreter,
+    interpreter::{Interpreter, DEFAULT_MAX_CALL_DEPTH},
+    native::VirtualTimeSource,
     parser, scanner, Result,
 };
 
 fn parse_stmts(src: &str) -> Result<Vec<Statement>> {
     let tokens = scanner::scan_tokens(src).unwrap();
-    parser::parse(&tokens)
+    parser::parse(&tokens).map(Program::into_statements)
 }
 
 /// executes the program and returns the generated output
@@ -758,7 +767,93 @@ fn test_stack_overflow() {
             "
         ),
         Err(ErrorOrEarlyReturn::RuntimeError(
-            RuntimeError::StackOverflow
+            RuntimeError::StackOverflow(_, _)
+        ))
+    ));
+}
+
+#[test]
+fn test_stack_overflow_reports_depth_and_function() {
+    match exec_stmts(
+        "
+            fun f() {
+                f();
+            }
+            f();
+        ",
+    ) {
+        Err(ErrorOrEarlyReturn::RuntimeError(RuntimeError::StackOverflow(depth, function))) => {
+            assert_eq!(depth, DEFAULT_MAX_CALL_DEPTH + 1);
+            assert_eq!(function, "f");
+        }
+        other => panic!("expected a stack overflow, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_call_depth_ignores_blocks() {
+    let statements = parse_stmts(
+        "
+            fun f(n) {
+                { { { { if (n > 0) { f(n - 1); } } } } }
+            }
+            f(99);
+        ",
+    )
+    .unwrap();
+    let mut out = Vec::new();
+    let mut int = Interpreter::new(&mut out);
+    int.set_max_call_depth(100);
+
+    for stmt in statements {
+        int.exec_stmt(&stmt).unwrap();
+    }
+    assert_eq!(int.call_depth(), 0);
+}
+
+#[test]
+fn test_configurable_max_call_depth() {
+    let statements = parse_stmts(
+        "
+            fun f(n) {
+                if (n > 0) f(n - 1);
+            }
+            f(10);
+        ",
+    )
+    .unwrap();
+    let mut out = Vec::new();
+    let mut int = Interpreter::new(&mut out);
+    int.set_max_call_depth(10);
+
+    let result = statements
+        .iter()
+        .try_for_each(|stmt| int.exec_stmt(stmt).map(|_| ()));
+    assert!(matches!(
+        result,
+        Err(ErrorOrEarlyReturn::RuntimeError(
+            RuntimeError::StackOverflow(11, ref function)
+        )) if function == "f"
+    ));
+}
+
+#[test]
+fn test_stack_overflow_with_deep_expressions() {
+    let src = format!(
+        "
+            fun f(n) {{
+                if (n == 0) return 0;
+                return 1 + {}f(n - 1){};
+            }}
+            f(100000);
+        ",
+        "(".repeat(200),
+        ")".repeat(200)
+    );
+    assert!(matches!(
+        exec_stmts(&src),
+        Err(ErrorOrEarlyReturn::RuntimeError(
+            RuntimeError::StackOverflow(_, _)
         ))
     ));
 }
@@ -772,6 +867,72 @@ fn test_native_function_call() {
         .is_ok());
 }
 
+#[test]
+fn test_script_arguments() {
+    let mut out = Vec::new();
+    let mut int = Interpreter::new(&mut out);
+    int.set_args(vec!["first".into(), "second".into()]);
+
+    for stmt in parse_stmts("print argc(); print argv(1); print argv(2);").unwrap() {
+        int.exec_stmt(&stmt).unwrap();
+    }
+
+    drop(int);
+    assert_eq!(String::from_utf8(out).unwrap(), "2\nsecond\nNil\n");
+}
+
+#[test]
+fn test_native_function_with_wrong_number_of_arguments() {
+    assert!(matches!(
+        exec_stmts("clock(1);"),
+        Err(ErrorOrEarlyReturn::RuntimeError(
+            RuntimeError::NumberOfArgumentsMismatch(0, _, 1)
+        ))
+    ));
+}
+
+#[test]
+fn test_virtual_clock() {
+    let clock = VirtualTimeSource::new(10.0);
+    let mut out = Vec::new();
+    let mut int = Interpreter::new(&mut out);
+    int.set_time_source(Box::new(clock.clone()));
+
+    for stmt in parse_stmts("print clock();").unwrap() {
+        int.exec_stmt(&stmt).unwrap();
+    }
+    clock.advance(1.5);
+    for stmt in parse_stmts("print clock(); print clock();").unwrap() {
+        int.exec_stmt(&stmt).unwrap();
+    }
+
+    drop(int);
+    assert_eq!(String::from_utf8(out).unwrap(), "10\n11.5\n11.5\n");
+}
+
+#[test]
+fn test_seeded_random_is_replayable() {
+    let run = |seed| {
+        let mut out = Vec::new();
+        let mut int = Interpreter::new(&mut out);
+        int.seed_random(seed);
+        for stmt in parse_stmts("print random(); print random(); print random();").unwrap() {
+            int.exec_stmt(&stmt).unwrap();
+        }
+        drop(int);
+        String::from_utf8(out).unwrap()
+    };
+
+    let first = run(42);
+    assert_eq!(first, run(42));
+    assert_ne!(first, run(43));
+
+    for line in first.lines() {
+        let n = line.parse::<f64>().unwrap();
+        assert!((0.0..1.0).contains(&n));
+    }
+}
+
 #[test]
 fn test_simple_closure() {
     assert_eq!(
@@ -841,3 +1002,33 @@ fn test_returned_closure_different_envs() {
         "1\n2\n1\n2\n"
     );
 }
+
+#[test]
+fn test_interrupt() {
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let mut out = Vec::new();
+    let mut int = Interpreter::new(&mut out);
+    int.set_interrupt(interrupted.clone());
+
+    let flag = interrupted.clone();
+    let handler = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(50));
+        flag.store(true, Ordering::Relaxed);
+    });
+    let result = parse_stmts("var i = 0; while (true) { i = i + 1; }")
+        .unwrap()
+        .iter()
+        .try_for_each(|stmt| int.exec_stmt(stmt).map(drop));
+    handler.join().unwrap();
+    assert!(matches!(
+        result,
+        Err(ErrorOrEarlyReturn::RuntimeError(RuntimeError::Interrupted))
+    ));
+    assert!(!interrupted.load(Ordering::Relaxed));
+
+    // the session goes on
+    for stmt in parse_stmts("print i > 0;").unwrap() {
+        int.exec_stmt(&stmt).unwrap();
+    }
+    assert_eq!(String::from_utf8(out).unwrap(), "true\n");
+}
@@ -0,0 +1,297 @@
+use std::thread::JoinHandle;
+
+use lsp_server::{Connection, Message, Notification, Request, RequestId};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidOpenTextDocument, Exit, Initialized,
+        Notification as LspNotification, PublishDiagnostics,
+    },
+    request::{
+        Completion, DocumentSymbolRequest, GotoDefinition, HoverRequest, Initialize, References,
+        Request as LspRequest, Shutdown,
+    },
+    CompletionParams, CompletionResponse, DidChangeTextDocumentParams, DidOpenTextDocumentParams,
+    DocumentSymbolParams, DocumentSymbolResponse, GotoDefinitionParams, GotoDefinitionResponse,
+    HoverContents, HoverParams, InitializeParams, Location, Position, PublishDiagnosticsParams,
+    Range, ReferenceContext, ReferenceParams, SymbolKind, TextDocumentContentChangeEvent,
+    TextDocumentIdentifier, TextDocumentItem, TextDocumentPositionParams, Url,
+    VersionedTextDocumentIdentifier,
+};
+use rlox::lsp;
+
+const SRC: &str = "\
+fun fact(n) {
+    if (n <= 1) return 1;
+    return n * fact(n - 1);
+}
+var total = fact(5);
+print total + missing;
+";
+
+/// Editor talking to a server running in another thread.
+struct Client {
+    connection: Connection,
+    server: JoinHandle<Result<(), String>>,
+    next_id: i32,
+}
+
+impl Client {
+    fn start() -> Self {
+        let (server, connection) = Connection::memory();
+        let server = std::thread::spawn(move || lsp::serve(&server).map_err(|e| e.to_string()));
+        let mut client = Client {
+            connection,
+            server,
+            next_id: 0,
+        };
+        client.request::<Initialize>(InitializeParams::default());
+        client.notify::<Initialized>(lsp_types::InitializedParams {});
+        client
+    }
+
+    fn request<R: LspRequest>(&mut self, params: R::Params) -> R::Result {
+        self.next_id += 1;
+        let id = RequestId::from(self.next_id);
+        let request = Request::new(id.clone(), R::METHOD.into(), params);
+        self.connection.sender.send(request.into()).unwrap();
+
+        loop {
+            match self.connection.receiver.recv().unwrap() {
+                Message::Response(response) if response.id == id => {
+                    assert!(response.error.is_none(), "{:?}", response.error);
+                    return serde_json::from_value(response.result.unwrap()).unwrap();
+                }
+                _ => continue,
+            }
+        }
+    }
+
+    fn notify<N: LspNotification>(&self, params: N::Params) {
+        let notification = Notification::new(N::METHOD.into(), params);
+        self.connection.sender.send(notification.into()).unwrap();
+    }
+
+    fn diagnostics(&self) -> PublishDiagnosticsParams {
+        match self.connection.receiver.recv().unwrap() {
+            Message::Notification(notification) => {
+                assert_eq!(notification.method, PublishDiagnostics::METHOD);
+                serde_json::from_value(notification.params).unwrap()
+            }
+            other => panic!("expected diagnostics, got {:?}", other),
+        }
+    }
+
+    fn open(&self, text: &str) -> PublishDiagnosticsParams {
+        self.notify::<DidOpenTextDocument>(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem::new(uri(), "lox".into(), 1, text.into()),
+        });
+        self.diagnostics()
+    }
+
+    fn shutdown(mut self) {
+        self.request::<Shutdown>(());
+        self.notify::<Exit>(());
+        assert_eq!(self.server.join().unwrap(), Ok(()));
+    }
+}
+
+fn uri() -> Url {
+    Url::parse("file:///test.lox").unwrap()
+}
+
+fn at(line: u32, character: u32) -> TextDocumentPositionParams {
+    TextDocumentPositionParams::new(
+        TextDocumentIdentifier::new(uri()),
+        Position::new(line, character),
+    )
+}
+
+fn range(line: u32, start: u32, end: u32) -> Range {
+    Range::new(Position::new(line, start), Position::new(line, end))
+}
+
+#[test]
+fn test_diagnostics_on_open_and_change() {
+    let mut client = Client::start();
+
+    let diagnostics = client.open(SRC).diagnostics;
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].message, "Undefined variable 'missing'.");
+    assert_eq!(diagnostics[0].range, range(5, 14, 21));
+
+    client.notify::<DidChangeTextDocument>(DidChangeTextDocumentParams {
+        text_document: VersionedTextDocumentIdentifier::new(uri(), 2),
+        content_changes: vec![TextDocumentContentChangeEvent {
+            range: None,
+            range_length: None,
+            text: "var a = 1;\nprint a +;\n".into(),
+        }],
+    });
+    let params = client.diagnostics();
+    assert_eq!(params.version, Some(2));
+    assert_eq!(params.diagnostics.len(), 1);
+    assert_eq!(params.diagnostics[0].message, "Expression expected");
+    assert_eq!(params.diagnostics[0].range, range(1, 9, 10));
+
+    // completion still offers the globals of the last version without syntax errors
+    let items = match client.request::<Completion>(CompletionParams {
+        text_document_position: at(1, 0),
+        work_done_progress_params: Default::default(),
+        partial_result_params: Default::default(),
+        context: None,
+    }) {
+        Some(CompletionResponse::Array(items)) => items,
+        other => panic!("unexpected completion {:?}", other),
+    };
+    assert!(items.iter().any(|item| item.label == "total"));
+
+    client.shutdown();
+}
+
+#[test]
+fn test_resolve_diagnostics() {
+    let client = Client::start();
+    let diagnostics = client
+        .open("{ var a = 1; var a = a; }\nfun f(x) { var y = y; }\n")
+        .diagnostics;
+    let messages: Vec<_> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "Already a variable named 'a' in this scope.",
+            "Can't read local variable 'a' in its own initializer.",
+            "Can't read local variable 'y' in its own initializer.",
+        ]
+    );
+    client.shutdown();
+}
+
+#[test]
+fn test_definition_and_references() {
+    let mut client = Client::start();
+    client.open(SRC);
+
+    // `fact` in `fact(n - 1)`
+    let definition = client.request::<GotoDefinition>(GotoDefinitionParams {
+        text_document_position_params: at(2, 16),
+        work_done_progress_params: Default::default(),
+        partial_result_params: Default::default(),
+    });
+    assert_eq!(
+        definition,
+        Some(GotoDefinitionResponse::Scalar(Location::new(
+            uri(),
+            range(0, 4, 8)
+        )))
+    );
+
+    // the parameter `n`
+    let references = client.request::<References>(ReferenceParams {
+        text_document_position: at(0, 9),
+        work_done_progress_params: Default::default(),
+        partial_result_params: Default::default(),
+        context: ReferenceContext {
+            include_declaration: true,
+        },
+    });
+    let ranges: Vec<Range> = references
+        .unwrap()
+        .into_iter()
+        .map(|location| location.range)
+        .collect();
+    assert_eq!(
+        ranges,
+        vec![
+            range(0, 9, 10),
+            range(1, 8, 9),
+            range(2, 11, 12),
+            range(2, 20, 21)
+        ]
+    );
+
+    client.shutdown();
+}
+
+#[test]
+fn test_hover_shows_signatures() {
+    let mut client = Client::start();
+    client.open(SRC);
+
+    let hover = client
+        .request::<HoverRequest>(HoverParams {
+            text_document_position_params: at(4, 13),
+            work_done_progress_params: Default::default(),
+        })
+        .unwrap();
+    match hover.contents {
+        HoverContents::Markup(content) => assert_eq!(content.value, "```lox\nfun fact(n)\n```"),
+        other => panic!("unexpected hover {:?}", other),
+    }
+    assert_eq!(hover.range, Some(range(4, 12, 16)));
+
+    client.shutdown();
+}
+
+#[test]
+fn test_document_symbols() {
+    let mut client = Client::start();
+    client.open("fun f(a) { var b; fun g() {} }\nvar c;\n");
+
+    let symbols = match client.request::<DocumentSymbolRequest>(DocumentSymbolParams {
+        text_document: TextDocumentIdentifier::new(uri()),
+        work_done_progress_params: Default::default(),
+        partial_result_params: Default::default(),
+    }) {
+        Some(DocumentSymbolResponse::Nested(symbols)) => symbols,
+        other => panic!("unexpected symbols {:?}", other),
+    };
+
+    let names = |symbols: &[lsp_types::DocumentSymbol]| {
+        symbols
+            .iter()
+            .map(|symbol| (symbol.name.clone(), symbol.kind))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        names(&symbols),
+        vec![
+            ("f".into(), SymbolKind::FUNCTION),
+            ("c".into(), SymbolKind::VARIABLE)
+        ]
+    );
+    assert_eq!(symbols[0].detail.as_deref(), Some("fun f(a)"));
+    assert_eq!(
+        names(symbols[0].children.as_ref().unwrap()),
+        vec![
+            ("b".into(), SymbolKind::VARIABLE),
+            ("g".into(), SymbolKind::FUNCTION)
+        ]
+    );
+
+    client.shutdown();
+}
+
+#[test]
+fn test_completion_of_names_in_scope() {
+    let mut client = Client::start();
+    client.open("var a;\nfun f(p) {\n    var inner;\n    \n}\n{ var hidden; }\n");
+
+    let labels = match client.request::<Completion>(CompletionParams {
+        text_document_position: at(3, 4),
+        work_done_progress_params: Default::default(),
+        partial_result_params: Default::default(),
+        context: None,
+    }) {
+        Some(CompletionResponse::Array(items)) => {
+            items.into_iter().map(|item| item.label).collect::<Vec<_>>()
+        }
+        other => panic!("unexpected completion {:?}", other),
+    };
+
+    for expected in ["inner", "p", "f", "a", "clock", "while", "return"] {
+        assert!(labels.contains(&expected.into()), "missing {}", expected);
+    }
+    assert!(!labels.contains(&"hidden".into()));
+
+    client.shutdown();
+}
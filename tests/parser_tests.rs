@@ -1,15 +1,17 @@
-use std::rc::Rc;
+use std::sync::Arc;
 
 use rlox::{
+    analysis::Diagnostic,
     ast::*,
     error::{CompilationError, ErrorOrEarlyReturn},
+    interpreter::Interpreter,
     parser,
     scanner::{self, TokenType::*},
     Result,
 };
 
 fn parse(src: &str) -> Result<Vec<Statement>> {
-    parser::parse(&scanner::scan_tokens(src).unwrap())
+    parser::parse(&scanner::scan_tokens(src).unwrap()).map(Program::into_statements)
 }
 
 #[test]
@@ -17,11 +19,15 @@ fn test_sum() {
     let result = parse("1 + 1;").unwrap();
     assert_eq!(
         result,
-        vec![Statement::Expr(Expr::Binary(BinaryExpr {
-            left: Box::new(Expr::Literal(LiteralExpr::Number(1.0))),
-            op: Plus,
-            right: Box::new(Expr::Literal(LiteralExpr::Number(1.0)))
-        }))]
+        vec![StatementKind::Expr(
+            ExprKind::Binary(BinaryExpr {
+                left: Box::new(ExprKind::Literal(LiteralExpr::Number(1.0)).into()),
+                op: Plus,
+                right: Box::new(ExprKind::Literal(LiteralExpr::Number(1.0)).into())
+            })
+            .into()
+        )
+        .into()]
     );
 }
 
@@ -30,11 +36,15 @@ fn test_subtraction() {
     let result = parse("1 - 1;").unwrap();
     assert_eq!(
         result,
-        vec![Statement::Expr(Expr::Binary(BinaryExpr {
-            left: Box::new(Expr::Literal(LiteralExpr::Number(1.0))),
-            op: Minus,
-            right: Box::new(Expr::Literal(LiteralExpr::Number(1.0)))
-        }))]
+        vec![StatementKind::Expr(
+            ExprKind::Binary(BinaryExpr {
+                left: Box::new(ExprKind::Literal(LiteralExpr::Number(1.0)).into()),
+                op: Minus,
+                right: Box::new(ExprKind::Literal(LiteralExpr::Number(1.0)).into())
+            })
+            .into()
+        )
+        .into()]
     );
 }
 
@@ -43,7 +53,7 @@ fn test_print() {
     let result = parse("print 10;").unwrap();
     assert_eq!(
         result,
-        vec![Statement::Print(Expr::Literal(LiteralExpr::Number(10.0)))]
+        vec![StatementKind::Print(ExprKind::Literal(LiteralExpr::Number(10.0)).into()).into()]
     );
 }
 
@@ -52,11 +62,15 @@ fn test_print_with_expr() {
     let result = parse("print 10 + 11;").unwrap();
     assert_eq!(
         result,
-        vec![Statement::Print(Expr::Binary(BinaryExpr {
-            left: Box::new(Expr::Literal(LiteralExpr::Number(10.0))),
-            op: Plus,
-            right: Box::new(Expr::Literal(LiteralExpr::Number(11.0)))
-        }))]
+        vec![StatementKind::Print(
+            ExprKind::Binary(BinaryExpr {
+                left: Box::new(ExprKind::Literal(LiteralExpr::Number(10.0)).into()),
+                op: Plus,
+                right: Box::new(ExprKind::Literal(LiteralExpr::Number(11.0)).into())
+            })
+            .into()
+        )
+        .into()]
     );
 }
 
@@ -64,10 +78,14 @@ fn test_print_with_expr() {
 fn test_assignment() {
     assert_eq!(
         parse("a = 10;").unwrap(),
-        vec![Statement::Expr(Expr::Assignment(
-            "a".into(),
-            Box::new(Expr::Literal(LiteralExpr::Number(10.0)))
-        ))]
+        vec![StatementKind::Expr(
+            ExprKind::Assignment(
+                "a".into(),
+                Box::new(ExprKind::Literal(LiteralExpr::Number(10.0)).into())
+            )
+            .into()
+        )
+        .into()]
     );
 }
 
@@ -75,14 +93,21 @@ fn test_assignment() {
 fn test_assignment_of_expression() {
     assert_eq!(
         parse("a = 10 + 11;").unwrap(),
-        vec![Statement::Expr(Expr::Assignment(
-            "a".into(),
-            Box::new(Expr::Binary(BinaryExpr {
-                left: Box::new(Expr::Literal(LiteralExpr::Number(10.0))),
-                op: Plus,
-                right: Box::new(Expr::Literal(LiteralExpr::Number(11.0)))
-            }))
-        ))]
+        vec![StatementKind::Expr(
+            ExprKind::Assignment(
+                "a".into(),
+                Box::new(
+                    ExprKind::Binary(BinaryExpr {
+                        left: Box::new(ExprKind::Literal(LiteralExpr::Number(10.0)).into()),
+                        op: Plus,
+                        right: Box::new(ExprKind::Literal(LiteralExpr::Number(11.0)).into())
+                    })
+                    .into()
+                )
+            )
+            .into()
+        )
+        .into()]
     );
 }
 
@@ -93,20 +118,32 @@ fn test_assignment_invalid_lvalue() {
 
 #[test]
 fn test_empty_block() {
-    assert_eq!(parse("{}").unwrap(), vec![Statement::Block(vec![])]);
+    assert_eq!(
+        parse("{}").unwrap(),
+        vec![StatementKind::Block(vec![]).into()]
+    );
 }
 
 #[test]
 fn test_nonempty_block() {
     assert_eq!(
         parse("{ var a = 10; a = 1; }").unwrap(),
-        vec![Statement::Block(vec![
-            Statement::VariableDecl("a".into(), Some(Expr::Literal(LiteralExpr::Number(10.0)))),
-            Statement::Expr(Expr::Assignment(
+        vec![StatementKind::Block(vec![
+            StatementKind::VariableDecl(
                 "a".into(),
-                Box::new(Expr::Literal(LiteralExpr::Number(1.0)))
-            ))
-        ])]
+                Some(ExprKind::Literal(LiteralExpr::Number(10.0)).into())
+            )
+            .into(),
+            StatementKind::Expr(
+                ExprKind::Assignment(
+                    "a".into(),
+                    Box::new(ExprKind::Literal(LiteralExpr::Number(1.0)).into())
+                )
+                .into()
+            )
+            .into()
+        ])
+        .into()]
     );
 }
 
@@ -119,13 +156,15 @@ fn test_unfinished_block() {
 fn test_if_then() {
     assert_eq!(
         parse(r#" if (true) print "Hello"; "#).unwrap(),
-        vec![Statement::If(IfStatement {
-            cond: Expr::Literal(LiteralExpr::Boolean(true)),
-            then_branch: Box::new(Statement::Print(Expr::Literal(LiteralExpr::String(
-                "Hello".into()
-            )))),
+        vec![StatementKind::If(IfStatement {
+            cond: ExprKind::Literal(LiteralExpr::Boolean(true)).into(),
+            then_branch: Box::new(
+                StatementKind::Print(ExprKind::Literal(LiteralExpr::String("Hello".into())).into())
+                    .into()
+            ),
             else_branch: None,
-        })]
+        })
+        .into()]
     );
 }
 
@@ -133,13 +172,18 @@ fn test_if_then() {
 fn test_if_then_with_block() {
     assert_eq!(
         parse(r#" if (true) { print "Hello"; } "#).unwrap(),
-        vec![Statement::If(IfStatement {
-            cond: Expr::Literal(LiteralExpr::Boolean(true)),
-            then_branch: Box::new(Statement::Block(vec![Statement::Print(Expr::Literal(
-                LiteralExpr::String("Hello".into())
-            ))])),
+        vec![StatementKind::If(IfStatement {
+            cond: ExprKind::Literal(LiteralExpr::Boolean(true)).into(),
+            then_branch: Box::new(
+                StatementKind::Block(vec![StatementKind::Print(
+                    ExprKind::Literal(LiteralExpr::String("Hello".into())).into()
+                )
+                .into()])
+                .into()
+            ),
             else_branch: None,
-        })]
+        })
+        .into()]
     );
 }
 
@@ -147,15 +191,18 @@ fn test_if_then_with_block() {
 fn test_else() {
     assert_eq!(
         parse(r#" if (true) print "Hello"; else print "World"; "#).unwrap(),
-        vec![Statement::If(IfStatement {
-            cond: Expr::Literal(LiteralExpr::Boolean(true)),
-            then_branch: Box::new(Statement::Print(Expr::Literal(LiteralExpr::String(
-                "Hello".into()
-            )))),
-            else_branch: Some(Box::new(Statement::Print(Expr::Literal(
-                LiteralExpr::String("World".into())
-            )))),
-        })]
+        vec![StatementKind::If(IfStatement {
+            cond: ExprKind::Literal(LiteralExpr::Boolean(true)).into(),
+            then_branch: Box::new(
+                StatementKind::Print(ExprKind::Literal(LiteralExpr::String("Hello".into())).into())
+                    .into()
+            ),
+            else_branch: Some(Box::new(
+                StatementKind::Print(ExprKind::Literal(LiteralExpr::String("World".into())).into())
+                    .into()
+            )),
+        })
+        .into()]
     );
 }
 
@@ -163,15 +210,21 @@ fn test_else() {
 fn test_else_with_block() {
     assert_eq!(
         parse(r#" if (true) print "Hello"; else { print "World"; } "#).unwrap(),
-        vec![Statement::If(IfStatement {
-            cond: Expr::Literal(LiteralExpr::Boolean(true)),
-            then_branch: Box::new(Statement::Print(Expr::Literal(LiteralExpr::String(
-                "Hello".into()
-            )))),
-            else_branch: Some(Box::new(Statement::Block(vec![Statement::Print(
-                Expr::Literal(LiteralExpr::String("World".into()))
-            )]))),
-        })]
+        vec![StatementKind::If(IfStatement {
+            cond: ExprKind::Literal(LiteralExpr::Boolean(true)).into(),
+            then_branch: Box::new(
+                StatementKind::Print(ExprKind::Literal(LiteralExpr::String("Hello".into())).into())
+                    .into()
+            ),
+            else_branch: Some(Box::new(
+                StatementKind::Block(vec![StatementKind::Print(
+                    ExprKind::Literal(LiteralExpr::String("World".into())).into()
+                )
+                .into()])
+                .into()
+            )),
+        })
+        .into()]
     );
 }
 
@@ -188,21 +241,32 @@ fn test_else_if() {
                 print "!"; "#
         )
         .unwrap(),
-        vec![Statement::If(IfStatement {
-            cond: Expr::Literal(LiteralExpr::Boolean(true)),
-            then_branch: Box::new(Statement::Print(Expr::Literal(LiteralExpr::String(
-                "Hello".into()
-            )))),
-            else_branch: Some(Box::new(Statement::If(IfStatement {
-                cond: Expr::Literal(LiteralExpr::Boolean(true)),
-                then_branch: Box::new(Statement::Print(Expr::Literal(LiteralExpr::String(
-                    "World".into()
-                )))),
-                else_branch: Some(Box::new(Statement::Print(Expr::Literal(
-                    LiteralExpr::String("!".into())
-                ))))
-            }))),
-        })]
+        vec![StatementKind::If(IfStatement {
+            cond: ExprKind::Literal(LiteralExpr::Boolean(true)).into(),
+            then_branch: Box::new(
+                StatementKind::Print(ExprKind::Literal(LiteralExpr::String("Hello".into())).into())
+                    .into()
+            ),
+            else_branch: Some(Box::new(
+                StatementKind::If(IfStatement {
+                    cond: ExprKind::Literal(LiteralExpr::Boolean(true)).into(),
+                    then_branch: Box::new(
+                        StatementKind::Print(
+                            ExprKind::Literal(LiteralExpr::String("World".into())).into()
+                        )
+                        .into()
+                    ),
+                    else_branch: Some(Box::new(
+                        StatementKind::Print(
+                            ExprKind::Literal(LiteralExpr::String("!".into())).into()
+                        )
+                        .into()
+                    ))
+                })
+                .into()
+            )),
+        })
+        .into()]
     );
 }
 
@@ -218,19 +282,29 @@ fn test_dangling_else_goes_to_innermost_if() {
                     print "World"; "#
         )
         .unwrap(),
-        vec![Statement::If(IfStatement {
-            cond: Expr::Literal(LiteralExpr::Boolean(true)),
-            then_branch: Box::new(Statement::If(IfStatement {
-                cond: Expr::Literal(LiteralExpr::Boolean(true)),
-                then_branch: Box::new(Statement::Print(Expr::Literal(LiteralExpr::String(
-                    "Hello".into()
-                )))),
-                else_branch: Some(Box::new(Statement::Print(Expr::Literal(
-                    LiteralExpr::String("World".into())
-                )))),
-            })),
+        vec![StatementKind::If(IfStatement {
+            cond: ExprKind::Literal(LiteralExpr::Boolean(true)).into(),
+            then_branch: Box::new(
+                StatementKind::If(IfStatement {
+                    cond: ExprKind::Literal(LiteralExpr::Boolean(true)).into(),
+                    then_branch: Box::new(
+                        StatementKind::Print(
+                            ExprKind::Literal(LiteralExpr::String("Hello".into())).into()
+                        )
+                        .into()
+                    ),
+                    else_branch: Some(Box::new(
+                        StatementKind::Print(
+                            ExprKind::Literal(LiteralExpr::String("World".into())).into()
+                        )
+                        .into()
+                    )),
+                })
+                .into()
+            ),
             else_branch: None,
-        })]
+        })
+        .into()]
     );
 }
 
@@ -243,12 +317,14 @@ fn test_while() {
         "#
         )
         .unwrap(),
-        vec![Statement::While(WhileStatement {
-            cond: Expr::Literal(LiteralExpr::Boolean(false)),
-            stmt: Box::new(Statement::Print(Expr::Literal(LiteralExpr::String(
-                "Hello".into()
-            )))),
-        })]
+        vec![StatementKind::While(WhileStatement {
+            cond: ExprKind::Literal(LiteralExpr::Boolean(false)).into(),
+            stmt: Box::new(
+                StatementKind::Print(ExprKind::Literal(LiteralExpr::String("Hello".into())).into())
+                    .into()
+            ),
+        })
+        .into()]
     );
 }
 
@@ -263,12 +339,17 @@ fn test_while_with_block() {
         "#
         )
         .unwrap(),
-        vec![Statement::While(WhileStatement {
-            cond: Expr::Literal(LiteralExpr::Boolean(false)),
-            stmt: Box::new(Statement::Block(vec![Statement::Print(Expr::Literal(
-                LiteralExpr::String("Hello".into())
-            ))])),
-        })]
+        vec![StatementKind::While(WhileStatement {
+            cond: ExprKind::Literal(LiteralExpr::Boolean(false)).into(),
+            stmt: Box::new(
+                StatementKind::Block(vec![StatementKind::Print(
+                    ExprKind::Literal(LiteralExpr::String("Hello".into())).into()
+                )
+                .into()])
+                .into()
+            ),
+        })
+        .into()]
     );
 }
 
@@ -403,10 +484,14 @@ fn test_for_loop_with_all_clauses() {
 fn test_simplest_function_call() {
     assert_eq!(
         parse("f();").unwrap(),
-        vec![Statement::Expr(Expr::Call(CallExpr {
-            callee: Box::new(Expr::Identifier("f".into())),
-            args: vec![]
-        }))]
+        vec![StatementKind::Expr(
+            ExprKind::Call(CallExpr {
+                callee: Box::new(ExprKind::Identifier("f".into()).into()),
+                args: vec![]
+            })
+            .into()
+        )
+        .into()]
     );
 }
 
@@ -414,10 +499,14 @@ fn test_simplest_function_call() {
 fn test_call_with_one_argument() {
     assert_eq!(
         parse("f(1);").unwrap(),
-        vec![Statement::Expr(Expr::Call(CallExpr {
-            callee: Box::new(Expr::Identifier("f".into())),
-            args: vec![Expr::Literal(LiteralExpr::Number(1.0))]
-        }))]
+        vec![StatementKind::Expr(
+            ExprKind::Call(CallExpr {
+                callee: Box::new(ExprKind::Identifier("f".into()).into()),
+                args: vec![ExprKind::Literal(LiteralExpr::Number(1.0)).into()]
+            })
+            .into()
+        )
+        .into()]
     );
 }
 
@@ -425,22 +514,28 @@ fn test_call_with_one_argument() {
 fn test_call_with_3_arguments() {
     assert_eq!(
         parse("f(1, 2 + a, 3 * b);").unwrap(),
-        vec![Statement::Expr(Expr::Call(CallExpr {
-            callee: Box::new(Expr::Identifier("f".into())),
-            args: vec![
-                Expr::Literal(LiteralExpr::Number(1.0)),
-                Expr::Binary(BinaryExpr {
-                    left: Box::new(Expr::Literal(LiteralExpr::Number(2.0))),
-                    op: Plus,
-                    right: Box::new(Expr::Identifier("a".into())),
-                }),
-                Expr::Binary(BinaryExpr {
-                    left: Box::new(Expr::Literal(LiteralExpr::Number(3.0))),
-                    op: Star,
-                    right: Box::new(Expr::Identifier("b".into())),
-                })
-            ]
-        }))]
+        vec![StatementKind::Expr(
+            ExprKind::Call(CallExpr {
+                callee: Box::new(ExprKind::Identifier("f".into()).into()),
+                args: vec![
+                    ExprKind::Literal(LiteralExpr::Number(1.0)).into(),
+                    ExprKind::Binary(BinaryExpr {
+                        left: Box::new(ExprKind::Literal(LiteralExpr::Number(2.0)).into()),
+                        op: Plus,
+                        right: Box::new(ExprKind::Identifier("a".into()).into()),
+                    })
+                    .into(),
+                    ExprKind::Binary(BinaryExpr {
+                        left: Box::new(ExprKind::Literal(LiteralExpr::Number(3.0)).into()),
+                        op: Star,
+                        right: Box::new(ExprKind::Identifier("b".into()).into()),
+                    })
+                    .into()
+                ]
+            })
+            .into()
+        )
+        .into()]
     );
 }
 
@@ -448,13 +543,23 @@ fn test_call_with_3_arguments() {
 fn test_call_with_nontrivial_callee() {
     assert_eq!(
         parse("(f())();").unwrap(),
-        vec![Statement::Expr(Expr::Call(CallExpr {
-            callee: Box::new(Expr::Grouping(Box::new(Expr::Call(CallExpr {
-                callee: Box::new(Expr::Identifier("f".into())),
-                args: vec![],
-            })))),
-            args: vec![]
-        }))]
+        vec![StatementKind::Expr(
+            ExprKind::Call(CallExpr {
+                callee: Box::new(
+                    ExprKind::Grouping(Box::new(
+                        ExprKind::Call(CallExpr {
+                            callee: Box::new(ExprKind::Identifier("f".into()).into()),
+                            args: vec![],
+                        })
+                        .into()
+                    ))
+                    .into()
+                ),
+                args: vec![]
+            })
+            .into()
+        )
+        .into()]
     );
 }
 
@@ -462,46 +567,52 @@ fn test_call_with_nontrivial_callee() {
 fn test_call_after_call() {
     assert_eq!(
         parse("f()();").unwrap(),
-        vec![Statement::Expr(Expr::Call(CallExpr {
-            callee: Box::new(Expr::Call(CallExpr {
-                callee: Box::new(Expr::Identifier("f".into())),
+        vec![StatementKind::Expr(
+            ExprKind::Call(CallExpr {
+                callee: Box::new(
+                    ExprKind::Call(CallExpr {
+                        callee: Box::new(ExprKind::Identifier("f".into()).into()),
+                        args: vec![],
+                    })
+                    .into()
+                ),
                 args: vec![],
-            })),
-            args: vec![],
-        }))]
+            })
+            .into()
+        )
+        .into()]
     );
 }
 
 #[test]
 fn test_call_trailing_comma() {
-    assert!(matches!(parse("f(a,);"), Err(_)));
+    assert!(parse("f(a,);").is_err());
 }
 
 #[test]
 fn test_call_expression_expected() {
-    assert!(matches!(parse("f(, a);"), Err(_)));
+    assert!(parse("f(, a);").is_err());
 }
 
 #[test]
 fn test_call_without_closing_parenthesis() {
-    assert!(matches!(parse("f(;"), Err(_)))
+    assert!(parse("f(;").is_err())
 }
 
 #[test]
 fn test_call_without_closing_parenthesis_after_arg() {
-    assert!(matches!(parse("f(a;"), Err(_)))
+    assert!(parse("f(a;").is_err())
 }
 
 #[test]
 fn test_call_without_closing_parenthesis_after_args() {
-    assert!(matches!(parse("f(a, b, c;"), Err(_)))
+    assert!(parse("f(a, b, c;").is_err())
 }
 
 #[test]
 fn test_call_with_too_many_arguments() {
-    assert!(matches!(
-        parse(
-            "f(
+    assert!(parse(
+        "f(
                 1, 2, 3, 4, 5, 6, 7, 8, 9, 10,
                 11, 12, 13, 14, 15, 16, 17, 18, 19, 20,
                 21, 22, 23, 24, 25, 26, 27, 28, 29, 30,
@@ -529,20 +640,21 @@ fn test_call_with_too_many_arguments() {
                 241, 242, 243, 244, 245, 246, 247, 248, 249, 250,
                 251, 252, 253, 254, 255, 256, 257
             );"
-        ),
-        Err(_)
-    ));
+    )
+    .is_err());
 }
 
 #[test]
 fn test_function_declaration_without_params_and_empty_body() {
     assert_eq!(
         parse("fun f() {}").unwrap(),
-        vec![Statement::FunDecl(Rc::new(FunctionDecl {
+        vec![StatementKind::FunDecl(Arc::new(FunctionDecl {
+            id: NodeId::fresh(),
             name: "f".into(),
             params: vec![],
             body: vec![]
-        }))]
+        }))
+        .into()]
     );
 }
 
@@ -550,46 +662,51 @@ fn test_function_declaration_without_params_and_empty_body() {
 fn test_function_declaration_with_body() {
     assert_eq!(
         parse("fun f() { var a = 1; }").unwrap(),
-        vec![Statement::FunDecl(Rc::new(FunctionDecl {
+        vec![StatementKind::FunDecl(Arc::new(FunctionDecl {
+            id: NodeId::fresh(),
             name: "f".into(),
             params: vec![],
-            body: vec![Statement::VariableDecl(
+            body: vec![StatementKind::VariableDecl(
                 "a".into(),
-                Some(Expr::Literal(LiteralExpr::Number(1.0)))
-            )]
-        }))]
+                Some(ExprKind::Literal(LiteralExpr::Number(1.0)).into())
+            )
+            .into()]
+        }))
+        .into()]
     );
 }
 
 #[test]
 fn test_function_decl_without_name() {
-    assert!(matches!(parse("fun () {}"), Err(_)));
+    assert!(parse("fun () {}").is_err());
 }
 
 #[test]
 fn test_function_decl_with_invalid_name() {
-    assert!(matches!(parse("fun 1() {}"), Err(_)));
+    assert!(parse("fun 1() {}").is_err());
 }
 
 #[test]
 fn test_function_decl_without_body() {
-    assert!(matches!(parse("fun f () ;"), Err(_)));
+    assert!(parse("fun f () ;").is_err());
 }
 
 #[test]
 fn test_function_decl_without_params() {
-    assert!(matches!(parse("fun f {}"), Err(_)));
+    assert!(parse("fun f {}").is_err());
 }
 
 #[test]
 fn test_function_decl_with_one_param() {
     assert_eq!(
         parse("fun f(x) {}").unwrap(),
-        vec![Statement::FunDecl(Rc::new(FunctionDecl {
+        vec![StatementKind::FunDecl(Arc::new(FunctionDecl {
+            id: NodeId::fresh(),
             name: "f".into(),
             params: vec!["x".into()],
             body: vec![]
-        }))]
+        }))
+        .into()]
     );
 }
 
@@ -597,19 +714,20 @@ fn test_function_decl_with_one_param() {
 fn test_function_decl_with_more_than_one_param() {
     assert_eq!(
         parse("fun f(x, y) {}").unwrap(),
-        vec![Statement::FunDecl(Rc::new(FunctionDecl {
+        vec![StatementKind::FunDecl(Arc::new(FunctionDecl {
+            id: NodeId::fresh(),
             name: "f".into(),
             params: vec!["x".into(), "y".into()],
             body: vec![]
-        }))]
+        }))
+        .into()]
     );
 }
 
 #[test]
 fn test_function_decl_with_too_many_params() {
-    assert!(matches!(
-        parse(
-            "
+    assert!(parse(
+        "
                 fun f(
                     p1, p2, p3, p4, p5, p6, p7, p8, p9, p10,
                     p11, p12, p13, p14, p15, p16, p17, p18, p19, p20,
@@ -639,9 +757,8 @@ fn test_function_decl_with_too_many_params() {
                     p251, p252, p253, p254, p255, p256, p257
                 ) {}
             "
-        ),
-        Err(_)
-    ));
+    )
+    .is_err());
 }
 
 #[test]
@@ -655,11 +772,13 @@ fn test_return_without_expression() {
             "
         )
         .unwrap(),
-        vec![Statement::FunDecl(Rc::new(FunctionDecl {
+        vec![StatementKind::FunDecl(Arc::new(FunctionDecl {
+            id: NodeId::fresh(),
             name: "f".into(),
             params: vec![],
-            body: vec![Statement::Return(None)]
-        }))]
+            body: vec![StatementKind::Return(None).into()]
+        }))
+        .into()]
     );
 }
 
@@ -674,15 +793,21 @@ fn test_return_with_expression() {
             "
         )
         .unwrap(),
-        vec![Statement::FunDecl(Rc::new(FunctionDecl {
+        vec![StatementKind::FunDecl(Arc::new(FunctionDecl {
+            id: NodeId::fresh(),
             name: "f".into(),
             params: vec![],
-            body: vec![Statement::Return(Some(Expr::Binary(BinaryExpr {
-                left: Box::new(Expr::Literal(LiteralExpr::Number(10.0))),
-                op: Plus,
-                right: Box::new(Expr::Literal(LiteralExpr::Number(10.0))),
-            })))]
-        }))]
+            body: vec![StatementKind::Return(Some(
+                ExprKind::Binary(BinaryExpr {
+                    left: Box::new(ExprKind::Literal(LiteralExpr::Number(10.0)).into()),
+                    op: Plus,
+                    right: Box::new(ExprKind::Literal(LiteralExpr::Number(10.0)).into()),
+                })
+                .into()
+            ))
+            .into()]
+        }))
+        .into()]
     );
 }
 
@@ -704,17 +829,15 @@ fn test_return_outside_function() {
 
 #[test]
 fn test_return_inside_outer_function() {
-    assert!(matches!(
-        parse(
-            "
+    assert!(parse(
+        "
                 fun f() {
                     fun g() { }
                     return 10;
                 }
             "
-        ),
-        Ok(_)
-    ));
+    )
+    .is_ok());
 }
 
 #[test]
@@ -735,3 +858,156 @@ fn test_return_after_functions() {
         ))
     ));
 }
+
+#[test]
+fn test_nesting_below_limit() {
+    let src = format!("print {}1{};", "(".repeat(200), ")".repeat(200));
+    assert!(parse(&src).is_ok());
+}
+
+#[test]
+fn test_deeply_nested_parens() {
+    let src = format!("print {}1{};", "(".repeat(100_000), ")".repeat(100_000));
+    assert!(matches!(
+        parse(&src),
+        Err(ErrorOrEarlyReturn::CompilationError(
+            CompilationError::TooDeeplyNested(parser::MAX_NESTING_DEPTH)
+        ))
+    ));
+}
+
+#[test]
+fn test_deeply_nested_blocks() {
+    let src = format!("{}print 1;{}", "{".repeat(100_000), "}".repeat(100_000));
+    assert!(matches!(
+        parse(&src),
+        Err(ErrorOrEarlyReturn::CompilationError(
+            CompilationError::TooDeeplyNested(_)
+        ))
+    ));
+}
+
+#[test]
+fn test_long_operator_chain() {
+    let src = format!("var a = 1; print a{};", " + a".repeat(100_000));
+    let statements = parse(&src).unwrap();
+    assert_eq!(statements.clone(), statements);
+    assert!(format!("{:?}", statements).ends_with("}))]"));
+
+    let mut out = vec![];
+    let mut interpreter = Interpreter::new(&mut out);
+    for stmt in &statements {
+        interpreter.exec_stmt(stmt).unwrap();
+    }
+    assert_eq!(out, b"100001\n");
+
+    // a chain inside parentheses is still one level deep
+    let src = format!(
+        "print {}a{}{};",
+        "(".repeat(200),
+        " + a".repeat(1000),
+        ")".repeat(200)
+    );
+    assert!(parse(&src).is_ok());
+}
+
+/// Writes the expression with a pair of parentheses around every operation.
+fn sexpr(expr: &Expr) -> std::string::String {
+    match &expr.kind {
+        ExprKind::Identifier(name) => name.clone(),
+        ExprKind::Literal(LiteralExpr::Number(number)) => number.to_string(),
+        ExprKind::Literal(literal) => format!("{:?}", literal),
+        ExprKind::Unary(unary) => format!("({:?} {})", unary.op, sexpr(&unary.expr)),
+        ExprKind::Binary(binary) => format!(
+            "({:?} {} {})",
+            binary.op,
+            sexpr(&binary.left),
+            sexpr(&binary.right)
+        ),
+        ExprKind::Grouping(expr) => sexpr(expr),
+        ExprKind::Assignment(name, value) => format!("(= {} {})", name, sexpr(value)),
+        ExprKind::Call(call) => {
+            let args: Vec<std::string::String> = call.args.iter().map(sexpr).collect();
+            format!("(call {} [{}])", sexpr(&call.callee), args.join(" "))
+        }
+    }
+}
+
+#[test]
+fn test_precedence_and_associativity() {
+    for (src, expected) in [
+        ("1 - 2 - 3;", "(Minus (Minus 1 2) 3)"),
+        ("a = b = c;", "(= a (= b c))"),
+        (
+            "1 + 2 * 3 < 4 == !x;",
+            "(EqualEqual (Less (Plus 1 (Star 2 3)) 4) (Bang x))",
+        ),
+        ("a or b and c;", "(Or a (And b c))"),
+        ("-f(1)(2);", "(Minus (call (call f [1]) [2]))"),
+        ("- -x * y;", "(Star (Minus (Minus x)) y)"),
+        ("(1 + 2) / 3;", "(Slash (Plus 1 2) 3)"),
+    ] {
+        match parse(src).unwrap().as_slice() {
+            [Statement {
+                kind: StatementKind::Expr(expr),
+                ..
+            }] => assert_eq!(sexpr(expr), expected, "{}", src),
+            other => panic!("not an expression: {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn test_program() {
+    let src = "var a = 1;\nfun f() { return b; }\nvar a = 2;\nprint a;";
+    let program = parser::parse(&scanner::scan_tokens_lossless(src).unwrap()).unwrap();
+
+    assert_eq!(program.statements().len(), 4);
+    assert_eq!(program.file(), None);
+    assert!(program.imports().is_empty());
+
+    let mut declarations: Vec<&str> = program.declarations().collect();
+    declarations.sort();
+    assert_eq!(declarations, ["a", "f"]);
+    assert_eq!(program.declaration("a"), Some(&program.statements()[2]));
+    assert_eq!(program.declaration("b"), None);
+
+    let b = src.find('b').unwrap();
+    assert_eq!(
+        program.diagnostics(),
+        [Diagnostic {
+            range: b..b + 1,
+            message: "Undefined variable 'b'.".into()
+        }]
+    );
+}
+
+#[test]
+fn test_parse_iter() {
+    let program = parser::parse_iter(scanner::tokens("var a = 1;\nprint a;")).unwrap();
+    assert_eq!(
+        program,
+        parser::parse(&scanner::scan_tokens("var a = 1;\nprint a;").unwrap()).unwrap()
+    );
+
+    // the syntax error comes first
+    assert!(matches!(
+        parser::parse_iter(scanner::tokens("print ;\nprint @;")),
+        Err(ErrorOrEarlyReturn::CompilationError(
+            CompilationError::GenericError(_)
+        ))
+    ));
+    // the parser gets to the scan error
+    assert!(matches!(
+        parser::parse_iter(scanner::tokens("print 1;\nprint @;")),
+        Err(ErrorOrEarlyReturn::CompilationError(
+            CompilationError::UnexpectedCharacter(..)
+        ))
+    ));
+    assert!(matches!(
+        parser::parse_iter(scanner::tokens("print 1; @")),
+        Err(ErrorOrEarlyReturn::CompilationError(
+            CompilationError::UnexpectedCharacter(..)
+        ))
+    ));
+}
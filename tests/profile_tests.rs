@@ -0,0 +1,135 @@
+use std::cell::Cell;
+
+use rlox::{
+    debugger::Script,
+    interpreter::Interpreter,
+    native::TimeSource,
+    profile::{FunctionProfile, Profiler},
+    Result,
+};
+
+/// Clock that moves one second forward every time it is read.
+#[derive(Default)]
+struct Ticks(Cell<f64>);
+
+impl TimeSource for Ticks {
+    fn now(&self) -> Result<f64> {
+        self.0.set(self.0.get() + 1.0);
+        Ok(self.0.get())
+    }
+}
+
+fn profile(src: &str, check: impl FnOnce(&Profiler)) {
+    let script = Script::compile(src).unwrap();
+    let mut profiler = Profiler::new(&script);
+    profiler.set_time_source(Box::new(Ticks::default()));
+    {
+        let mut output = Vec::new();
+        let mut int = Interpreter::new(&mut output);
+        int.set_debugger(&mut profiler);
+        let _ = script.run(&mut int);
+    }
+    profiler.finish().unwrap();
+    check(&profiler);
+}
+
+const SRC: &str = "\
+fun inner() {
+    return 1;
+}
+fun outer() {
+    inner();
+    return inner();
+}
+outer();
+outer();
+";
+
+#[test]
+fn test_functions() {
+    profile(SRC, |profiler| {
+        // each call and return takes a second
+        assert_eq!(
+            profiler.functions(),
+            &[
+                FunctionProfile {
+                    name: "outer".into(),
+                    line: Some(4),
+                    calls: 2,
+                    inclusive: 10.0,
+                    exclusive: 6.0,
+                },
+                FunctionProfile {
+                    name: "inner".into(),
+                    line: Some(1),
+                    calls: 4,
+                    inclusive: 4.0,
+                    exclusive: 4.0,
+                },
+            ]
+        );
+        assert_eq!(profiler.total(), 13.0);
+    });
+}
+
+#[test]
+fn test_lines() {
+    profile(SRC, |profiler| {
+        let lines: Vec<(u32, u64)> = profiler.lines().iter().map(|(&l, &c)| (l, c)).collect();
+        assert_eq!(
+            lines,
+            vec![(1, 1), (2, 4), (4, 1), (5, 2), (6, 2), (8, 1), (9, 1)]
+        );
+    });
+}
+
+#[test]
+fn test_recursion_is_counted_once() {
+    let src = "\
+fun count(n) {
+    if (n > 0) count(n - 1);
+}
+count(2);
+";
+    profile(src, |profiler| {
+        let count = &profiler.functions()[0];
+        assert_eq!(count.calls, 3);
+        assert_eq!(count.inclusive, 5.0);
+        assert_eq!(count.exclusive, 5.0);
+    });
+}
+
+#[test]
+fn test_runtime_error_ends_the_calls() {
+    profile("fun fail() { return 1 / 0; }\nfail();", |profiler| {
+        assert_eq!(profiler.functions()[0].inclusive, 1.0);
+    });
+}
+
+#[test]
+fn test_collapsed_stacks() {
+    profile(SRC, |profiler| {
+        let mut output = Vec::new();
+        profiler.write_collapsed(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\
+<script> 3000000
+<script>;outer 6000000
+<script>;outer;inner 4000000
+"
+        );
+    });
+}
+
+#[test]
+fn test_report() {
+    profile(SRC, |profiler| {
+        let mut output = Vec::new();
+        profiler.write_report(&mut output).unwrap();
+        let report = String::from_utf8(output).unwrap();
+        assert!(report.starts_with("Total time: 13000.000 ms\n"));
+        assert!(report.contains("         2    10000.000     6000.000  outer (line 4)\n"));
+        assert!(report.contains("         4      2  return 1;\n"));
+    });
+}
@@ -0,0 +1,159 @@
+//! Runs the hand-written and the logos scanners over the same sources, which
+//! must give the same tokens, lines and errors.
+
+use rlox::{
+    cst::is_trivia,
+    native::Random,
+    scanner::{Backend, Token},
+};
+
+const CORPUS: &[&str] = &[
+    "",
+    "(){},.-+;/ *",
+    "! != = == > >= < <=",
+    "!!= === <== >>=",
+    "and class else false fun for if nil or print return super this true var while",
+    "andy classic elsewhere _if if_ While VAR",
+    "a _b c1 __ A_B_9",
+    "0 1 123 123.456 0.5",
+    "123. .5 1.2.3 123.a",
+    "0xFF 0xff_FF 0o755 0b1010 1e9 6.02e-23 1E+3 1_000_000 1_000.000_1",
+    "0 00 0x0 1.5e3.2 1e3e 0e0",
+    // malformed numbers, and numbers followed by a word
+    "0x",
+    "0xG",
+    "0b12",
+    "0o8",
+    "1e",
+    "1e+",
+    "print 0or 1;",
+    "print 0band 1;",
+    "if (true) print 1else print 2;",
+    "0o78 0xFFor 1e+x",
+    "1.5e",
+    "1__0",
+    "1_",
+    "1_.5",
+    "1.5_",
+    "print 1;\nx = 1e_3;",
+    "\"\" \"hello\" \"hello, world\"",
+    "\"multi\nline\"\nprint 1;",
+    "\"Bráulio\" \"日本\"",
+    "// comment only",
+    "// comment\nprint 1; // trailing\n// last",
+    "//",
+    "a / b // c / d",
+    "a /* b */ c",
+    "/* one\n/* two\n*/ three\n*/ print 1;",
+    "/**/ /*/ */ /***/ /** doc */ /// doc\n//// four\nx",
+    "a */ b",
+    "a / * b",
+    "\tvar\r\na\n\n\n=\t1;",
+    "print 1;\r\nprint 2;\r\n",
+    "fun fib(n) {\n  if (n < 2) return n;\n  return fib(n - 1) + fib(n - 2);\n}\nprint fib(10);",
+    "for (var i = 0; i < 10; i = i + 1) { print i; }",
+    // errors
+    "@",
+    "print 1;\nvar x = #;",
+    "é",
+    "var é = 1;",
+    "\"unterminated",
+    "print 1;\n/* unterminated",
+    "/* a /* b */\nprint 1;",
+    "/*",
+    "/*/",
+    "print 1;\n\"unterminated\nstring",
+    "1 + 2 ~ 3",
+    "a = 1; $",
+];
+
+fn assert_same(src: &str) {
+    let expected = Backend::RecursiveDescent.scanner().scan_tokens(src);
+    let actual = Backend::Logos.scanner().scan_tokens(src);
+
+    match (expected, actual) {
+        (Ok(expected), Ok(actual)) => assert_eq!(actual, expected, "tokens of {:?}", src),
+        (Err(expected), Err(actual)) => {
+            assert_eq!(
+                actual.to_string(),
+                expected.to_string(),
+                "error of {:?}",
+                src
+            )
+        }
+        (expected, actual) => panic!(
+            "different results for {:?}:\n  hand-written: {:?}\n  logos: {:?}",
+            src, expected, actual
+        ),
+    }
+
+    // the lossless tokens may split the trivia differently, but give back the source
+    let expected = Backend::RecursiveDescent
+        .scanner()
+        .scan_tokens_lossless(src);
+    let actual = Backend::Logos.scanner().scan_tokens_lossless(src);
+    match (expected, actual) {
+        (Ok(expected), Ok(actual)) => {
+            let text: String = actual.iter().map(|token| token.lexeme).collect();
+            assert_eq!(text, src);
+            assert_eq!(
+                significant(actual),
+                significant(expected),
+                "lossless tokens of {:?}",
+                src
+            );
+        }
+        (expected, actual) => assert_eq!(actual.is_ok(), expected.is_ok(), "{:?}", src),
+    }
+}
+
+fn significant(tokens: Vec<Token<'_>>) -> Vec<Token<'_>> {
+    tokens
+        .into_iter()
+        .filter(|token| !is_trivia(token.type_))
+        .collect()
+}
+
+#[test]
+fn test_corpus() {
+    for src in CORPUS {
+        assert_same(src);
+    }
+}
+
+#[test]
+fn test_token_lines() {
+    let src = "a\n\"b\nc\"\n// d\ne";
+    let lines: Vec<u32> = Backend::Logos
+        .scanner()
+        .scan_tokens(src)
+        .unwrap()
+        .iter()
+        .map(|token| token.line)
+        .collect();
+    assert_eq!(lines, vec![1, 2, 5]);
+}
+
+/// Sources made of random fragments, including invalid ones.
+#[test]
+fn test_generated_corpus() {
+    const FRAGMENTS: &[&str] = &[
+        "(", ")", "{", "}", ",", ".", "-", "+", ";", "/", "*", "!", "=", "<", ">", "==", "!=",
+        "<=", ">=", "and", "or", "if", "else", "while", "for", "fun", "var", "print", "return",
+        "nil", "true", "false", "class", "this", "super", "x", "_y", "z9", "ifx", "0", "42",
+        "3.14", "7.", "0x1F", "0b", "0o7", "1e-3", "2E", "_", "e5", "\"s\"", "\"a\nb\"", "\"",
+        "// c\n", "//", "/*", "*/", "/* c */", " ", "\t", "\r", "\n", "@", "é", "#",
+    ];
+
+    // seeded, so the corpus is the same on every run
+    let mut random = Random::with_seed(0x5eed);
+    let mut next = move || random.next_u64();
+
+    for _ in 0..2000 {
+        let length = next() % 12;
+        let src: String = (0..length)
+            .map(|_| FRAGMENTS[(next() % FRAGMENTS.len() as u64) as usize])
+            .collect();
+        assert_same(&src);
+    }
+}
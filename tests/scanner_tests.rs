@@ -52,7 +52,7 @@ fn test_scan_tokens_newline_bug() {
 #[test]
 fn test_all_tokens() {
     let src = r#"
-        (){},.+-;/*
+        (){},.+-;/ *
         ! != = == > >= < <=
         identifier "string" 12345.123
         and class else false fun for if nil or print return super this true var while
@@ -107,3 +107,122 @@ fn test_all_tokens() {
         ]
     );
 }
+
+#[test]
+fn test_scan_tokens_lossless() {
+    let src = "// comment\nprint  1;\n";
+    let tokens = scan_tokens_lossless(src).unwrap();
+    assert_eq!(tokens.iter().map(|t| t.lexeme).collect::<String>(), src);
+    assert_eq!(tokens[0], Token::new(TokenType::Comment, "// comment\n", 1));
+    assert_eq!(tokens.last().unwrap().type_, TokenType::Eof);
+}
+
+#[test]
+fn test_scan_tokens_with_trivia() {
+    let src = "// leading\nprint 1; // trailing\n\n";
+    let tokens = scan_tokens_with_trivia(src).unwrap();
+
+    let types: Vec<_> = tokens.iter().map(|t| t.token.type_).collect();
+    assert_eq!(
+        types,
+        vec![
+            TokenType::Print,
+            TokenType::NumberLiteral,
+            TokenType::Semicolon,
+            TokenType::Eof
+        ]
+    );
+    assert_eq!(
+        tokens[0].leading,
+        vec![Token::new(TokenType::Comment, "// leading\n", 1)]
+    );
+    assert_eq!(
+        tokens[2].trailing,
+        vec![
+            Token::new(TokenType::Whitespace, " ", 2),
+            Token::new(TokenType::Comment, "// trailing\n", 2),
+        ]
+    );
+    assert_eq!(
+        tokens[3].leading,
+        vec![Token::new(TokenType::Whitespace, "\n", 3)]
+    );
+}
+
+#[test]
+fn test_parse_number() {
+    assert_eq!(parse_number("0xFF"), Some(255.0));
+    assert_eq!(parse_number("0b1010"), Some(10.0));
+    assert_eq!(parse_number("0o755"), Some(493.0));
+    assert_eq!(parse_number("1e9"), Some(1e9));
+    assert_eq!(parse_number("6.02e-23"), Some(6.02e-23));
+    assert_eq!(parse_number("1_000_000"), Some(1_000_000.0));
+    assert_eq!(parse_number("1__0"), None);
+    assert_eq!(parse_number("0x"), None);
+}
+
+#[test]
+fn test_scan_tokens_invalid_number() {
+    let error = scan_tokens("var x = 1__0;").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Compilation error: Invalid number literal 1__0"
+    );
+}
+
+#[test]
+fn test_block_and_doc_comments() {
+    let src = "/** doc */\nfun f() {}\n/* outer /* inner */\n*/ /// doc\nprint 1;";
+    let tokens = scan_tokens(src).unwrap();
+    assert_eq!(tokens.len(), 9);
+    assert_eq!(
+        tokens.last().unwrap(),
+        &Token::new(TokenType::Semicolon, ";", 5)
+    );
+
+    let trivia: Vec<(TokenType, &str)> = scan_tokens_lossless(src)
+        .unwrap()
+        .into_iter()
+        .filter(|t| matches!(t.type_, TokenType::Comment | TokenType::DocComment))
+        .map(|t| (t.type_, t.lexeme))
+        .collect();
+    assert_eq!(
+        trivia,
+        vec![
+            (TokenType::DocComment, "/** doc */"),
+            (TokenType::Comment, "/* outer /* inner */\n*/"),
+            (TokenType::DocComment, "/// doc\n"),
+        ]
+    );
+}
+
+#[test]
+fn test_unterminated_block_comment() {
+    let error = scan_tokens("print 1;\n/* a /* b */\nprint 2;").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Compilation error: Unterminated block comment, opened at line 2"
+    );
+}
+
+#[test]
+fn test_tokens_are_lazy() {
+    // the error is never reached
+    let mut tokens = tokens("print 1; @");
+    assert_eq!(
+        tokens.next().unwrap().unwrap(),
+        Token::new(TokenType::Print, "print", 1)
+    );
+
+    let lexer = Lexer::new("a // b");
+    let types: Vec<TokenType> = lexer.map(|token| token.unwrap().type_).collect();
+    assert_eq!(
+        types,
+        vec![
+            TokenType::Identifier,
+            TokenType::Whitespace,
+            TokenType::Comment,
+            TokenType::Eof
+        ]
+    );
+}
@@ -0,0 +1,110 @@
+use rlox::{
+    ast::Program,
+    parser,
+    scanner::{self, OwnedToken, TokenType},
+    source_map::{SourceMap, Span},
+};
+
+#[test]
+fn test_files() {
+    let mut map = SourceMap::new();
+    let a = map.add("a.lox", "print 1;");
+    let b = map.add("b.lox", "print 2;");
+
+    assert_ne!(a, b);
+    assert_eq!(map.name(b), "b.lox");
+    assert_eq!(map.source(a), "print 1;");
+    assert_eq!(
+        map.text(Span {
+            file: b,
+            start: 6,
+            end: 7
+        }),
+        "2"
+    );
+}
+
+#[test]
+fn test_owned_tokens() {
+    let mut map = SourceMap::new();
+    let file = map.add("a.lox", "// one\nvar x = \"é\";\nprint x;");
+    let tokens = map.scan_tokens(file).unwrap();
+
+    assert_eq!(tokens.len(), 8);
+    assert_eq!(
+        tokens[3],
+        OwnedToken::new(
+            TokenType::String,
+            Span {
+                file,
+                start: 15,
+                end: 19
+            },
+            2
+        )
+    );
+    assert_eq!(tokens[3].lexeme(&map), "\"é\"");
+    assert_eq!(tokens[6].to_token(&map).lexeme, "x");
+}
+
+#[test]
+fn test_outlives_the_source() {
+    fn send<T: Send + Sync + 'static>(value: T) -> T {
+        value
+    }
+
+    let (map, file) = {
+        let src = String::from("print 1 + 2;");
+        let mut map = SourceMap::new();
+        let file = map.add("inline", src.as_str());
+        (map, file)
+    };
+    let tokens = send(map.scan_tokens(file).unwrap());
+    let map = std::thread::spawn(move || send(map)).join().unwrap();
+
+    assert_eq!(tokens[1].lexeme(&map), "1");
+    assert_eq!(map.parse(file).unwrap().statements().len(), 1);
+}
+
+#[test]
+fn test_program_crosses_threads() {
+    fn assert_send<T: Send>() {}
+    assert_send::<Program>();
+
+    let program = {
+        let src = String::from(
+            "fun f() { return x; }
+print f;",
+        );
+        parser::parse(&scanner::scan_tokens_lossless(&src).unwrap()).unwrap()
+    };
+    let program = std::thread::spawn(move || {
+        assert_eq!(program.diagnostics().len(), 1);
+        program
+    })
+    .join()
+    .unwrap();
+
+    assert_eq!(program.statements().len(), 2);
+    assert_eq!(program.declarations().collect::<Vec<_>>(), ["f"]);
+}
+
+#[test]
+fn test_parse() {
+    let mut map = SourceMap::new();
+    map.add("a.lox", "print 1;");
+    let file = map.add("b.lox", "// b\nprint x;");
+    let program = map.parse(file).unwrap();
+
+    assert_eq!(program.file(), Some(file));
+    assert_eq!(program.diagnostics().len(), 1);
+    let range = program.diagnostics()[0].range.clone();
+    assert_eq!(&map.source(file)[range], "x");
+}
+
+#[test]
+fn test_errors() {
+    let mut map = SourceMap::new();
+    let file = map.add("a.lox", "print @;");
+    assert!(map.scan_tokens(file).is_err());
+}
@@ -0,0 +1,110 @@
+use rlox::{
+    debugger::Script,
+    interpreter::Interpreter,
+    trace::{TraceFormat, Tracer},
+};
+
+const SRC: &str = "\
+fun add(a, b) {
+    var sum = a + b;
+    return sum;
+}
+print add(1, argc());
+";
+
+/// Runs the script, returning its trace and what it printed.
+fn trace(src: &str, format: TraceFormat) -> (String, String) {
+    let script = Script::compile(src).unwrap();
+    let mut trace = Vec::new();
+    let mut output = Vec::new();
+
+    {
+        let mut tracer = Tracer::new(&script, &mut trace, format);
+        let mut int = Interpreter::new(&mut output);
+        int.set_debugger(&mut tracer);
+        script.run(&mut int).unwrap();
+    }
+
+    (
+        String::from_utf8(trace).unwrap(),
+        String::from_utf8(output).unwrap(),
+    )
+}
+
+#[test]
+fn test_text() {
+    let (trace, output) = trace(SRC, TraceFormat::Text);
+    assert_eq!(output, "1\n");
+    assert_eq!(
+        trace,
+        "\
+1: fun add(a, b) {
+5: print add(1, argc());
+5: call argc()
+5: return from argc: 0
+5: call add(1, 0)
+  2: var sum = a + b;
+  3: return sum;
+3: return from add: 1
+"
+    );
+}
+
+#[test]
+fn test_json_lines() {
+    let (trace, _) = trace(SRC, TraceFormat::Json);
+    let entries: Vec<serde_json::Value> = trace
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+
+    assert_eq!(entries.len(), 8);
+    assert_eq!(
+        entries[4],
+        serde_json::json!({
+            "event": "call",
+            "depth": 0,
+            "line": 5,
+            "function": "add",
+            "args": [1.0, 0.0],
+        })
+    );
+    assert_eq!(entries[5]["depth"], 1);
+    assert_eq!(entries[5]["source"], "var sum = a + b;");
+    assert_eq!(entries[7]["event"], "return");
+    assert_eq!(entries[7]["value"], 1.0);
+}
+
+#[test]
+fn test_strings_are_quoted() {
+    let (trace, _) = trace("fun id(x) { return x; }\nid(\"a\");", TraceFormat::Text);
+    assert!(trace.contains("2: call id(\"a\")\n"));
+    assert!(trace.contains("return from id: \"a\"\n"));
+}
+
+#[test]
+fn test_nested_calls() {
+    let src = "\
+fun fact(n) {
+    if (n <= 1) return 1;
+    return n * fact(n - 1);
+}
+fact(2);
+";
+    let (trace, _) = trace(src, TraceFormat::Text);
+    assert_eq!(
+        trace,
+        "\
+1: fun fact(n) {
+5: fact(2);
+5: call fact(2)
+  2: if (n <= 1) return 1;
+  3: return n * fact(n - 1);
+  3: call fact(1)
+    2: if (n <= 1) return 1;
+    2: if (n <= 1) return 1;
+  2: return from fact: 1
+3: return from fact: 2
+"
+    );
+}
@@ -0,0 +1,195 @@
+use rlox::{
+    ast::*,
+    parser,
+    scanner::{self, TokenType},
+    visit::{self, Fold, Visitor, VisitorMut},
+};
+
+fn parse(src: &str) -> Vec<Statement> {
+    parser::parse(&scanner::scan_tokens(src).unwrap())
+        .unwrap()
+        .into_statements()
+}
+
+#[derive(Default)]
+struct Names<'ast> {
+    used: Vec<&'ast str>,
+    functions: usize,
+}
+
+impl<'ast> Visitor<'ast> for Names<'ast> {
+    fn visit_function(&mut self, function: &'ast std::sync::Arc<FunctionDecl>) {
+        self.functions += 1;
+        visit::walk_function(self, function);
+    }
+
+    fn visit_identifier(&mut self, name: &'ast str) {
+        self.used.push(name);
+    }
+}
+
+#[test]
+fn test_visitor() {
+    let statements = parse(
+        "fun f(a) { if (a) { return g(a, b); } while (c) print -d; }
+        var x = e or f(1);",
+    );
+    let mut names = Names::default();
+    names.visit_block(&statements);
+
+    assert_eq!(names.used, ["a", "g", "a", "b", "c", "d", "e", "f"]);
+    assert_eq!(names.functions, 1);
+}
+
+struct Rename;
+
+impl VisitorMut for Rename {
+    fn visit_var_decl_mut(&mut self, name: &mut String, init: Option<&mut Expr>) {
+        name.insert(0, '_');
+        visit::walk_var_decl_mut(self, name, init);
+    }
+
+    fn visit_identifier_mut(&mut self, name: &mut String) {
+        name.insert(0, '_');
+    }
+
+    fn visit_assignment_mut(&mut self, name: &mut String, value: &mut Expr) {
+        name.insert(0, '_');
+        visit::walk_assignment_mut(self, name, value);
+    }
+}
+
+#[test]
+fn test_visitor_mut() {
+    let mut statements = parse("var a = 1; fun f() { a = a + 1; }");
+    let shared = match &statements[1].kind {
+        StatementKind::FunDecl(function) => function.clone(),
+        _ => unreachable!(),
+    };
+    Rename.visit_block_mut(&mut statements);
+
+    assert_eq!(statements, parse("var _a = 1; fun f() { _a = _a + 1; }"));
+    // the shared declaration is left alone
+    assert_eq!(shared.body, parse("a = a + 1;"));
+}
+
+struct ConstantFolding;
+
+impl Fold for ConstantFolding {
+    fn fold_expr(&mut self, expr: Expr) -> Expr {
+        use ExprKind::Literal;
+        use LiteralExpr::Number;
+
+        let expr = visit::fold_expr(self, expr);
+        let kind = match &expr.kind {
+            ExprKind::Grouping(inner) if matches!(inner.kind, Literal(_)) => inner.kind.clone(),
+            ExprKind::Binary(binary) => match (&binary.left.kind, binary.op, &binary.right.kind) {
+                (Literal(Number(left)), TokenType::Plus, Literal(Number(right))) => {
+                    Literal(Number(left + right))
+                }
+                (Literal(Number(left)), TokenType::Star, Literal(Number(right))) => {
+                    Literal(Number(left * right))
+                }
+                _ => return expr,
+            },
+            _ => return expr,
+        };
+        Expr { id: expr.id, kind }
+    }
+}
+
+#[test]
+fn test_fold() {
+    let statements = parse("print (1 + 2) * 3 + x; fun f() { return 2 * (3 + 4); }");
+    let id = statements[0].id;
+    let folded = ConstantFolding.fold_block(statements);
+
+    assert_eq!(folded, parse("print 9 + x; fun f() { return 14; }"));
+    assert_eq!(folded[0].id, id);
+}
+
+#[derive(Default)]
+struct Returns {
+    bare: usize,
+    values: Vec<Expr>,
+}
+
+impl<'ast> Visitor<'ast> for Returns {
+    fn visit_return(&mut self, value: Option<&'ast Expr>) {
+        match value {
+            Some(value) => self.values.push(value.clone()),
+            None => self.bare += 1,
+        }
+        visit::walk_return(self, value);
+    }
+}
+
+/// Makes every function without a value return `nil` explicitly.
+struct ExplicitNil;
+
+impl Fold for ExplicitNil {
+    fn fold_return(&mut self, value: Option<Expr>) -> Option<Expr> {
+        visit::fold_return(self, value).or_else(|| Some(ExprKind::Literal(LiteralExpr::Nil).into()))
+    }
+}
+
+#[test]
+fn test_return() {
+    let statements = parse("fun f(a) { if (a) return; return a + 1; } fun g() { return; }");
+    let mut returns = Returns::default();
+    returns.visit_block(&statements);
+
+    assert_eq!(returns.bare, 2);
+    assert_eq!(
+        returns.values,
+        [parser::parse_expr(&scanner::scan_tokens("a + 1").unwrap()).unwrap()]
+    );
+    assert_eq!(
+        ExplicitNil.fold_block(statements),
+        parse("fun f(a) { if (a) return nil; return a + 1; } fun g() { return nil; }")
+    );
+}
+
+#[derive(Default)]
+struct Ids {
+    kinds: NodeMap<&'static str>,
+}
+
+impl<'ast> Visitor<'ast> for Ids {
+    fn visit_stmt(&mut self, stmt: &'ast Statement) {
+        assert!(self.kinds.insert(stmt.id, "statement").is_none());
+        visit::walk_stmt(self, stmt);
+    }
+
+    fn visit_function(&mut self, function: &'ast std::sync::Arc<FunctionDecl>) {
+        assert!(self.kinds.insert(function.id, "function").is_none());
+        visit::walk_function(self, function);
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+        assert!(self.kinds.insert(expr.id, "expression").is_none());
+        visit::walk_expr(self, expr);
+    }
+}
+
+#[test]
+fn test_node_ids_are_unique() {
+    let mut ids = Ids::default();
+    ids.visit_block(&parse(
+        "fun f(a) { return a + 1; } for (var i = 0; i < 2; i = i + 1) f(i);",
+    ));
+    ids.visit_block(&parse("fun f(a) { return a + 1; }"));
+
+    let count = |kind| ids.kinds.values().filter(|&&other| other == kind).count();
+    assert_eq!(count("function"), 2);
+    assert_eq!(count("statement"), 10);
+    assert_eq!(count("expression"), 17);
+
+    // clones share the ids of the original
+    let statements = parse("print 1 + 2;");
+    let mut ids = Ids::default();
+    ids.visit_block(&statements);
+    let mut clone = Ids::default();
+    clone.visit_block(&statements.clone());
+    assert_eq!(clone.kinds, ids.kinds);
+}